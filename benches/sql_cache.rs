@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tailwag_orm::{
+    data_definition::table::{
+        DatabaseColumnType, DatabaseTableDefinition, Identifier, TableColumn,
+    },
+    queries::{
+        filterable_types::{FilterEq, FilterPartialEq, FilterableType},
+        CacheableSql, Query,
+    },
+    BuildSql,
+};
+
+fn get_table_def() -> Arc<DatabaseTableDefinition> {
+    let mut table = DatabaseTableDefinition::new("item")
+        .unwrap()
+        .column(TableColumn::uuid("id").unwrap().non_null().pk())
+        .column(TableColumn::string("name").unwrap())
+        .column(TableColumn::int("stock").unwrap())
+        .column(TableColumn::float("price").unwrap())
+        .column(TableColumn::timestamp("created_at").unwrap())
+        .column(
+            TableColumn::new(
+                "line_items",
+                DatabaseColumnType::OneToMany(Identifier::new_unchecked("line_item")),
+                Vec::new(),
+            )
+            .unwrap(),
+        );
+    for i in 0..10 {
+        table = table.column(TableColumn::string(&format!("attribute_{i}")).unwrap());
+    }
+    Arc::new(table)
+}
+
+fn query(table: &Arc<DatabaseTableDefinition>) -> Query<()> {
+    let name = FilterableType::<String>::new(Identifier::new_unchecked("item.name"));
+    let stock = FilterableType::<i64>::new(Identifier::new_unchecked("item.stock"));
+    Query::new(table.clone())
+        .filter(name.eq("widget") & stock.gt(10))
+        .order_by(name.asc())
+        .limit(50)
+}
+
+/// The hot path of `ExecutableQuery::execute`: producing the SQL and binds for a query.
+fn bench_render(c: &mut Criterion) {
+    let table = get_table_def();
+    let mut group = c.benchmark_group("render_query");
+    group.bench_function("query_builder", |b| {
+        b.iter(|| {
+            let mut builder = sqlx::QueryBuilder::new("");
+            black_box(query(&table)).build_sql(&mut builder);
+            black_box(builder.into_sql())
+        })
+    });
+    group.bench_function("cached_sql", |b| {
+        b.iter(|| black_box(black_box(query(&table)).cached_sql()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_render);
+criterion_main!(benches);
@@ -2,44 +2,65 @@ use syn::parse_macro_input;
 mod logic;
 mod util;
 
-#[proc_macro_derive(GetTableDefinition, attributes(db_ignore))]
+#[proc_macro_derive(GetTableDefinition, attributes(db_ignore, db_enum, primary_key, default, db_default, generated, check, index, unique, schema))]
 pub fn derive_get_table_definition(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input);
     let impl_trait_tokens = logic::derive::get_table_definition::derive_struct(&input);
     impl_trait_tokens.into()
 }
 
-#[proc_macro_derive(Deleteable, attributes(db_ignore))]
+#[proc_macro_derive(Deleteable, attributes(db_ignore, primary_key))]
 pub fn derive_deleteable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input);
     let impl_trait_tokens = logic::derive::deleteable::derive_struct(&input);
     impl_trait_tokens.into()
 }
 
-#[proc_macro_derive(Updateable, attributes(db_ignore, string))]
+#[proc_macro_derive(Updateable, attributes(db_ignore, db_enum, string, primary_key, default, db_default, generated))]
 pub fn derive_updateable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input);
     let impl_trait_tokens = logic::derive::updateable::derive_struct(&input);
     impl_trait_tokens.into()
 }
 
-#[proc_macro_derive(Insertable, attributes(create_type, db_ignore, string, create_ignore))]
+#[proc_macro_derive(Insertable, attributes(create_type, db_ignore, db_enum, string, create_ignore, primary_key, default, db_default, generated))]
 pub fn derive_insertable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input);
     let impl_trait_tokens = logic::derive::insertable::derive_struct(&input);
     impl_trait_tokens.into()
 }
 
-#[proc_macro_derive(Id)]
+#[proc_macro_derive(Id, attributes(primary_key))]
 pub fn derive_id(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input);
     let impl_trait_tokens = logic::derive::id::derive_struct(&input);
     impl_trait_tokens.into()
 }
 
-#[proc_macro_derive(Filterable, attributes(no_filter, db_ignore, string))]
+#[proc_macro_derive(Filterable, attributes(no_filter, db_ignore, db_enum, string))]
 pub fn derive_filterable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input);
     let impl_trait_tokens = logic::derive::filterable::derive_struct(&input);
     impl_trait_tokens.into()
 }
+
+#[proc_macro_derive(DecodeRow, attributes(db_ignore, db_enum, string, json))]
+pub fn derive_decode_row(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input);
+    let impl_trait_tokens = logic::derive::decode_row::derive_struct(&input);
+    impl_trait_tokens.into()
+}
+
+#[proc_macro_derive(DbEnum)]
+pub fn derive_db_enum(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input);
+    let impl_trait_tokens = logic::derive::db_enum::derive_enum(&input);
+    impl_trait_tokens.into()
+}
+
+#[proc_macro_derive(Projection, attributes(projection))]
+pub fn derive_projection(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input);
+    let impl_trait_tokens = logic::derive::projection::derive_struct(&input);
+    impl_trait_tokens.into()
+}
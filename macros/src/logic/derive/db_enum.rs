@@ -0,0 +1,52 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput};
+use tailwag_utils::strings::ToSnakeCase;
+
+pub fn derive_enum(input: &DeriveInput) -> TokenStream {
+    let &DeriveInput {
+        ident,
+        data,
+        ..
+    } = &input;
+
+    // Panic with error message if we get a non-enum
+    let Data::Enum(data) = data else {
+        panic!("Only enums are supported")
+    };
+    let variants = data
+        .variants
+        .iter()
+        .map(|variant| match variant.fields {
+            syn::Fields::Unit => &variant.ident,
+            _ => panic!("Only fieldless enums can be stored as a Postgres ENUM"),
+        })
+        .collect::<Vec<_>>();
+    // Stored by name, to match serde's default representation.
+    let variant_names = variants.iter().map(|variant| variant.to_string()).collect::<Vec<_>>();
+    let type_name = ident.to_string().to_snake_case();
+
+    quote!(
+        impl tailwag::orm::data_definition::table::DbEnum for #ident {
+            fn enum_type() -> tailwag::orm::data_definition::table::EnumType {
+                tailwag::orm::data_definition::table::EnumType::new(
+                    tailwag::orm::data_definition::table::Identifier::new(#type_name).expect("Invalid enum type name"),
+                    vec![#(#variant_names.to_string()),*],
+                )
+            }
+
+            fn as_variant(&self) -> &'static str {
+                match self {
+                    #(Self::#variants => #variant_names,)*
+                }
+            }
+
+            fn from_variant(variant: &str) -> Option<Self> {
+                match variant {
+                    #(#variant_names => Some(Self::#variants),)*
+                    _ => None,
+                }
+            }
+        }
+    )
+}
@@ -0,0 +1,72 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput};
+use tailwag_orm::data_definition::table::DatabaseColumnType;
+use tailwag_utils::macro_utils::attribute_parsing::GetAttribute;
+
+use crate::util::database_table_definition::{get_inner_type, get_type_from_field, is_option};
+
+pub fn derive_struct(input: &DeriveInput) -> TokenStream {
+    let &DeriveInput {
+        ident,
+        data,
+        ..
+    } = &input;
+
+    // Panic with error message if we get a non-struct
+    let Data::Struct(data) = data else {
+        panic!("Only Structs are supported")
+    };
+
+    match &data.fields {
+        syn::Fields::Named(fields) => {
+            let field_decoders = fields.named.iter().map(|field| {
+                let field_name = field.ident.as_ref().expect("Should only have named fields.");
+                let field_type = &field.ty;
+                // Relationships are selected under the field name, not the column name (e.g. `customer`, not `customer_id`).
+                let column_name = field_name.to_string();
+
+                if field.get_attribute("db_ignore").is_some() {
+                    return quote!(#field_name: Default::default(),);
+                }
+                if field.get_attribute("string").is_some() {
+                    return if is_option(field) {
+                        let inner_type = get_inner_type(field);
+                        quote!(#field_name: tailwag::orm::data_manager::decode::decode_parsed_column::<#inner_type>(row, #column_name)?,)
+                    } else {
+                        quote!(#field_name: tailwag::orm::data_manager::decode::decode_parsed_column::<#field_type>(row, #column_name)?
+                            .ok_or_else(|| tailwag::orm::Error::Deserialize {
+                                column: #column_name.to_string(),
+                                message: "unexpected NULL".to_string(),
+                            })?,)
+                    };
+                }
+                match get_type_from_field(field) {
+                    DatabaseColumnType::Json => quote!(
+                        #field_name: tailwag::orm::data_manager::decode::decode_json_column::<#field_type>(row, #column_name)?,
+                    ),
+                    DatabaseColumnType::OneToOne(_)
+                    | DatabaseColumnType::OneToMany(_)
+                    | DatabaseColumnType::ManyToMany(_) => quote!(
+                        #field_name: tailwag::orm::data_manager::decode::decode_relation_column::<#field_type>(row, #column_name)?,
+                    ),
+                    _ => quote!(
+                        #field_name: <#field_type as tailwag::orm::data_manager::decode::DecodeColumn>::decode_column(row, #column_name)?,
+                    ),
+                }
+            });
+
+            quote!(
+                impl tailwag::orm::data_manager::decode::DecodeRow for #ident {
+                    fn decode_row(row: &sqlx::postgres::PgRow) -> Result<Self, tailwag::orm::Error> {
+                        Ok(Self {
+                            #(#field_decoders)*
+                        })
+                    }
+                }
+            )
+        },
+        syn::Fields::Unnamed(_) => unimplemented!("Unnamed fields not supported yet"),
+        syn::Fields::Unit => unimplemented!("Unit fields not supported yet"),
+    }
+}
@@ -2,21 +2,23 @@ use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{Data, DeriveInput};
 
-fn build_get_delete_statement(_input: &DeriveInput) -> TokenStream {
+use crate::util::database_table_definition::get_primary_key_tokens;
+
+fn build_get_delete_statement(fields: &syn::FieldsNamed) -> TokenStream {
+    let (_, key_value) = get_primary_key_tokens(fields);
     let tokens = quote!(
         fn get_delete_statement(
             &self
         ) -> tailwag::orm::object_management::delete::DeleteStatement<Self> {
-            let id_column = tailwag::orm::data_definition::table::TableColumn::uuid("id").unwrap();
-
-            let delete = tailwag::orm::object_management::delete::DeleteStatement::<Self>::new(
-                <Self as tailwag::orm::data_manager::GetTableDefinition>::get_table_definition()
-                    .clone(),
-                tailwag::orm::queries::Filter::Equal(
-                    tailwag::orm::queries::FilterComparisonParam::TableColumn(id_column.into()),
-                    tailwag::orm::queries::FilterComparisonParam::Uuid(self.id.clone()),
-                ),
-            );
+            let table_def =
+                <Self as tailwag::orm::data_manager::GetTableDefinition>::get_table_definition();
+            // The key is built from the same fields as the table definition, so their shapes always match.
+            let filter = table_def
+                .primary_key_filter(&#key_value)
+                .expect("The primary key doesn't match the table definition - this should not happen.");
+
+            let delete =
+                tailwag::orm::object_management::delete::DeleteStatement::<Self>::new(table_def, filter);
 
             delete
         }
@@ -42,7 +44,7 @@ pub fn derive_struct(input: &DeriveInput) -> TokenStream {
             let _field_names = fields.named.iter().map(|f| &f.ident);
             let functions: Vec<TokenStream> = vec![
                 // todo!("Add functions here")
-                build_get_delete_statement(input),
+                build_get_delete_statement(fields),
             ];
 
             let parse_args_impl_tokens = quote!(
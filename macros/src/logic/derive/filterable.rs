@@ -1,8 +1,14 @@
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use syn::{Data, DeriveInput};
+use tailwag_orm::data_definition::table::{DatabaseColumnType, DatabaseTableDefinition, Identifier};
 use tailwag_utils::{macro_utils::attribute_parsing::GetAttribute, strings::ToSnakeCase};
 
+use crate::util::database_table_definition::{
+    get_column_name, get_inner_type, get_many_to_many_type, get_primary_key_fields, get_related_type,
+    get_type_from_field, is_option,
+};
+
 pub fn derive_struct(input: &DeriveInput) -> TokenStream {
     let &DeriveInput {
         ident,
@@ -28,13 +34,104 @@ pub fn derive_struct(input: &DeriveInput) -> TokenStream {
             let new_fields = filterable_fields.clone().map(|field| {
                 let field_ident = field.ident.clone().expect("Should only have named fields.");
                 let orig_type = field.ty.clone();
-                quote!(pub #field_ident: tailwag::orm::queries::filterable_types::FilterableType<#orig_type>)
+                match get_type_from_field(field) {
+                    DatabaseColumnType::OneToOne(_) => {
+                        let child_type = if is_option(field) {
+                            let inner_type = get_inner_type(field);
+                            quote!(#inner_type)
+                        } else {
+                            quote!(#orig_type)
+                        };
+                        quote!(pub #field_ident: tailwag::orm::queries::filterable_types::FilterableOneToOne<#child_type>)
+                    },
+                    DatabaseColumnType::OneToMany(_) => {
+                        let child_type = get_inner_type(field);
+                        quote!(pub #field_ident: tailwag::orm::queries::filterable_types::FilterableOneToMany<#child_type>)
+                    },
+                    DatabaseColumnType::ManyToMany(_) => {
+                        let child_type = get_many_to_many_type(field);
+                        quote!(pub #field_ident: tailwag::orm::queries::filterable_types::FilterableManyToMany<#child_type>)
+                    },
+                    // Only the key is stored, so that's all that can be filtered on.
+                    DatabaseColumnType::Related(_) => {
+                        let related_type = get_related_type(field);
+                        quote!(pub #field_ident: tailwag::orm::queries::filterable_types::FilterableType<<#related_type as tailwag::orm::data_manager::rest_api::Id>::Key>)
+                    },
+                    _ => {
+                        quote!(pub #field_ident: tailwag::orm::queries::filterable_types::FilterableType<#orig_type>)
+                    },
+                }
             });
-            let default_fields = filterable_fields.clone().map(|field| {
+            // Relationships join on this table's key. Tables without a single-column key fall back to `id`, and are
+            // rejected when the table definition is validated.
+            let key_column = match get_primary_key_fields(fields).as_slice() {
+                [key] => get_column_name(key),
+                _ => "id".to_string(),
+            };
+            let parent_key = format!("{table_name}.{key_column}");
+            let scoped_fields = filterable_fields.clone().map(|field| {
                 let field_ident = field.ident.clone().expect("Should only have named fields.");
-                let field_ident_str = format!("{table_name}.{field_ident}");
                 let orig_type = field.ty.clone();
-                quote!(#field_ident: tailwag::orm::queries::filterable_types::FilterableType::<#orig_type>::new(tailwag::orm::data_definition::table::Identifier::new_unchecked(#field_ident_str)))
+                match get_type_from_field(field) {
+                    DatabaseColumnType::OneToOne(child_table) => {
+                        let child_table = child_table.to_string();
+                        let child_type = if is_option(field) {
+                            let inner_type = get_inner_type(field);
+                            quote!(#inner_type)
+                        } else {
+                            quote!(#orig_type)
+                        };
+                        let foreign_key = format!("{table_name}.{field_ident}_id");
+                        quote!(#field_ident: tailwag::orm::queries::filterable_types::FilterableOneToOne::new(
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#child_table),
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(format!(
+                                "{}.{}",
+                                #child_table,
+                                <#child_type as tailwag::orm::queries::filterable_types::Filterable>::KEY_COLUMN,
+                            )),
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#foreign_key),
+                            scope.clone(),
+                        ))
+                    },
+                    DatabaseColumnType::OneToMany(child_table) => {
+                        let child_table = child_table.to_string();
+                        let parent_reference = format!("{child_table}.parent_id"); // TODO: Same `parent_id` assumption as the joins in `Query`
+                        quote!(#field_ident: tailwag::orm::queries::filterable_types::FilterableOneToMany::new(
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#child_table),
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#parent_reference),
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#parent_key),
+                            scope.clone(),
+                        ))
+                    },
+                    DatabaseColumnType::ManyToMany(child_table) => {
+                        let join_table = DatabaseTableDefinition::join_table_name(
+                            &Identifier::new_unchecked(table_name.to_string()),
+                            &Identifier::new_unchecked(field_ident.to_string()),
+                            &child_table,
+                        )
+                        .to_string();
+                        let parent_reference = format!("{join_table}.parent_id");
+                        let child_reference = format!("{join_table}.child_id");
+                        let child_table = child_table.to_string();
+                        quote!(#field_ident: tailwag::orm::queries::filterable_types::FilterableManyToMany::new(
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#child_table),
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#join_table),
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#parent_reference),
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#child_reference),
+                            tailwag::orm::data_definition::table::Identifier::new_unchecked(#parent_key),
+                            scope.clone(),
+                        ))
+                    },
+                    DatabaseColumnType::Related(_) => {
+                        let field_ident_str = format!("{table_name}.{field_ident}_id");
+                        let related_type = get_related_type(field);
+                        quote!(#field_ident: tailwag::orm::queries::filterable_types::FilterableType::<<#related_type as tailwag::orm::data_manager::rest_api::Id>::Key>::new(tailwag::orm::data_definition::table::Identifier::new_unchecked(#field_ident_str)).in_scope(scope.clone()))
+                    },
+                    _ => {
+                        let field_ident_str = format!("{table_name}.{field_ident}");
+                        quote!(#field_ident: tailwag::orm::queries::filterable_types::FilterableType::<#orig_type>::new(tailwag::orm::data_definition::table::Identifier::new_unchecked(#field_ident_str)).in_scope(scope.clone()))
+                    },
+                }
             });
 
             // OUTPUT STARTS HERE
@@ -42,16 +139,28 @@ pub fn derive_struct(input: &DeriveInput) -> TokenStream {
                 pub struct #filter_type_struct_ident {
                     #(#new_fields,)*
                 }
-                impl Default for #filter_type_struct_ident {
-                    fn default() -> Self {
+                impl #filter_type_struct_ident {
+                    #[allow(unused_variables)]
+                    fn build(scope: Option<std::sync::Arc<tailwag::orm::queries::filterable_types::RelationScope>>) -> Self {
                         Self {
-                            #(#default_fields,)*
+                            #(#scoped_fields,)*
                         }
                     }
                 }
+                impl Default for #filter_type_struct_ident {
+                    fn default() -> Self {
+                        Self::build(None)
+                    }
+                }
+                impl tailwag::orm::queries::filterable_types::RelationFilters for #filter_type_struct_ident {
+                    fn in_scope(scope: std::sync::Arc<tailwag::orm::queries::filterable_types::RelationScope>) -> Self {
+                        Self::build(Some(scope))
+                    }
+                }
                 impl tailwag::orm::queries::filterable_types::Filterable for #ident
                 {
                     type FilterType = #filter_type_struct_ident;
+                    const KEY_COLUMN: &'static str = #key_column;
                 }
             )
         },
@@ -1,9 +1,9 @@
 use proc_macro2::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::{Data, DeriveInput};
 use tailwag_utils::strings::ToScreamingSnakeCase;
 
-use crate::util::database_table_definition::get_child_table_tokens;
+use crate::util::database_table_definition::{get_child_table_tokens, get_column_name, get_join_table_tokens, get_inner_type, get_schema};
 
 pub fn derive_struct(input: &DeriveInput) -> TokenStream {
     let &DeriveInput {
@@ -58,17 +58,52 @@ fn build_get_table_definition(
     let input_table_definition =
         crate::util::database_table_definition::build_table_definition::<()>(input);
 
+    let Data::Struct(data) = &input.data else {
+        panic!("Only Structs are supported")
+    };
+
     // Build columns
     let table_columns = input_table_definition.columns.values().map(|column| {
         let column_name: &str = &column.column_name;
         let column_type = match &column.column_type {
             tailwag_orm::data_definition::table::DatabaseColumnType::Boolean=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Boolean),
             tailwag_orm::data_definition::table::DatabaseColumnType::Int=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Int),
+            tailwag_orm::data_definition::table::DatabaseColumnType::BigInt=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::BigInt),
             tailwag_orm::data_definition::table::DatabaseColumnType::Float=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Float),
             tailwag_orm::data_definition::table::DatabaseColumnType::String=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::String),
             tailwag_orm::data_definition::table::DatabaseColumnType::Timestamp=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Timestamp),
             tailwag_orm::data_definition::table::DatabaseColumnType::Uuid=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Uuid),
             tailwag_orm::data_definition::table::DatabaseColumnType::Json=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Json),
+            tailwag_orm::data_definition::table::DatabaseColumnType::Numeric=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Numeric),
+            tailwag_orm::data_definition::table::DatabaseColumnType::Date=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Date),
+            tailwag_orm::data_definition::table::DatabaseColumnType::Time=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Time),
+            tailwag_orm::data_definition::table::DatabaseColumnType::TimestampTz=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::TimestampTz),
+            tailwag_orm::data_definition::table::DatabaseColumnType::Bytea=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Bytea),
+            tailwag_orm::data_definition::table::DatabaseColumnType::Interval=>quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Interval),
+            // Arrays only hold scalar types, which are all unit variants.
+            tailwag_orm::data_definition::table::DatabaseColumnType::Array(element_type) => {
+                let element_type = format_ident!("{}", format!("{element_type:?}"));
+                quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Array(Box::new(tailwag::orm::data_definition::table::DatabaseColumnType::#element_type)))
+            },
+            // The variants live on the enum itself, so the type is read from its `DbEnum` impl.
+            tailwag_orm::data_definition::table::DatabaseColumnType::Enum(_) => {
+                let field = data
+                    .fields
+                    .iter()
+                    .find(|field| get_column_name(field) == column_name)
+                    .expect("No field found for enum column");
+                let enum_type = match column.is_nullable() {
+                    true => {
+                        let inner = get_inner_type(field);
+                        quote!(#inner)
+                    },
+                    false => {
+                        let ty = &field.ty;
+                        quote!(#ty)
+                    },
+                };
+                quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Enum(<#enum_type as tailwag::orm::data_definition::table::DbEnum>::enum_type()))
+            },
             tailwag_orm::data_definition::table::DatabaseColumnType::OneToMany(child) => {
                 let child = child.to_string();
                 quote!(tailwag::orm::data_definition::table::DatabaseColumnType::OneToMany(tailwag::orm::data_definition::table::Identifier::new(#child).unwrap()))
@@ -78,17 +113,41 @@ fn build_get_table_definition(
                 quote!(tailwag::orm::data_definition::table::DatabaseColumnType::ManyToMany(tailwag::orm::data_definition::table::Identifier::new(#child).unwrap()))
             }
             tailwag_orm::data_definition::table::DatabaseColumnType::OneToOne(child) => {
-                let child = format!("{child}_id");
+                let child = child.to_string();
                 quote!(tailwag::orm::data_definition::table::DatabaseColumnType::OneToOne(tailwag::orm::data_definition::table::Identifier::new(#child).unwrap()))
             }
+            tailwag_orm::data_definition::table::DatabaseColumnType::Related(child) => {
+                let child = child.to_string();
+                quote!(tailwag::orm::data_definition::table::DatabaseColumnType::Related(tailwag::orm::data_definition::table::Identifier::new(#child).unwrap()))
+            }
         };
         let constraints = column.constraints.iter().map(|constraint| {
-            match *constraint.detail {
+            match &*constraint.detail {
                 tailwag_orm::data_definition::table::TableColumnConstraintDetail::NotNull => quote!(.non_null()),
                 tailwag_orm::data_definition::table::TableColumnConstraintDetail::PrimaryKey(_) => quote!(.pk()),
-                tailwag_orm::data_definition::table::TableColumnConstraintDetail::References(_) => todo!(),
-                tailwag_orm::data_definition::table::TableColumnConstraintDetail::Unique(_) => todo!(),
+                tailwag_orm::data_definition::table::TableColumnConstraintDetail::References(fk) => {
+                    let ref_table = fk.ref_table.to_string();
+                    let ref_column = fk.ref_column.as_ref().map(|column| column.to_string());
+                    let ref_column = match ref_column {
+                        Some(column) => quote!(Some(tailwag::orm::data_definition::table::Identifier::new_unchecked(#column))),
+                        None => quote!(None),
+                    };
+                    let (match_type, on_delete_action, on_update_action) = build_referential_tokens(&fk.match_type, &fk.on_delete_action, &fk.on_update_action);
+                    quote!(.foreign_key(tailwag::orm::data_definition::table::ReferencesConstraint {
+                        ref_table: tailwag::orm::data_definition::table::Identifier::new_unchecked(#ref_table),
+                        ref_column: #ref_column,
+                        match_type: #match_type,
+                        on_delete_action: #on_delete_action,
+                        on_update_action: #on_update_action,
+                    }))
+                },
+                tailwag_orm::data_definition::table::TableColumnConstraintDetail::Unique(_) => quote!(.unique()),
                 tailwag_orm::data_definition::table::TableColumnConstraintDetail::Null => quote!(),
+                tailwag_orm::data_definition::table::TableColumnConstraintDetail::Default(expression) => quote!(.default(#expression)),
+                tailwag_orm::data_definition::table::TableColumnConstraintDetail::Generated(expression) => quote!(.generated(#expression)),
+                tailwag_orm::data_definition::table::TableColumnConstraintDetail::Identity => quote!(.identity()),
+                // Checks are closures, so they're added from the `#[check]` attributes directly. See `build_check_constraints()`.
+                tailwag_orm::data_definition::table::TableColumnConstraintDetail::Check(_) => quote!(),
             }
         });
 
@@ -98,8 +157,62 @@ fn build_get_table_definition(
         )
     });
 
+    // Build table constraints
+    let columns_of = |columns: &[tailwag_orm::data_definition::table::TableColumn]| {
+        let column_names = columns.iter().map(|column| column.column_name.to_string());
+        quote!(vec![
+            #(def.columns[&tailwag::orm::data_definition::table::Identifier::new_unchecked(#column_names)].clone()),*
+        ])
+    };
+    let table_constraints = input_table_definition.constraints.iter().filter_map(|constraint| {
+        let name = constraint.name.as_ref().map(|name| name.to_string());
+        let name_option = match &name {
+            Some(name) => quote!(Some(tailwag::orm::data_definition::table::Identifier::new_unchecked(#name))),
+            None => quote!(None),
+        };
+        let name = quote!(#name_option.expect("Constraint is unnamed"));
+        let tokens = match &*constraint.detail {
+            tailwag_orm::data_definition::table::TableConstraintDetail::PrimaryKey(pk) => {
+                let columns = columns_of(&pk.columns);
+                quote!(tailwag::orm::data_definition::table::TableConstraint::primary_key(#columns))
+            },
+            tailwag_orm::data_definition::table::TableConstraintDetail::Unique(unique) => {
+                let columns = columns_of(&unique.columns);
+                quote!(tailwag::orm::data_definition::table::TableConstraint::unique(#name, #columns))
+            },
+            tailwag_orm::data_definition::table::TableConstraintDetail::ForeignKey(fk) => {
+                let columns = columns_of(&fk.columns);
+                let ref_table = fk.ref_table.to_string();
+                let ref_columns = fk.ref_columns.iter().map(|column| column.to_string());
+                let (match_type, on_delete_action, on_update_action) = build_referential_tokens(&fk.match_type, &fk.on_delete_action, &fk.on_update_action);
+                quote!(tailwag::orm::data_definition::table::TableConstraint {
+                    name: #name_option,
+                    detail: std::sync::Arc::new(tailwag::orm::data_definition::table::TableConstraintDetail::ForeignKey(
+                        tailwag::orm::data_definition::table::ForeignKeyConstraint {
+                            ref_table: tailwag::orm::data_definition::table::Identifier::new_unchecked(#ref_table),
+                            ref_columns: vec![#(tailwag::orm::data_definition::table::Identifier::new_unchecked(#ref_columns)),*],
+                            columns: #columns,
+                            match_type: #match_type,
+                            on_delete_action: #on_delete_action,
+                            on_update_action: #on_update_action,
+                        },
+                    )),
+                })
+            },
+            // Checks are closures, so they're added from the `#[check]` attributes directly. See `build_check_constraints()`.
+            tailwag_orm::data_definition::table::TableConstraintDetail::Check(_) => return None,
+        };
+        Some(tokens)
+    });
+
     let table_name = input_table_definition.table_name.to_string();
+    let (index_tokens, filtered_index_tokens) = build_indexes(input);
+    let filter_tokens = with_filters(
+        build_check_constraints(input, &table_name).into_iter().chain(filtered_index_tokens).collect(),
+    );
     let child_tables = get_child_table_tokens(input);
+    let join_tables = get_join_table_tokens(input);
+    let schema_tokens = get_schema(input).map(|schema| quote!(.schema(#schema).expect("Schema name is invalid")));
 
     // !! START OF QUOTE
     let tokens = quote!(
@@ -107,10 +220,15 @@ fn build_get_table_definition(
             let table_def:  &tailwag::orm::data_definition::table::DatabaseTableDefinition = #once_cell_name.get_or_init(|| {
                 let mut def = tailwag::orm::data_definition::table::DatabaseTableDefinition::new(&#table_name)
                     .expect("Table name is invalid")
+                    #schema_tokens
                     #(.column(#table_columns))*
-                    // #(.constraint(#table_constraints)*) // TODO - weak constriants support currently
                     ;
+                #(def.constraints.push(#table_constraints);)*
+                #(#index_tokens)*
+                #filter_tokens
                 def.child_tables = #child_tables;
+                #join_tables
+                def.resolve_relation_keys();
                 def
             });
 
@@ -121,3 +239,189 @@ fn build_get_table_definition(
 
     tokens
 }
+
+/// Builds the tokens for a foreign key's `MATCH` type and `ON DELETE` / `ON UPDATE` actions.
+fn build_referential_tokens(
+    match_type: &Option<tailwag_orm::data_definition::table::ReferencesConstraintMatchType>,
+    on_delete_action: &Option<tailwag_orm::data_definition::table::ReferentialAction>,
+    on_update_action: &Option<tailwag_orm::data_definition::table::ReferentialAction>,
+) -> (TokenStream, TokenStream, TokenStream) {
+    use tailwag_orm::data_definition::table::{ReferencesConstraintMatchType, ReferentialAction};
+
+    let match_type = match match_type {
+        Some(ReferencesConstraintMatchType::Full) => quote!(Some(tailwag::orm::data_definition::table::ReferencesConstraintMatchType::Full)),
+        Some(ReferencesConstraintMatchType::Partial) => quote!(Some(tailwag::orm::data_definition::table::ReferencesConstraintMatchType::Partial)),
+        Some(ReferencesConstraintMatchType::Simple) => quote!(Some(tailwag::orm::data_definition::table::ReferencesConstraintMatchType::Simple)),
+        None => quote!(None),
+    };
+    let action = |action: &Option<ReferentialAction>| {
+        let identifiers = |columns: &[tailwag_orm::data_definition::table::Identifier]| {
+            let columns = columns.iter().map(|column| column.to_string());
+            quote!(vec![#(tailwag::orm::data_definition::table::Identifier::new_unchecked(#columns)),*])
+        };
+        match action {
+            Some(ReferentialAction::NoAction) => quote!(Some(tailwag::orm::data_definition::table::ReferentialAction::NoAction)),
+            Some(ReferentialAction::Restrict) => quote!(Some(tailwag::orm::data_definition::table::ReferentialAction::Restrict)),
+            Some(ReferentialAction::Cascade) => quote!(Some(tailwag::orm::data_definition::table::ReferentialAction::Cascade)),
+            Some(ReferentialAction::SetNull(columns)) => {
+                let columns = identifiers(columns);
+                quote!(Some(tailwag::orm::data_definition::table::ReferentialAction::SetNull(#columns)))
+            },
+            Some(ReferentialAction::SetDefault(columns)) => {
+                let columns = identifiers(columns);
+                quote!(Some(tailwag::orm::data_definition::table::ReferentialAction::SetDefault(#columns)))
+            },
+            None => quote!(None),
+        }
+    };
+    (match_type, action(on_delete_action), action(on_update_action))
+}
+
+/// Builds the `#[check(|f| ...)]` constraints. The closures are given the type's `Filterable::FilterType`, so
+/// checks on a field become column constraints, and checks on the struct become table constraints.
+fn build_check_constraints(
+    input: &DeriveInput,
+    table_name: &str,
+) -> Vec<TokenStream> {
+    let checks_for = |attrs: &[syn::Attribute]| {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("check"))
+            .map(|attr| {
+                attr.parse_args::<syn::Expr>()
+                    .expect("Expected a closure, e.g. #[check(|f| f.price.gte(0))]")
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let Data::Struct(data) = &input.data else {
+        panic!("Only Structs are supported")
+    };
+    let column_checks = data.fields.iter().filter_map(|field| {
+        let checks = checks_for(&field.attrs);
+        if checks.is_empty() {
+            return None;
+        }
+        let column_name = get_column_name(field);
+        Some(quote!(
+            let column = def.columns[&tailwag::orm::data_definition::table::Identifier::new_unchecked(#column_name)].clone();
+            // Multiple checks are combined, so that the column has a single (predictably named) constraint.
+            def.add_column((*column).clone().check(&(#(apply_filter(&filters, #checks))&*)));
+        ))
+    });
+    let table_checks = checks_for(&input.attrs).into_iter().enumerate().map(|(i, check)| {
+        let name = format!("{table_name}_check_{i}");
+        quote!(
+            def.constraints.push(tailwag::orm::data_definition::table::TableConstraint::check(
+                tailwag::orm::data_definition::table::Identifier::new_unchecked(#name),
+                &apply_filter(&filters, #check),
+            ));
+        )
+    });
+    column_checks.chain(table_checks).collect()
+}
+
+/// Builds the `#[index]` attributes, on fields or on the struct (with `columns(..)`). Returns the indexes
+/// with a `filter = |f| ...` (a partial index) separately, as those need to be built `with_filters()`.
+fn build_indexes(input: &DeriveInput) -> (Vec<TokenStream>, Vec<TokenStream>) {
+    let Data::Struct(data) = &input.data else {
+        panic!("Only Structs are supported")
+    };
+    let column_name_of = |field_name: &syn::Ident| {
+        let field = data
+            .fields
+            .iter()
+            .find(|field| field.ident.as_ref() == Some(field_name))
+            .unwrap_or_else(|| panic!("#[index] column `{field_name}` is not a field"));
+        get_column_name(field)
+    };
+
+    let field_indexes = data.fields.iter().flat_map(|field| {
+        field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("index"))
+            .map(|attr| (attr, vec![get_column_name(field)]))
+    });
+    let struct_indexes = input.attrs.iter().filter(|attr| attr.path().is_ident("index")).map(|attr| (attr, Vec::new()));
+
+    let mut indexes = Vec::new();
+    let mut filtered_indexes = Vec::new();
+    for (attr, mut columns) in field_indexes.chain(struct_indexes) {
+        let mut modifiers = Vec::new();
+        let mut filter = None;
+        // A bare `#[index]` has no arguments to parse.
+        if let syn::Meta::List(_) = attr.meta {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("columns") {
+                    meta.parse_nested_meta(|column| {
+                        let field_name = column.path.require_ident()?;
+                        columns.push(column_name_of(field_name));
+                        Ok(())
+                    })
+                } else if meta.path.is_ident("unique") {
+                    modifiers.push(quote!(.unique()));
+                    Ok(())
+                } else if meta.path.is_ident("name") {
+                    let name = meta.value()?.parse::<syn::LitStr>()?.value();
+                    modifiers.push(quote!(.named(tailwag::orm::data_definition::table::Identifier::new(#name).expect("Invalid index name"))));
+                    Ok(())
+                } else if meta.path.is_ident("method") {
+                    let method = meta.value()?.parse::<syn::Ident>()?;
+                    let method = tailwag_orm::data_definition::table::IndexMethod::try_from(method.to_string().as_str())
+                        .map_err(|e| meta.error(e))?;
+                    let method: TokenStream = format!("{method:?}").parse().unwrap();
+                    modifiers.push(quote!(.using(tailwag::orm::data_definition::table::IndexMethod::#method)));
+                    Ok(())
+                } else if meta.path.is_ident("filter") {
+                    filter = Some(meta.value()?.parse::<syn::Expr>()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("Expected one of: columns(..), unique, name = \"..\", method = .., filter = |f| .."))
+                }
+            })
+            .expect("Invalid #[index] attribute");
+        }
+        if columns.is_empty() {
+            panic!("Expected the indexed columns, e.g. #[index(columns(a, b))]");
+        }
+
+        let index = quote!(
+            tailwag::orm::data_definition::table::TableIndex::new(
+                &def.table_name,
+                vec![#(tailwag::orm::data_definition::table::Identifier::new_unchecked(#columns)),*],
+            )
+            #(#modifiers)*
+        );
+        match filter {
+            Some(filter) => filtered_indexes.push(quote!(
+                def.indexes.push(#index.filter(&apply_filter(&filters, #filter)));
+            )),
+            None => indexes.push(quote!(
+                def.indexes.push(#index);
+            )),
+        }
+    }
+
+    (indexes, filtered_indexes)
+}
+
+/// Builds statements using filter closures (`|f| ...`). The closures are given the type's `Filterable::FilterType`.
+fn with_filters(statements: Vec<TokenStream>) -> TokenStream {
+    if statements.is_empty() {
+        return quote!();
+    }
+
+    quote!({
+        #[allow(unused_imports)]
+        use tailwag::orm::queries::filterable_types::{FilterEq, FilterIn, FilterLike, FilterPartialEq};
+        fn apply_filter<F>(
+            filters: &F,
+            filter: impl FnOnce(&F) -> tailwag::orm::queries::Filter,
+        ) -> tailwag::orm::queries::Filter {
+            filter(filters)
+        }
+        let filters = <<Self as tailwag::orm::queries::filterable_types::Filterable>::FilterType as Default>::default();
+        #(#statements)*
+    })
+}
@@ -2,6 +2,8 @@ use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{Data, DeriveInput};
 
+use crate::util::database_table_definition::get_primary_key_tokens;
+
 pub fn derive_struct(input: &DeriveInput) -> TokenStream {
     let &DeriveInput {
         ident,
@@ -15,12 +17,15 @@ pub fn derive_struct(input: &DeriveInput) -> TokenStream {
     };
 
     match &data.fields {
-        syn::Fields::Named(_) => {
+        syn::Fields::Named(fields) => {
+            let (key_type, key_value) = get_primary_key_tokens(fields);
             quote!(
                 impl tailwag::orm::data_manager::rest_api::Id for #ident
                 {
-                    fn id(&self) -> &uuid::Uuid {
-                        &self.id
+                    type Key = #key_type;
+
+                    fn id(&self) -> Self::Key {
+                        #key_value
                     }
                 }
             )
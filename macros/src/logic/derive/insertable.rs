@@ -3,7 +3,7 @@ use quote::{format_ident, quote, ToTokens};
 use syn::{Data, DeriveInput, Ident};
 use tailwag_utils::macro_utils::attribute_parsing::GetAttribute;
 
-use crate::util::database_table_definition::{get_inner_type, get_type_from_field};
+use crate::util::database_table_definition::{get_inner_type, get_related_type, get_type_from_field, is_identity_key, is_option};
 
 /// TODO [TECH DEBT] - The decision to make the SQL queries generated by macros was overkill. When I did this, I had recently started
 /// learning to write derive macros, and found this a good chance to practice.
@@ -65,9 +65,12 @@ fn build_create_request(input: &DeriveInput) -> (Ident, TokenStream) {
     let type_ident = &input.ident;
     let request_ident = format_ident!("{}CreateRequest", input.ident);
     let syn::Fields::Named(fields) = &data.fields else { panic!("Struct contains unnamed fields.")};
+    // The default `id` key is generated on create. Keys chosen with #[primary_key] are passed through instead.
+    let generate_id = fields.named.iter().all(|field| field.get_attribute("primary_key").is_none());
     let passthrough_fields = fields.named.iter()
-        .filter(|field| field.get_attribute("db_ignore").is_none() && field.get_attribute("create_ignore").is_none())
-        .filter(|field|"id" != &field.ident.as_ref().expect("Must have ident on named field").to_string());
+        .filter(|field| field.get_attribute("db_ignore").is_none() && field.get_attribute("create_ignore").is_none() && field.get_attribute("generated").is_none() && !is_identity_key(field))
+        .filter(move |field| !generate_id || "id" != &field.ident.as_ref().expect("Must have ident on named field").to_string());
+    let generated_id = generate_id.then(|| quote!(id: uuid::Uuid::new_v4(),));
 
     let field_tokens = passthrough_fields.clone().map(
         |field| {
@@ -80,6 +83,16 @@ fn build_create_request(input: &DeriveInput) -> (Ident, TokenStream) {
                     let field_type = get_inner_type(&field);
                     quote!(pub #field_name: Vec<<#field_type as tailwag::orm::queries::Insertable>::CreateRequest>,)
                 },
+                // Created by key - the related object must already exist.
+                tailwag_orm::data_definition::table::DatabaseColumnType::Related(_) => {
+                    let related_type = get_related_type(field);
+                    let key_type = quote!(<#related_type as tailwag::orm::data_manager::rest_api::Id>::Key);
+                    if is_option(field) {
+                        quote!(pub #field_name: Option<#key_type>,)
+                    } else {
+                        quote!(pub #field_name: #key_type,)
+                    }
+                },
                 _ => quote!(pub #field_name: #field_type,)
             }
             // If field is primitive, pass it through.
@@ -89,9 +102,16 @@ fn build_create_request(input: &DeriveInput) -> (Ident, TokenStream) {
         |field|match get_type_from_field(field) {
             tailwag_orm::data_definition::table::DatabaseColumnType::OneToMany(_) |
             tailwag_orm::data_definition::table::DatabaseColumnType::ManyToMany(_) =>false,
+            tailwag_orm::data_definition::table::DatabaseColumnType::Related(_) => false,
             _ => true,
         }
     ).map(|field| &field.ident);
+    let related_names = passthrough_fields.clone().filter(
+        |field| matches!(get_type_from_field(field), tailwag_orm::data_definition::table::DatabaseColumnType::Related(_)) && !is_option(field)
+    ).map(|field| &field.ident);
+    let optional_related_names = passthrough_fields.clone().filter(
+        |field| matches!(get_type_from_field(field), tailwag_orm::data_definition::table::DatabaseColumnType::Related(_)) && is_option(field)
+    ).map(|field| &field.ident);
     let vec_fields = passthrough_fields.clone().filter(
         |field|match get_type_from_field(field) {
             tailwag_orm::data_definition::table::DatabaseColumnType::OneToMany(_) |
@@ -101,8 +121,8 @@ fn build_create_request(input: &DeriveInput) -> (Ident, TokenStream) {
     );
     let vec_field_names = vec_fields .map(|field| &field.ident);
 
-    // Need to default to any db_ignored fields
-    let ignored_fields = fields.named.iter().filter(|field| field.get_attribute("db_ignore").is_some() || field.get_attribute("create_ignore").is_some()).map(|field|&field.ident);
+    // Need to default to any db_ignored fields. Generated fields and identity keys are filled in by the database.
+    let ignored_fields = fields.named.iter().filter(|field| field.get_attribute("db_ignore").is_some() || field.get_attribute("create_ignore").is_some() || field.get_attribute("generated").is_some() || is_identity_key(field)).map(|field|&field.ident);
 
     (request_ident.clone(), quote!(
         #[derive(Default, serde::Deserialize, serde::Serialize)]
@@ -112,8 +132,10 @@ fn build_create_request(input: &DeriveInput) -> (Ident, TokenStream) {
         impl From<#request_ident> for #type_ident {
             fn from(val: #request_ident) -> Self {
                 #type_ident {
-                    id: uuid::Uuid::new_v4(),
+                    #generated_id
                     #(#field_names: val.#field_names.into(),)*
+                    #(#related_names: tailwag::orm::data_manager::Related::new(val.#related_names),)*
+                    #(#optional_related_names: val.#optional_related_names.map(tailwag::orm::data_manager::Related::new),)*
                     #(#vec_field_names: val.#vec_field_names.into_iter().map(<_ as From<_>>::from).collect(),)*
                     #(#ignored_fields: Default::default(),)*
                 }
@@ -126,7 +148,8 @@ fn build_get_insert_statement(input: &DeriveInput) -> TokenStream {
     let input_table_definition =
         crate::util::database_table_definition::build_table_definition::<()>(input);
 
-    let insert_maps = input_table_definition.columns.values().map(|column| {
+    // Generated columns are computed by the database, and can't be written to. Identity keys are numbered by it.
+    let insert_maps = input_table_definition.columns.values().filter(|column| !column.is_generated() && !column.is_identity()).map(|column| {
         let column_name = format_ident!("{}", column.column_name.to_string());
         let mut field_name = column_name.clone();
         let column_name_as_string = column.column_name.to_string();
@@ -135,19 +158,30 @@ fn build_get_insert_statement(input: &DeriveInput) -> TokenStream {
 
         let wrapped_type = match &column.column_type {
             E::Boolean => quote!(tailwag::orm::data_definition::table::ColumnValue::Boolean(#column_name.clone())),
-            E::Int => quote!(tailwag::orm::data_definition::table::ColumnValue::Int(#column_name.clone())),
+            E::Int | E::BigInt => quote!(tailwag::orm::data_definition::table::ColumnValue::Int(#column_name.clone())),
             E::Float => quote!(tailwag::orm::data_definition::table::ColumnValue::Float(#column_name.clone())),
             E::String => quote!(tailwag::orm::data_definition::table::ColumnValue::String(#column_name.to_string())),
             E::Timestamp => quote!(tailwag::orm::data_definition::table::ColumnValue::Timestamp(#column_name.clone())),
             E::Uuid => quote!(tailwag::orm::data_definition::table::ColumnValue::Uuid(#column_name.clone())),
+            E::Related(_) => {
+                field_name = format_ident!("{}", column.column_name.trim_end_matches("_id").to_string());
+                quote!(tailwag::orm::data_manager::RelationKey::column_value(#field_name.id()))
+            },
             E::Json => quote!(tailwag::orm::data_definition::table::ColumnValue::Json(#column_name.to_string())),
+            E::Enum(_) => quote!(tailwag::orm::data_definition::table::ColumnValue::from_enum(#column_name)),
+            E::Numeric => quote!(tailwag::orm::data_definition::table::ColumnValue::Numeric(#column_name.clone())),
+            E::Date => quote!(tailwag::orm::data_definition::table::ColumnValue::Date(#column_name.clone())),
+            E::Time => quote!(tailwag::orm::data_definition::table::ColumnValue::Time(#column_name.clone())),
+            E::TimestampTz => quote!(tailwag::orm::data_definition::table::ColumnValue::TimestampTz(#column_name.clone())),
+            E::Bytea => quote!(tailwag::orm::data_definition::table::ColumnValue::Bytea(#column_name.clone())),
+            E::Interval => quote!(tailwag::orm::data_definition::table::ColumnValue::Interval(#column_name.clone())),
+            E::Array(_) => quote!(tailwag::orm::data_definition::table::ColumnValue::Array(#column_name.clone().into())),
             E::OneToOne(_child_type) => {
                 field_name = format_ident!("{}", column.column_name.trim_end_matches("_id").to_string()); // Hack to work around soem ugliness with the DataDefinition / column mapping
-                // TODO: This assumes ID.
                 quote!(
                     {
                         let stmt = #field_name.get_insert_statement();
-                        tailwag::orm::data_definition::table::ColumnValue::OneToOne{child_table: stmt.table_name(), value: Box::new(stmt.object_repr().clone())}
+                        tailwag::orm::data_definition::table::ColumnValue::OneToOne{child_table: stmt.table_name(), schema: stmt.schema(), primary_key: stmt.primary_key().to_vec(), value: Box::new(stmt.object_repr().clone())}
                     }
                 )
                 // todo!()
@@ -159,12 +193,31 @@ fn build_get_insert_statement(input: &DeriveInput) -> TokenStream {
                         let child_table = insert_statements.clone().find_map(|stmt|Some(stmt.table_name())).unwrap_or(
                             tailwag::orm::data_definition::table::Identifier::new_unchecked("__nothin_to_insert__")
                         );
+                        let schema = insert_statements.clone().find_map(|stmt|stmt.schema());
+                        let primary_key = insert_statements.clone().next().map(|stmt|stmt.primary_key().to_vec()).unwrap_or_default();
                         let values = insert_statements.into_iter().map(|stmt|Box::new(stmt.object_repr().clone())).collect();
-                        tailwag::orm::data_definition::table::ColumnValue::OneToMany{child_table, values}
+                        tailwag::orm::data_definition::table::ColumnValue::OneToMany{child_table, schema, primary_key, values}
+                    }
+                )
+            },
+            // The child table is known up front, so that links can still be cleared when the list is empty.
+            E::ManyToMany(child_table) => {
+                let child_table = child_table.to_string();
+                quote!(
+                    {
+                        let statements = #field_name.iter().map(|child|child.get_insert_statement()).collect::<Vec<_>>();
+                        let schema = statements.iter().find_map(|stmt|stmt.schema());
+                        let primary_key = statements.first().map(|stmt|stmt.primary_key().to_vec()).unwrap_or_default();
+                        let values = statements.iter().map(|stmt|Box::new(stmt.object_repr().clone())).collect();
+                        tailwag::orm::data_definition::table::ColumnValue::ManyToMany{
+                            child_table: tailwag::orm::data_definition::table::Identifier::new_unchecked(#child_table),
+                            schema,
+                            primary_key,
+                            values,
+                        }
                     }
                 )
             },
-            E::ManyToMany(_) => todo!("{:?} is a ManyToMany relationship that isn't yet supported", &column.column_type),
         };
 
         
@@ -201,14 +254,35 @@ fn build_get_insert_statement(input: &DeriveInput) -> TokenStream {
 
             #(#insert_maps)*
 
-            let insert = tailwag::orm::object_management::insert::InsertStatement::new(
-                <Self as tailwag::orm::data_manager::GetTableDefinition>::get_table_definition().table_name.clone(),
+            let table_def = <Self as tailwag::orm::data_manager::GetTableDefinition>::get_table_definition();
+            let mut insert = tailwag::orm::object_management::insert::InsertStatement::new(
+                table_def.table_name.clone(),
                 insert_map,
-            );
+            ).with_schema(table_def.schema.clone());
+            let primary_key = table_def.primary_key_columns();
+            if !primary_key.is_empty() {
+                insert = insert.with_primary_key(primary_key.into_iter().map(|column| column.column_name.clone()).collect());
+            }
 
             insert
         }
     );
 
-    tokens
+    // Identity keys are only known once inserted, so they're read back from the inserted row.
+    let identity_keys = input_table_definition.columns.values().filter(|column| column.is_identity()).map(|column| {
+        let column_name = column.column_name.to_string();
+        let field_name = format_ident!("{}", column_name);
+        quote!(self.#field_name = tailwag::orm::data_manager::decode::DecodeColumn::decode_column(row, #column_name)?;)
+    }).collect::<Vec<_>>();
+    if identity_keys.is_empty() {
+        return tokens;
+    }
+    quote!(
+        #tokens
+
+        fn set_generated_keys(&mut self, row: &sqlx::postgres::PgRow) -> Result<(), tailwag::orm::Error> {
+            #(#identity_keys)*
+            Ok(())
+        }
+    )
 }
@@ -1,7 +1,10 @@
 // pub mod _builder;
+pub mod db_enum;
+pub mod decode_row;
 pub mod deleteable;
 pub mod filterable;
 pub mod get_table_definition;
 pub mod id;
 pub mod insertable;
+pub mod projection;
 pub mod updateable;
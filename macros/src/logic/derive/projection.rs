@@ -0,0 +1,51 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput};
+use tailwag_utils::macro_utils::attribute_parsing::GetAttribute;
+
+pub fn derive_struct(input: &DeriveInput) -> TokenStream {
+    let &DeriveInput {
+        ident,
+        data,
+        ..
+    } = &input;
+
+    // Panic with error message if we get a non-struct
+    let Data::Struct(data) = data else {
+        panic!("Only Structs are supported")
+    };
+    let source_type = input
+        .get_attribute("projection")
+        .map(|attr| attr.parse_args::<syn::Path>().expect("Expected #[projection(SourceType)]"))
+        .expect("Projections require a #[projection(SourceType)] attribute");
+
+    match &data.fields {
+        syn::Fields::Named(fields) => {
+            let field_names = fields
+                .named
+                .iter()
+                .map(|f| f.ident.as_ref().expect("Should only have named fields."));
+            let field_names_str = field_names.clone().map(|f| f.to_string());
+
+            quote!(
+                impl tailwag::orm::queries::Projection for #ident {
+                    type Source = #source_type;
+
+                    fn fields() -> Vec<tailwag::orm::data_definition::table::Identifier> {
+                        vec![#(tailwag::orm::data_definition::table::Identifier::new_unchecked(#field_names_str),)*]
+                    }
+                }
+
+                // Fails to compile if the projection names a field that doesn't exist on the source type.
+                const _: () = {
+                    #[allow(unused)]
+                    fn assert_fields_exist(source: &#source_type) {
+                        #(let _ = &source.#field_names;)*
+                    }
+                };
+            )
+        },
+        syn::Fields::Unnamed(_) => unimplemented!("Unnamed fields not supported yet"),
+        syn::Fields::Unit => unimplemented!("Unit fields not supported yet"),
+    }
+}
@@ -10,7 +10,8 @@ fn build_get_update_statement(input: &DeriveInput) -> TokenStream {
     let input_table_definition =
         crate::util::database_table_definition::build_table_definition::<()>(input);
 
-    let update_maps = input_table_definition.columns.values().map(|column| {
+    // Generated columns are computed by the database, and can't be written to.
+    let update_maps = input_table_definition.columns.values().filter(|column| !column.is_generated()).map(|column| {
         let column_name = format_ident!("{}", column.column_name.to_string());
         let mut field_name = column_name.clone();
         let column_name_as_string = column.column_name.to_string();
@@ -18,19 +19,30 @@ fn build_get_update_statement(input: &DeriveInput) -> TokenStream {
         type E = tailwag_orm::data_definition::table::DatabaseColumnType;
         let wrapped_type = match &column.column_type {
             E::Boolean => quote!(tailwag::orm::data_definition::table::ColumnValue::Boolean(#column_name.clone())),
-            E::Int => quote!(tailwag::orm::data_definition::table::ColumnValue::Int(#column_name.clone())),
+            E::Int | E::BigInt => quote!(tailwag::orm::data_definition::table::ColumnValue::Int(#column_name.clone())),
             E::Float => quote!(tailwag::orm::data_definition::table::ColumnValue::Float(#column_name.clone())),
             E::String => quote!(tailwag::orm::data_definition::table::ColumnValue::String(#column_name.to_string())),
             E::Timestamp => quote!(tailwag::orm::data_definition::table::ColumnValue::Timestamp(#column_name.clone())),
             E::Uuid => quote!(tailwag::orm::data_definition::table::ColumnValue::Uuid(#column_name.clone())),
+            E::Related(_) => {
+                field_name = format_ident!("{}", column.column_name.trim_end_matches("_id").to_string());
+                quote!(tailwag::orm::data_manager::RelationKey::column_value(#field_name.id()))
+            },
             E::Json => quote!(tailwag::orm::data_definition::table::ColumnValue::Json(#column_name.to_string())),
+            E::Enum(_) => quote!(tailwag::orm::data_definition::table::ColumnValue::from_enum(#column_name)),
+            E::Numeric => quote!(tailwag::orm::data_definition::table::ColumnValue::Numeric(#column_name.clone())),
+            E::Date => quote!(tailwag::orm::data_definition::table::ColumnValue::Date(#column_name.clone())),
+            E::Time => quote!(tailwag::orm::data_definition::table::ColumnValue::Time(#column_name.clone())),
+            E::TimestampTz => quote!(tailwag::orm::data_definition::table::ColumnValue::TimestampTz(#column_name.clone())),
+            E::Bytea => quote!(tailwag::orm::data_definition::table::ColumnValue::Bytea(#column_name.clone())),
+            E::Interval => quote!(tailwag::orm::data_definition::table::ColumnValue::Interval(#column_name.clone())),
+            E::Array(_) => quote!(tailwag::orm::data_definition::table::ColumnValue::Array(#column_name.clone().into())),
             E::OneToOne(_child_type) => {
                 field_name = format_ident!("{}", column.column_name.trim_end_matches("_id").to_string()); // Hack to work around soem ugliness with the DataDefinition / column mapping
-                // TODO: This assumes ID.
                 quote!(
                     {
                         let stmt = #field_name.get_update_statement();
-                        tailwag::orm::data_definition::table::ColumnValue::OneToOne{child_table: stmt.table_name(), value: Box::new(stmt.object_repr().clone())}
+                        tailwag::orm::data_definition::table::ColumnValue::OneToOne{child_table: stmt.table_name(), schema: stmt.schema(), primary_key: stmt.primary_key().to_vec(), value: Box::new(stmt.object_repr().clone())}
                     }
                 )
                 // todo!()
@@ -42,12 +54,31 @@ fn build_get_update_statement(input: &DeriveInput) -> TokenStream {
                         let child_table = insert_statements.clone().find_map(|stmt|Some(stmt.table_name())).unwrap_or(
                             tailwag::orm::data_definition::table::Identifier::new_unchecked("__nothin_to_insert__")
                         );
+                        let schema = insert_statements.clone().find_map(|stmt|stmt.schema());
+                        let primary_key = insert_statements.clone().next().map(|stmt|stmt.primary_key().to_vec()).unwrap_or_default();
                         let values = insert_statements.into_iter().map(|stmt|Box::new(stmt.object_repr().clone())).collect();
-                        tailwag::orm::data_definition::table::ColumnValue::OneToMany{child_table, values}
+                        tailwag::orm::data_definition::table::ColumnValue::OneToMany{child_table, schema, primary_key, values}
+                    }
+                )
+            },
+            // The child table is known up front, so that links can still be cleared when the list is empty.
+            E::ManyToMany(child_table) => {
+                let child_table = child_table.to_string();
+                quote!(
+                    {
+                        let statements = #field_name.iter().map(|child|child.get_update_statement()).collect::<Vec<_>>();
+                        let schema = statements.iter().find_map(|stmt|stmt.schema());
+                        let primary_key = statements.first().map(|stmt|stmt.primary_key().to_vec()).unwrap_or_default();
+                        let values = statements.iter().map(|stmt|Box::new(stmt.object_repr().clone())).collect();
+                        tailwag::orm::data_definition::table::ColumnValue::ManyToMany{
+                            child_table: tailwag::orm::data_definition::table::Identifier::new_unchecked(#child_table),
+                            schema,
+                            primary_key,
+                            values,
+                        }
                     }
                 )
             },
-            tailwag_orm::data_definition::table::DatabaseColumnType::ManyToMany(_) => todo!("{:?} is a ManyToMany relationship that isn't yet supported", &column.column_type),
         };
 
         if column.is_nullable() {
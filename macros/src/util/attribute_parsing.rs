@@ -18,3 +18,22 @@ impl<'a> GetAttribute<'a> for Field {
             .find(|a| a.path().is_ident(attr_name))
     }
 }
+
+/// Reads the raw SQL from an attribute like `#[default("now()")]` or `#[generated(price * quantity)]`.
+/// String literals are used as-is, so that SQL which isn't valid Rust (e.g. `'pending'`) can be written.
+pub fn get_sql_expression(
+    field: &Field,
+    attr_name: &str,
+) -> Option<String> {
+    let attr = field.get_attribute(attr_name)?;
+    match attr.parse_args::<syn::LitStr>() {
+        Ok(lit) => Some(lit.value()),
+        Err(_) => Some(
+            attr.meta
+                .require_list()
+                .unwrap_or_else(|_| panic!("Expected #[{attr_name}(<sql>)]"))
+                .tokens
+                .to_string(),
+        ),
+    }
+}
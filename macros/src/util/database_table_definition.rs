@@ -2,15 +2,15 @@ use proc_macro2::TokenStream;
 /// TODO: Move the contents of this file outside, into a macro logic crate.
 ///
 /// That was the original point of this crate, but it has evolved into being used as ORM.
-use syn::{Data, DeriveInput, Field, GenericArgument, PathArguments};
+use syn::{punctuated::Punctuated, Data, DeriveInput, Field, GenericArgument, PathArguments, TypePath};
 
 use tailwag_orm::data_definition::table::Identifier;
 use tailwag_orm::data_definition::table::{
-    DatabaseColumnType, DatabaseTableDefinition, TableColumn,
+    DatabaseColumnType, DatabaseTableDefinition, EnumType, TableColumn, TableConstraint,
 };
 use tailwag_utils::strings::ToSnakeCase;
 
-use super::attribute_parsing::GetAttribute;
+use super::attribute_parsing::{get_sql_expression, GetAttribute};
 
 pub(crate) fn get_child_table_tokens(input: &DeriveInput) -> TokenStream {
     // Panic with error message if we get a non-struct
@@ -27,12 +27,20 @@ pub(crate) fn get_child_table_tokens(input: &DeriveInput) -> TokenStream {
         .iter()
         .filter(|f| f.get_attribute("db_ignore").is_none())
         .filter_map(|f| match get_type_from_field(f) {
+            DatabaseColumnType::Related(_) => {
+                let f_type = get_related_type(f);
+                Some(quote::quote!((tailwag::orm::data_definition::table::TableKey::of::<#f_type>(), Box::new(#f_type::get_table_definition()))))
+            },
+            DatabaseColumnType::ManyToMany(_) => {
+                let f_type = get_many_to_many_type(f);
+                Some(quote::quote!((tailwag::orm::data_definition::table::TableKey::of::<#f_type>(), Box::new(#f_type::get_table_definition()))))
+            },
             DatabaseColumnType::OneToOne(_) | DatabaseColumnType::OneToMany(_) => {
                 let syn::Type::Path(f_type) = &f.ty  else {return None};
                 let f_type = &f_type.path;
                 match try_get_inner_type(&f) {
-                    Some(f_type) => Some(quote::quote!((std::any::TypeId::of::<#f_type>(), Box::new(#f_type::get_table_definition())))),
-                    None => Some(quote::quote!((std::any::TypeId::of::<#f_type>(), Box::new(#f_type::get_table_definition())))) ,
+                    Some(f_type) => Some(quote::quote!((tailwag::orm::data_definition::table::TableKey::of::<#f_type>(), Box::new(#f_type::get_table_definition())))),
+                    None => Some(quote::quote!((tailwag::orm::data_definition::table::TableKey::of::<#f_type>(), Box::new(#f_type::get_table_definition())))) ,
                 }
             },
             _ => None,
@@ -46,6 +54,123 @@ pub(crate) fn get_child_table_tokens(input: &DeriveInput) -> TokenStream {
     .collect())
 }
 
+/// Builds the statements adding each many-to-many field's join table to `def.child_tables`, once the children are in
+/// place. Join tables link the two tables' keys, so they're derived from both definitions - a relationship that can't
+/// be joined (e.g. on a composite key) is left out here, and reported when the definition is validated.
+pub(crate) fn get_join_table_tokens(input: &DeriveInput) -> TokenStream {
+    let Data::Struct(data) = &input.data else {
+        panic!("Only Structs are supported.")
+    };
+    let syn::Fields::Named(fields) = &data.fields else {
+        panic!("Unnamed fields found in the struct.")
+    };
+
+    // Join tables aren't a type of their own, and there can be several between the same two tables, so each is keyed on
+    // a marker type local to its field.
+    let table_name = tailwag_utils::strings::to_snake_case(&input.ident.to_string());
+    let join_tables_tokens = fields
+        .named
+        .iter()
+        .filter(|f| f.get_attribute("db_ignore").is_none())
+        .filter_map(|f| match get_type_from_field(f) {
+            DatabaseColumnType::ManyToMany(child_table) => {
+                let field_name = f.ident.as_ref().expect("Only named fields are supported").to_string();
+                let f_type = get_many_to_many_type(f);
+                // A table relating to itself can't look its own definition up while it's being built.
+                let child_def = if *child_table == *table_name {
+                    quote::quote!(def.clone())
+                } else {
+                    quote::quote!(#f_type::get_table_definition())
+                };
+                Some(quote::quote!(
+                    if let Ok(join_table) = tailwag::orm::data_definition::table::DatabaseTableDefinition::join_table(
+                        &def,
+                        &tailwag::orm::data_definition::table::Identifier::new_unchecked(#field_name),
+                        &#child_def,
+                    ) {
+                        struct JoinTableMarker;
+                        def.child_tables.insert(tailwag::orm::data_definition::table::TableKey::of::<JoinTableMarker>(), Box::new(join_table));
+                    }
+                ))
+            },
+            _ => None,
+        });
+    quote::quote!(#(#join_tables_tokens)*)
+}
+
+/// The Postgres schema from `#[schema("billing")]` on the struct, if any.
+pub(crate) fn get_schema(input: &DeriveInput) -> Option<String> {
+    let attr = input.attrs.iter().find(|attr| attr.path().is_ident("schema"))?;
+    let schema = attr
+        .parse_args::<syn::LitStr>()
+        .expect("Expected the schema name, e.g. #[schema(\"billing\")]");
+    Some(schema.value())
+}
+
+/// The fields making up the primary key: those marked `#[primary_key]`, in field order, or else the `id` field.
+pub(crate) fn get_primary_key_fields(fields: &syn::FieldsNamed) -> Vec<&Field> {
+    let marked = fields
+        .named
+        .iter()
+        .filter(|f| f.get_attribute("primary_key").is_some())
+        .collect::<Vec<_>>();
+    if !marked.is_empty() {
+        return marked;
+    }
+    fields
+        .named
+        .iter()
+        .filter(|f| f.ident.as_ref().is_some_and(|ident| ident == "id"))
+        .collect()
+}
+
+/// True for a `#[primary_key(identity)]` field, which the database numbers on insert.
+pub(crate) fn is_identity_key(field: &Field) -> bool {
+    let Some(attr) = field.get_attribute("primary_key") else {
+        return false;
+    };
+    if let syn::Meta::Path(_) = attr.meta {
+        return false;
+    }
+    match attr.parse_args::<syn::Ident>() {
+        Ok(option) if option == "identity" => (),
+        _ => panic!("Expected #[primary_key] or #[primary_key(identity)]"),
+    }
+    match get_type_from_field(field) {
+        DatabaseColumnType::Int | DatabaseColumnType::BigInt => true,
+        _ => panic!("#[primary_key(identity)] is only supported on integer fields, e.g. i64."),
+    }
+}
+
+/// The primary key's type and its value on `self` - the field itself for a single key, or a tuple for a composite key.
+pub(crate) fn get_primary_key_tokens(fields: &syn::FieldsNamed) -> (TokenStream, TokenStream) {
+    match get_primary_key_fields(fields).as_slice() {
+        [] => panic!(
+            "No primary key found. Add an `id` field, or mark the key field(s) with #[primary_key]."
+        ),
+        [key] => {
+            let (name, ty) = (&key.ident, &key.ty);
+            (quote::quote!(#ty), quote::quote!(self.#name.clone()))
+        },
+        keys => {
+            let names = keys.iter().map(|f| &f.ident);
+            let types = keys.iter().map(|f| &f.ty);
+            (quote::quote!((#(#types,)*)), quote::quote!((#(self.#names.clone(),)*)))
+        },
+    }
+}
+
+/// The name of the column a field is stored in. Relationships stored on this table are stored by id.
+pub(crate) fn get_column_name(field: &Field) -> String {
+    let field_name = field.ident.as_ref().expect("Found unnamed field in struct");
+    match get_type_from_field(field) {
+        DatabaseColumnType::OneToOne(_) | DatabaseColumnType::Related(_) => {
+            format!("{field_name}_id")
+        },
+        _ => field_name.to_string(),
+    }
+}
+
 pub(crate) fn build_table_definition<T>(input: &DeriveInput) -> DatabaseTableDefinition {
     let &DeriveInput {
         ident,
@@ -63,37 +188,80 @@ pub(crate) fn build_table_definition<T>(input: &DeriveInput) -> DatabaseTableDef
         panic!("Unnamed fields found in the struct.")
     };
 
+    let primary_key = get_primary_key_fields(fields);
+    let is_composite_key = primary_key.len() > 1;
     let columns = fields.named.iter().filter(|f| f.get_attribute("db_ignore").is_none()).map(|f| {
-        let field_name = f.ident.as_ref().expect("Found unnamed field in struct");
-
         let column_type = get_type_from_field(f);
-        let column_name = match &column_type {
-            DatabaseColumnType::OneToOne(_) => {
-                format!("{field_name}_id")
-            },
-            DatabaseColumnType::OneToMany(_) => format!("{field_name}"),
-            _ => field_name.to_string(),
-        };
+        let column_name = get_column_name(f);
         let mut column =
             TableColumn::new(&column_name, column_type, Vec::new()).expect("Invalid table_name");
         // TODO: Handle #[flatten], which will flatten the pieces into a single table. Will that work? Gonna be tough in a derive macro.
 
-        if f.get_attribute("primary_key").is_some() || &field_name.to_string() == "id" {
+        // Composite keys are a table constraint, added below.
+        if primary_key.contains(&f) && !is_composite_key {
             column = column.pk();
         }
+        if is_identity_key(f) {
+            column = column.identity();
+        }
 
         if !is_option(f) {
             column = column.non_null();
         }
 
+        // `#[default]` clashes with `#[derive(Default)]`, so `#[db_default]` is accepted as well.
+        if let Some(expression) =
+            get_sql_expression(f, "default").or_else(|| get_sql_expression(f, "db_default"))
+        {
+            column = column.default(&expression);
+        }
+        if let Some(expression) = get_sql_expression(f, "generated") {
+            column = column.generated(&expression);
+        }
+        if f.get_attribute("unique").is_some() {
+            column = column.unique();
+        }
+
         // TODO: If _??!!??!???? then handle foreign keys
         column
     });
 
     let mut table = DatabaseTableDefinition::new(&table_name).expect("Table name is invalid");
+    if let Some(schema) = get_schema(input) {
+        table = table.schema(&schema).expect("Schema name is invalid");
+    }
     for column in columns {
         table.add_column(column);
     }
+    if is_composite_key {
+        let key_columns = primary_key
+            .iter()
+            .map(|f| table.columns[&Identifier::new_unchecked(get_column_name(f))].clone())
+            .collect();
+        table.constraints.push(TableConstraint::primary_key(key_columns));
+    }
+
+    // `#[unique(tenant_id, email)]` on the struct - named the way Postgres names unique constraints.
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("unique")) {
+        let field_names = attr
+            .parse_args_with(Punctuated::<syn::Ident, syn::Token![,]>::parse_terminated)
+            .expect("Expected the unique columns, e.g. #[unique(tenant_id, email)]");
+        let key_columns = field_names
+            .iter()
+            .map(|field_name| {
+                let field = fields
+                    .named
+                    .iter()
+                    .find(|f| f.ident.as_ref() == Some(field_name))
+                    .unwrap_or_else(|| panic!("#[unique] column `{field_name}` is not a field"));
+                table.columns[&Identifier::new_unchecked(get_column_name(field))].clone()
+            })
+            .collect::<Vec<_>>();
+        let column_names =
+            key_columns.iter().map(|column| column.column_name.to_string()).collect::<Vec<_>>();
+        let name = format!("{table_name}_{}_key", column_names.join("_"));
+        table.constraints.push(TableConstraint::unique(Identifier::new_unchecked(name), key_columns));
+    }
 
     table.into()
 }
@@ -123,6 +291,88 @@ pub fn get_inner_type(field: &Field) -> &GenericArgument {
     try_get_inner_type(field).unwrap()
 }
 
+/// Finds `T` in the first `wrapper<T>` within `ty`, e.g. `Related<T>` inside an `Option<Related<T>>`.
+fn find_wrapped_type<'a>(
+    ty: &'a syn::Type,
+    wrapper: &str,
+) -> Option<&'a TypePath> {
+    let syn::Type::Path(typepath) = ty else {
+        return None;
+    };
+    let segment = typepath.path.segments.last()?;
+    let PathArguments::AngleBracketed(params) = &segment.arguments else {
+        return None;
+    };
+    let Some(GenericArgument::Type(inner)) = params.args.first() else {
+        return None;
+    };
+    if segment.ident == wrapper {
+        match inner {
+            syn::Type::Path(inner) => Some(inner),
+            _ => None,
+        }
+    } else {
+        find_wrapped_type(inner, wrapper)
+    }
+}
+
+/// Finds `T` in a `Related<T>` or `Option<Related<T>>` field.
+pub fn get_related_type(field: &Field) -> &TypePath {
+    find_wrapped_type(&field.ty, "Related").expect("Expected a Related<T> field")
+}
+
+/// Finds `T` in a `Vec<Arc<T>>` (many-to-many) field.
+pub fn get_many_to_many_type(field: &Field) -> &TypePath {
+    find_wrapped_type(&field.ty, "Arc").expect("Expected a Vec<Arc<T>> field")
+}
+
+/// Maps a primitive (non-relationship) type to its column type.
+fn get_scalar_type(typepath: &TypePath) -> Option<DatabaseColumnType> {
+    let qualified_path = get_qualified_path(typepath);
+    Some(match qualified_path.as_str() {
+        "std::string::String" | "string::String" | "String" => DatabaseColumnType::String,
+        "bool" => DatabaseColumnType::Boolean,
+        "i32" => DatabaseColumnType::Int,
+        // `u32` doesn't fit in an `INT` either.
+        "u32" | "u64" | "i64" | "usize" | "isize" => DatabaseColumnType::BigInt,
+        "f32" | "f64" | "fsize" => DatabaseColumnType::Float,
+        "chrono::NaiveDateTime" | "NaiveDateTime" => DatabaseColumnType::Timestamp,
+        // Only `DateTime<Utc>` is supported - the offset isn't stored, so other time zones wouldn't round-trip.
+        "chrono::DateTime" | "DateTime" => {
+            let time_zone = find_wrapped_type(&syn::Type::Path(typepath.clone()), "DateTime")
+                .map(get_qualified_path);
+            match time_zone.as_deref() {
+                Some("chrono::Utc" | "Utc" | "chrono::offset::Utc") => DatabaseColumnType::TimestampTz,
+                _ => panic!("Only DateTime<Utc> can be stored - convert other time zones to UTC first."),
+            }
+        },
+        "chrono::NaiveDate" | "NaiveDate" => DatabaseColumnType::Date,
+        "chrono::NaiveTime" | "NaiveTime" => DatabaseColumnType::Time,
+        "rust_decimal::Decimal" | "Decimal" => DatabaseColumnType::Numeric,
+        "std::time::Duration" | "core::time::Duration" => DatabaseColumnType::Interval,
+        // Other crates (e.g. `chrono` and `time`) have their own `Duration`, so a bare one is ambiguous.
+        "Duration" | "time::Duration" => {
+            panic!("Use the full path for Duration columns, e.g. std::time::Duration.")
+        },
+        "uuid::Uuid" | "Uuid" => DatabaseColumnType::Uuid,
+        _ => return None,
+    })
+}
+
+/// The column type for a `Vec` of primitives (or `Option<Vec<..>>`), or `None` if it's a relationship.
+fn get_vec_element_type(field: &Field) -> Option<DatabaseColumnType> {
+    let element_type = find_wrapped_type(&field.ty, "Vec")?;
+    match get_qualified_path(element_type).as_str() {
+        "u8" => return Some(DatabaseColumnType::Bytea),
+        // Integer arrays are bound as `i32`s (`INT[]`) or `i64`s (`BIGINT[]`), which these don't all fit in.
+        "u64" | "usize" | "isize" => {
+            panic!("Arrays of u64, usize or isize can't be stored - use Vec<i64> (BIGINT[]) instead.")
+        },
+        _ => (),
+    }
+    get_scalar_type(element_type).map(|element_type| DatabaseColumnType::Array(Box::new(element_type)))
+}
+
 pub fn get_type_from_field(field: &Field) -> DatabaseColumnType {
     match &field.ty {
         syn::Type::Path(typepath) => {
@@ -153,11 +403,19 @@ pub fn get_type_from_field(field: &Field) -> DatabaseColumnType {
                 _ => None,
             }
             .unwrap_or(qualified_path);
+            let scalar_type = match is_option(field) {
+                true => find_wrapped_type(&field.ty, "Option").unwrap_or(typepath),
+                false => typepath,
+            };
 
             let db_type = if field.get_attribute("string").is_some() {
                 DatabaseColumnType::String
             } else if field.get_attribute("json").is_some() {
                 DatabaseColumnType::Json
+            } else if field.get_attribute("db_enum").is_some() {
+                // The variants aren't known to the macro - they're filled in from `DbEnum::enum_type()`.
+                let type_name = qualified_path.split("::").last().unwrap().to_snake_case();
+                DatabaseColumnType::Enum(EnumType::new(Identifier::new_unchecked(type_name), Vec::new()))
             } else {
                 let child_table_name = field
                     .get_attribute("table_name")
@@ -166,17 +424,14 @@ pub fn get_type_from_field(field: &Field) -> DatabaseColumnType {
                     .map(|path| path.to_string())
                     .unwrap_or(qualified_path.split("::").last().unwrap().to_snake_case());
 
+                // A Vec of primitives is stored as an array (or BYTEA, for `Vec<u8>`). Any other Vec is a relationship.
+                if let Some(column_type) =
+                    get_scalar_type(scalar_type).or_else(|| get_vec_element_type(field))
+                {
+                    return column_type;
+                }
                 match qualified_path.as_str() {
-                    "std::string::String" | "string::String" | "String" => {
-                        DatabaseColumnType::String
-                    },
-                    "bool" => DatabaseColumnType::Boolean,
-                    "u32" | "u64" | "i32" | "i64" | "usize" | "isize" => DatabaseColumnType::Int,
-                    "f32" | "f64" | "fsize" => DatabaseColumnType::Float,
-                    // "chrono::DateTime" | "DateTime" => DatabaseColumnType::Timestamp, // TODO: This doesn't currently work (DateTime is TIMESTAMPTZ not TIMESTAMP)
-                    "chrono::NaiveDateTime" | "NaiveDateTime" => DatabaseColumnType::Timestamp,
-                    "uuid::Uuid" | "Uuid" => DatabaseColumnType::Uuid,
-                    // If it's a Vec, then we want to do one-to-many (does not account for Vec of primitives)
+                    // If it's a Vec, then we want to do one-to-many
                     "std::vec::Vec" | "vec::Vec" | "alloc::Vec" | "Vec" => {
                         // NOTE: For now, I'll plan that OneToMany requires the parent table have a `parent_id` attribute. I hope/plan to find a way around this later.
                         // let child_table_name = field.get_attribute("table_name"")
@@ -185,6 +440,14 @@ pub fn get_type_from_field(field: &Field) -> DatabaseColumnType {
                             GenericArgument::Type(syn::Type::Path(t)) => get_qualified_path(t),
                             _ => panic!("no type T found for Option<T>"),
                         };
+                        // Arc means "Not owned" / shared reference - a `Vec<Arc<T>>` becomes many-to-many.
+                        let is_shared =
+                            matches!(inner_type.as_str(), "std::sync::Arc" | "sync::Arc" | "Arc");
+                        let inner_type = if is_shared {
+                            get_qualified_path(get_many_to_many_type(field))
+                        } else {
+                            inner_type
+                        };
 
                         let child_table_name = field
                             .get_attribute("table_name")
@@ -193,11 +456,25 @@ pub fn get_type_from_field(field: &Field) -> DatabaseColumnType {
                             .map(|path| path.to_string())
                             .unwrap_or(inner_type.split("::").last().unwrap().to_snake_case());
 
-                        DatabaseColumnType::OneToMany(Identifier::new(&child_table_name).unwrap())
+                        if is_shared {
+                            DatabaseColumnType::ManyToMany(Identifier::new(&child_table_name).unwrap())
+                        } else {
+                            DatabaseColumnType::OneToMany(Identifier::new(&child_table_name).unwrap())
+                        }
+                    },
+                    // Lazily loaded - only the ID is stored.
+                    "tailwag::orm::data_manager::Related" | "data_manager::Related" | "Related" => {
+                        let related_type = get_related_type(field);
+                        let child_table_name = field
+                            .get_attribute("table_name")
+                            .map(|attr| attr.meta.require_list().unwrap())
+                            .map(|meta| meta.path.get_ident().unwrap())
+                            .map(|path| path.to_string())
+                            .unwrap_or(get_qualified_path(related_type).split("::").last().unwrap().to_snake_case());
+                        DatabaseColumnType::Related(Identifier::new(&child_table_name).unwrap())
                     },
-                    // Arc means "Not owned" / shared reference - becomes many-to-many (or maybe could be many-to-one)
                     "std::sync::Arc" | "sync::Arc" | "Arc" => {
-                        DatabaseColumnType::ManyToMany(Identifier::new(&child_table_name).unwrap())
+                        panic!("A single Arc<T> can't be stored - use Vec<Arc<T>> for a many-to-many relationship, or Related<T> for a reference to one object.")
                     },
                     _ => DatabaseColumnType::OneToOne(Identifier::new(&child_table_name).unwrap()),
                 }
@@ -209,3 +486,35 @@ pub fn get_type_from_field(field: &Field) -> DatabaseColumnType {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tailwag_orm::{migration::CreateTable, AsSql};
+
+    use super::build_table_definition;
+
+    #[test]
+    fn i64_identity_keys_are_bigint_identity_columns() {
+        let input = syn::parse_quote! {
+            struct Invoice {
+                #[primary_key(identity)]
+                number: i64,
+                line_count: i32,
+                total_cents: u64,
+                line_totals: Vec<i64>,
+            }
+        };
+        let table = build_table_definition::<()>(&input);
+
+        assert_eq!(
+            CreateTable::new(Arc::new(table)).as_sql(),
+            "CREATE TABLE IF NOT EXISTS invoice (\
+             line_count INT NOT NULL,\
+             line_totals BIGINT[] NOT NULL,\
+             number BIGINT PRIMARY KEY  GENERATED BY DEFAULT AS IDENTITY NOT NULL,\
+             total_cents BIGINT NOT NULL);"
+        );
+    }
+}
@@ -2,7 +2,7 @@ use std::{collections::HashMap, ops::Deref, sync::Arc};
 
 use super::table::{DatabaseTableDefinition, Identifier};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct DatabaseDefinition {
     data: Arc<DatabaseDefinitionBuilder>,
 }
@@ -15,21 +15,8 @@ impl Deref for DatabaseDefinition {
     }
 }
 
-// impl DatabaseDefinition {
-// TODO: Make it so that I can read and parse the existing DB Definition from a file (use serde_json)
-//     pub fn from_file(file_path: &str) {
-//         todo!()
-//     }
-
-//     pub fn to_file(file_path: &str) {
-//         todo!()
-//     }
-//
-// TODO: Make it so that I can read and parse the existing DB Definition on a remote DB
-//     pub fn from_postgres_database(db_connection_string: &str) {
-//         todo!()
-//     }
-// }
+// Reading the definition from a file lives in `snapshot.rs` (`from_file` / `to_file`), and from a live DB in
+// `introspection.rs` (`from_postgres_database`).
 
 impl DatabaseDefinition {
     /// Creates an empty DatabaseDefinitionData object.
@@ -41,6 +28,7 @@ impl DatabaseDefinition {
     ///
     /// Example:
     /// ```
+    /// # use tailwag_orm::data_definition::database_definition::DatabaseDefinition;
     /// let database_definition: DatabaseDefinition
     ///     = DatabaseDefinition::new("new_database")
     ///         .expect("Something went wrong")
@@ -55,8 +43,9 @@ impl DatabaseDefinition {
     /// instead this method panics if `name` is an invalid identifier.
     /// Example:
     /// ```
+    /// # use tailwag_orm::data_definition::database_definition::DatabaseDefinition;
     /// let database_definition: DatabaseDefinition
-    ///     = DatabaseDefinition::new("new_database").into();
+    ///     = DatabaseDefinition::new_unchecked("new_database").into();
     /// ```
     pub fn new_unchecked(name: &str) -> DatabaseDefinitionBuilder {
         match DatabaseDefinitionBuilder::new(name) {
@@ -72,17 +61,35 @@ pub struct DatabaseDefinitionBuilder {
     pub tables: Vec<DatabaseTableDefinition>,
 }
 
+/// Tables are compared regardless of their order, as snapshots sort them by name.
+impl PartialEq for DatabaseDefinitionBuilder {
+    fn eq(
+        &self,
+        other: &Self,
+    ) -> bool {
+        fn tables(definition: &DatabaseDefinitionBuilder) -> Vec<&DatabaseTableDefinition> {
+            let mut tables = definition.tables.iter().collect::<Vec<_>>();
+            tables.sort_by_key(|table| table.qualified_name());
+            tables
+        }
+        self.name == other.name && tables(self) == tables(other)
+    }
+}
+
+impl Eq for DatabaseDefinitionBuilder {}
+
 impl From<DatabaseDefinitionBuilder> for DatabaseDefinition {
     fn from(mut val: DatabaseDefinitionBuilder) -> Self {
         // This is where we need to preprocess the stuff and make sure the tables are okay / consistent.
         // TODO: ^^^^^ That
 
-        // First, gather all tables into a map:
+        // First, gather all tables into a map. Keyed on the qualified name, as tables in different schemas can share a
+        // name.
         // QUESTION: Should this be stored as a map ANYWAY for quick access?
         let map = val
             .tables
             .iter()
-            .map(|table| (&table.table_name, table))
+            .map(|table| (table.qualified_name(), table))
             .collect::<HashMap<_, _>>();
 
         let mut new_tables = Vec::new();
@@ -98,24 +105,36 @@ impl From<DatabaseDefinitionBuilder> for DatabaseDefinition {
                         // 5.
                     },
                     super::table::DatabaseColumnType::ManyToMany(child_table_ident) => {
-                        // TODO: Add teh FK constraints
-                        let join_table = DatabaseTableDefinition::new(&format!(
-                            "{}_to_{}",
-                            &table.table_name, &child_table_ident
-                        ))
-                        .expect("Should always be valid")
-                        .with_uuid(&format!("{}_id", &table.table_name))
-                        .expect("Should always be valid")
-                        .with_uuid(&format!("{}_id", &child_table_ident))
-                        .expect("Should always be valid");
-                        new_tables.push(join_table.into());
-                        todo!("Many to Many not yet supported. Need to add constraints still, and impl the non mmigratory parts.");
-
                         // Many to Many means/assumes the following:
                         // 1. A join table is required.
-                        // 2. This join table will have the PARENT_ID / CHILD_ID relationship
+                        // 2. This join table will have the PARENT_ID / CHILD_ID relationship, and is named after the field too
                         // 3. At least for the first iter, will be a one-way relationship. Will  think about the best way to model it for two-way
                         // 4. When querying - two joins must be done (join table and table table)
+                        // The join table lives in the parent's schema, and is keyed on both tables' keys.
+                        // Without a child table (or a single-column key on both sides) there's nothing to
+                        // link - `validate_tables()` reports why.
+                        let Some(child_table) =
+                            table.related_table(child_table_ident).or_else(|| {
+                                DatabaseTableDefinition::resolve_table(
+                                    map.values().copied(),
+                                    child_table_ident,
+                                    table.schema.as_ref(),
+                                )
+                            })
+                        else {
+                            continue;
+                        };
+                        let Ok(join_table) = DatabaseTableDefinition::join_table(
+                            table,
+                            &column.column_name,
+                            child_table,
+                        ) else {
+                            continue;
+                        };
+                        // Definitions read back from a snapshot already include their join tables.
+                        if !map.contains_key(&join_table.qualified_name()) {
+                            new_tables.push(join_table);
+                        }
 
                         // FUTURE FEATURE IDEA: Define ability to use a Metatdata table to specify info about the edge in the join table
                         // Will clustering be needed at all here?
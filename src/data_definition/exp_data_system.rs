@@ -1,15 +1,22 @@
-use std::{any::TypeId, cell::RefCell, collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 
-use sqlx::{Postgres, QueryBuilder};
+use sqlx::Postgres;
 
 use crate::{
     data_manager::{GetTableDefinition, PostgresDataProvider},
     migration::Migration,
     queries::Insertable,
-    BuildSql,
 };
 
-use super::table::{raw_data::TableDefinition, DatabaseTableDefinition, Identifier};
+use super::{
+    database_definition::{DatabaseDefinition, DatabaseDefinitionBuilder},
+    table::{raw_data::TableDefinition, DatabaseTableDefinition, Identifier, TableKey},
+    validation::validate_tables,
+};
+
+/// Where `DataSystem::run_migrations()` keeps a snapshot of the last migrated tables, to diff the next run against.
+/// Committing it to git keeps migrations consistent across machines.
+const MIGRATION_SNAPSHOT_PATH: &str = ".table_data/last.migration";
 
 // pub(crate) trait GenericizedTableDefinition: std::any::Any + TableDefinition {}
 pub(crate) type TableDef = Arc<DatabaseTableDefinition>;
@@ -18,8 +25,8 @@ pub(crate) type TableDef = Arc<DatabaseTableDefinition>;
 
 #[derive(Default)]
 pub struct DataSystemBuilder {
-    resources: HashMap<TypeId, DatabaseTableDefinition>,
-    table_name_to_type: HashMap<Identifier, TypeId>,
+    resources: HashMap<TableKey, DatabaseTableDefinition>,
+    table_name_to_type: HashMap<Identifier, TableKey>,
 }
 
 impl DataSystemBuilder {
@@ -38,9 +45,9 @@ impl DataSystemBuilder {
         &mut self,
         table_def: DatabaseTableDefinition,
     ) {
-        let type_id = TypeId::of::<T>();
-        self.table_name_to_type.insert(table_def.table_name.clone(), type_id);
-        self.resources.insert(type_id, table_def);
+        let key = TableKey::of::<T>();
+        self.table_name_to_type.insert(table_def.table_name.clone(), key.clone());
+        self.resources.insert(key, table_def);
     }
     // pub fn get<T: GetTableDefinition + Clone + Send + 'static>(
     //     &self
@@ -62,72 +69,28 @@ impl DataSystemBuilder {
 
         let mut stack = resources.clone().into_iter().collect::<Vec<_>>();
 
-        while let Some((_type_id, table_def)) = stack.pop() {
-            for (child_type_id, child_def) in table_def.child_tables().into_iter() {
-                stack.push((child_type_id, *child_def.clone()));
-                resources.insert(child_type_id, *child_def);
+        while let Some((_key, table_def)) = stack.pop() {
+            for (child_key, child_def) in table_def.child_tables().into_iter() {
+                stack.push((child_key.clone(), *child_def.clone()));
+                resources.insert(child_key, *child_def);
             }
         }
 
-        // Put things into a RefCell sot hat we can modify during iteration.
-        let resources = resources
-            .into_iter()
-            .map(|(k, v)| (k, RefCell::new(v)))
-            .collect::<HashMap<_, _>>();
-
-        // Make sure all child tables are appropriately added.
-        // let mut children_of_tables = Vec::new();
-
-        // First pass: Make sure all child tables exist, and modify them where needed.
-        for (_, table_def) in &resources {
-            let table_def = table_def.borrow();
-            let _table_name = table_def.table_name();
-            let _table_id_col = table_def.columns().get(&Identifier::new_unchecked("id")).unwrap();
-            for _child_table in table_def.child_tables() {
-                // match child_table {
-                //     super::table::TableRelationship::OneToMany(child_name) => {
-                //         let child_table_type_id = table_name_to_type
-                //             .get(&child_name)
-                //             .expect(&format!(
-                //                 "Expected child table {child_name} does not exist in Data System. Aborting."
-                //             ));
-                //         let mut child_table = resources.get(&child_table_type_id)
-                //             .expect(&format!(
-                //                 "Expected child table {child_name} does not exist in Data System. Aborting."
-                //             )).borrow_mut();
-                //         // TODO (UUID id requirement): Make this more dynamic when I remove the "must have UUID" requirement
-                //         let parent_table_col_name = format!("{table_name}_id");
-                //         child_table.add_column(
-                //             TableColumn::new_uuid(&parent_table_col_name)?
-                //                 .non_null()
-                //                 .fk_to(table_name.clone(), table_id_col.clone()),
-                //         );
-                //     },
-                //     super::table::TableRelationship::ManyToMany(_child_name) => {
-                //         todo!("Need to create a NEW join table connecting these")
-                //     },
-                //     super::table::TableRelationship::OneToOne(child_name) => {
-                //         let child_table = table_name_to_type
-                //             .get(&child_name)
-                //             .and_then(|type_id| resources.get(type_id));
-                //         // Just make sure the child table exists - migrations will handle the actual column name down the line.
-                //         // TODO: Actually migrations don't do it, it happens in the macro. I should move it here for posterity's sake. Can happen later.
-                //         assert!(child_table.is_some());
-                //     },
-                // }
-            }
+        // Every problem is reported at once, rather than failing on the first query that hits one.
+        let diagnostics = validate_tables(resources.values());
+        if !diagnostics.is_empty() {
+            return Err(crate::Error::InvalidSchema(diagnostics));
         }
+
         Ok(UnconnectedDataSystem {
-            resources: Arc::new(
-                resources.into_iter().map(|(k, v)| (k, Arc::new(v.into_inner()))).collect(),
-            ),
+            resources: Arc::new(resources.into_iter().map(|(k, v)| (k, Arc::new(v))).collect()),
         })
     }
 }
 
 #[derive(Clone)]
 pub struct UnconnectedDataSystem {
-    resources: Arc<HashMap<TypeId, Arc<DatabaseTableDefinition>>>,
+    resources: Arc<HashMap<TableKey, Arc<DatabaseTableDefinition>>>,
 }
 impl UnconnectedDataSystem {
     pub async fn connect(
@@ -143,7 +106,7 @@ impl UnconnectedDataSystem {
 
 #[derive(Clone)]
 pub struct DataSystem {
-    resources: Arc<HashMap<TypeId, Arc<DatabaseTableDefinition>>>,
+    resources: Arc<HashMap<TableKey, Arc<DatabaseTableDefinition>>>,
     pool: sqlx::Pool<Postgres>,
 }
 
@@ -156,44 +119,37 @@ impl DataSystem {
 impl DataSystem {
     pub fn get<T: Clone + Insertable + Send + 'static>(&self) -> Option<PostgresDataProvider<T>> {
         self.resources
-            .get(&TypeId::of::<T>())
+            .get(&TableKey::of::<T>())
             .map(|t| PostgresDataProvider::new(t.clone(), self.pool.clone()))
     }
 
     pub async fn run_migrations(&self) -> Result<(), crate::Error> {
-        fn get_prev_tables_if_exists() -> Option<Vec<Arc<DatabaseTableDefinition>>> {
-            std::fs::read(".table_data/last.migration")
-                .ok()
-                .and_then(|bytes| serde_json::from_slice(bytes.as_slice()).ok())
-        }
-
-        fn _save_prev_tables(
-            database: Vec<Arc<DatabaseTableDefinition>>
-        ) -> Result<(), std::io::Error> {
-            let deser = serde_json::to_string(&database)?;
-            let bytes = deser.as_bytes();
-
-            // Currently panicing - failing to serialize.
-            std::fs::write(".table_data/last.migration", bytes)?;
-            Ok(())
-        }
+        let previous = match DatabaseDefinition::from_file(MIGRATION_SNAPSHOT_PATH) {
+            Ok(previous) => Some(previous.tables.iter().cloned().map(Arc::new).collect()),
+            Err(crate::Error::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
 
         let current_config: Vec<Arc<DatabaseTableDefinition>> =
             self.resources.values().map(|table| table.to_owned()).collect();
-        if let Some(migrations) =
-            Migration::compare(get_prev_tables_if_exists(), current_config.clone())
-        {
-            let mut transaction = self.pool.begin().await?;
-            for action in migrations.actions {
-                let mut builder = QueryBuilder::new("");
-                action.build_sql(&mut builder);
-                builder.build().execute(&mut *transaction).await?;
-            }
-            transaction.commit().await?;
+        if let Some(migrations) = Migration::compare(previous, current_config.clone()) {
+            migrations.run(&self.pool).await?;
+        }
+
+        // Child tables are already registered as resources, so they're dropped here to avoid writing them twice.
+        let tables = current_config
+            .iter()
+            .map(|table| DatabaseTableDefinition {
+                child_tables: HashMap::new(),
+                ..(**table).clone()
+            })
+            .collect();
+        let snapshot: DatabaseDefinition = DatabaseDefinitionBuilder {
+            name: Identifier::new_unchecked("data_system"),
+            tables,
         }
-        // TODO: It's crashing when trying to serialze the migrations. Need to dig in.
-        // save_prev_tables(current_config)?;
-        Ok(())
+        .into();
+        snapshot.to_file(MIGRATION_SNAPSHOT_PATH)
     }
 }
 
@@ -0,0 +1,780 @@
+use std::{collections::HashMap, sync::Arc};
+
+use sqlx::{FromRow, Pool, Postgres};
+
+use super::{
+    database_definition::{DatabaseDefinition, DatabaseDefinitionBuilder},
+    table::{
+        CheckExpressionConstraint, DatabaseColumnType, DatabaseTableDefinition, EnumType,
+        ForeignKeyConstraint, Identifier, IndexMethod, ReferencesConstraint,
+        ReferencesConstraintMatchType, ReferentialAction, TableColumn, TableColumnConstraint,
+        TableColumnConstraintDetail, TableColumnData, TableConstraint, TableConstraintDetail,
+        TableIndex,
+    },
+};
+
+/// Something in the database that couldn't be mapped to the crate's types. Unmappable columns, constraints
+/// and indexes are left out of the definition; anything else is kept, minus the unmappable part.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UnmappedItem {
+    /// The table it belongs to, qualified with its schema (e.g. `billing.invoice`).
+    pub table: String,
+    /// What couldn't be mapped, e.g. `column location` or `index account_lower_email_idx`.
+    pub item: String,
+    pub reason: String,
+}
+
+/// A live database, read by `DatabaseDefinition::from_postgres_database()`.
+#[derive(Debug)]
+pub struct IntrospectedDatabase {
+    pub definition: DatabaseDefinition,
+    pub unmapped: Vec<UnmappedItem>,
+}
+
+impl DatabaseDefinition {
+    /// Reads the tables of every non-system schema from `information_schema` / `pg_catalog`, with their
+    /// columns, nullability, defaults, keys, foreign keys, unique and check constraints, and indexes.
+    ///
+    /// Types are mapped to the closest `DatabaseColumnType` - e.g. `SMALLINT` reads as `Int`,
+    /// and `TEXT` as `String`. Anything with no equivalent is listed in `unmapped`, as are columns whose type only
+    /// maps approximately (e.g. `SMALLINT`, or `VARCHAR(20)`'s length).
+    pub async fn from_postgres_database(
+        name: &str,
+        db_pool: &Pool<Postgres>,
+    ) -> Result<IntrospectedDatabase, crate::Error> {
+        let catalog = Catalog {
+            tables: sqlx::query_as(TABLES_QUERY).fetch_all(db_pool).await?,
+            columns: sqlx::query_as(COLUMNS_QUERY).fetch_all(db_pool).await?,
+            enums: sqlx::query_as(ENUMS_QUERY).fetch_all(db_pool).await?,
+            constraints: sqlx::query_as(CONSTRAINTS_QUERY).fetch_all(db_pool).await?,
+            indexes: sqlx::query_as(INDEXES_QUERY).fetch_all(db_pool).await?,
+        };
+        let (tables, unmapped) = catalog.into_tables();
+
+        let mut builder = DatabaseDefinitionBuilder::new(name)?;
+        builder.tables = tables;
+        Ok(IntrospectedDatabase {
+            definition: builder.into(),
+            unmapped,
+        })
+    }
+}
+
+const TABLES_QUERY: &str =
+    "SELECT table_schema::text, table_name::text FROM information_schema.tables \
+     WHERE table_type = 'BASE TABLE' AND table_schema NOT IN ('pg_catalog', 'information_schema') \
+     ORDER BY table_schema, table_name";
+
+const COLUMNS_QUERY: &str = "SELECT table_schema::text, table_name::text, column_name::text, data_type::text, \
+     udt_schema::text, udt_name::text, character_maximum_length::int AS max_length, \
+     numeric_precision::int, numeric_scale::int, is_nullable::text = 'YES' AS is_nullable, column_default::text, is_identity::text = 'YES' AS is_identity, \
+     generation_expression::text FROM information_schema.columns \
+     WHERE table_schema NOT IN ('pg_catalog', 'information_schema') \
+     ORDER BY table_schema, table_name, ordinal_position";
+
+const ENUMS_QUERY: &str = "SELECT n.nspname::text AS type_schema, t.typname::text AS type_name, e.enumlabel::text AS variant \
+     FROM pg_type t JOIN pg_enum e ON e.enumtypid = t.oid JOIN pg_namespace n ON n.oid = t.typnamespace \
+     ORDER BY n.nspname, t.typname, e.enumsortorder";
+
+const CONSTRAINTS_QUERY: &str = "SELECT n.nspname::text AS table_schema, cl.relname::text AS table_name, con.conname::text AS name, \
+     con.contype::text AS kind, \
+     ARRAY(SELECT a.attname::text FROM unnest(con.conkey) WITH ORDINALITY AS k(attnum, ord) \
+         JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum ORDER BY k.ord) AS columns, \
+     fn.nspname::text AS ref_schema, fcl.relname::text AS ref_table, \
+     ARRAY(SELECT a.attname::text FROM unnest(con.confkey) WITH ORDINALITY AS k(attnum, ord) \
+         JOIN pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.attnum ORDER BY k.ord) AS ref_columns, \
+     con.confdeltype::text AS on_delete, con.confupdtype::text AS on_update, con.confmatchtype::text AS match_type, \
+     pg_get_expr(con.conbin, con.conrelid) AS check_expression \
+     FROM pg_constraint con \
+     JOIN pg_class cl ON cl.oid = con.conrelid JOIN pg_namespace n ON n.oid = cl.relnamespace \
+     LEFT JOIN pg_class fcl ON fcl.oid = con.confrelid LEFT JOIN pg_namespace fn ON fn.oid = fcl.relnamespace \
+     WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') \
+     ORDER BY n.nspname, cl.relname, con.conname";
+
+const INDEXES_QUERY: &str = "SELECT n.nspname::text AS table_schema, t.relname::text AS table_name, i.relname::text AS name, \
+     ix.indisunique AS is_unique, am.amname::text AS method, \
+     ARRAY(SELECT a.attname::text FROM unnest(ix.indkey::int2[]) WITH ORDINALITY AS k(attnum, ord) \
+         JOIN pg_attribute a ON a.attrelid = ix.indrelid AND a.attnum = k.attnum \
+         WHERE k.ord <= ix.indnkeyatts ORDER BY k.ord) AS columns, \
+     (ix.indexprs IS NOT NULL OR ix.indnatts > ix.indnkeyatts) AS is_unsupported, \
+     pg_get_expr(ix.indpred, ix.indrelid) AS predicate \
+     FROM pg_index ix \
+     JOIN pg_class i ON i.oid = ix.indexrelid JOIN pg_class t ON t.oid = ix.indrelid \
+     JOIN pg_namespace n ON n.oid = t.relnamespace JOIN pg_am am ON am.oid = i.relam \
+     WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname NOT LIKE 'pg\\_toast%' \
+     AND NOT EXISTS (SELECT 1 FROM pg_constraint con WHERE con.conindid = ix.indexrelid) \
+     ORDER BY n.nspname, t.relname, i.relname";
+
+#[derive(FromRow)]
+struct TableRow {
+    table_schema: String,
+    table_name: String,
+}
+
+#[derive(FromRow)]
+struct ColumnRow {
+    table_schema: String,
+    table_name: String,
+    column_name: String,
+    /// `ARRAY` or `USER-DEFINED` for arrays and enums - `udt_name` has the actual type.
+    data_type: String,
+    udt_schema: String,
+    udt_name: String,
+    /// The `n` of `VARCHAR(n)`.
+    max_length: Option<i32>,
+    /// The `p` and `s` of `NUMERIC(p, s)` - also set for the other numeric types.
+    numeric_precision: Option<i32>,
+    numeric_scale: Option<i32>,
+    is_nullable: bool,
+    column_default: Option<String>,
+    is_identity: bool,
+    generation_expression: Option<String>,
+}
+
+#[derive(FromRow)]
+struct EnumRow {
+    type_schema: String,
+    type_name: String,
+    variant: String,
+}
+
+#[derive(FromRow)]
+struct ConstraintRow {
+    table_schema: String,
+    table_name: String,
+    name: String,
+    /// `pg_constraint.contype`, e.g. `p` for a primary key.
+    kind: String,
+    columns: Vec<String>,
+    ref_schema: Option<String>,
+    ref_table: Option<String>,
+    ref_columns: Vec<String>,
+    on_delete: String,
+    on_update: String,
+    match_type: String,
+    check_expression: Option<String>,
+}
+
+#[derive(FromRow)]
+struct IndexRow {
+    table_schema: String,
+    table_name: String,
+    name: String,
+    is_unique: bool,
+    method: String,
+    columns: Vec<String>,
+    /// Expression indexes, and indexes with `INCLUDE` columns.
+    is_unsupported: bool,
+    predicate: Option<String>,
+}
+
+/// The raw catalog rows, before they're mapped to table definitions.
+struct Catalog {
+    tables: Vec<TableRow>,
+    columns: Vec<ColumnRow>,
+    enums: Vec<EnumRow>,
+    constraints: Vec<ConstraintRow>,
+    indexes: Vec<IndexRow>,
+}
+
+/// A table being built - columns are kept mutable until the column constraints have been applied.
+struct PartialTable {
+    table: DatabaseTableDefinition,
+    columns: Vec<TableColumnData>,
+}
+
+fn qualified(
+    schema: &str,
+    table_name: &str,
+) -> String {
+    match schema {
+        "public" => table_name.to_string(),
+        schema => format!("{schema}.{table_name}"),
+    }
+}
+
+impl Catalog {
+    fn into_tables(self) -> (Vec<DatabaseTableDefinition>, Vec<UnmappedItem>) {
+        let mut unmapped = Vec::new();
+        let mut report = |table: &str, item: String, reason: &str| {
+            unmapped.push(UnmappedItem {
+                table: table.to_string(),
+                item,
+                reason: reason.to_string(),
+            })
+        };
+
+        // Keyed on (schema, name), as each schema can have its own type of the same name. Types outside the default
+        // schema are named with theirs. Names that aren't valid identifiers are reported by the columns using them.
+        let mut enums: HashMap<(String, String), Result<EnumType, String>> = HashMap::new();
+        for EnumRow {
+            type_schema,
+            type_name,
+            variant,
+        } in self.enums
+        {
+            let enum_type =
+                enums.entry((type_schema.clone(), type_name.clone())).or_insert_with(|| {
+                    Identifier::new(qualified(&type_schema, &type_name))
+                        .map(|name| EnumType::new(name, Vec::new()))
+                });
+            if let Ok(enum_type) = enum_type {
+                enum_type.variants.push(variant);
+            }
+        }
+
+        // Keyed on the qualified name, in the order the tables were read.
+        let mut order = Vec::new();
+        let mut tables: HashMap<String, PartialTable> = HashMap::new();
+        for row in self.tables {
+            let name = qualified(&row.table_schema, &row.table_name);
+            let mut table = match DatabaseTableDefinition::new(&row.table_name) {
+                Ok(table) => table,
+                Err(e) => {
+                    report(&name, format!("table {}", row.table_name), &e);
+                    continue;
+                },
+            };
+            if row.table_schema != "public" {
+                table = match table.schema(&row.table_schema) {
+                    Ok(table) => table,
+                    Err(e) => {
+                        report(&name, format!("table {}", row.table_name), &e);
+                        continue;
+                    },
+                };
+            }
+            order.push(name.clone());
+            tables.insert(
+                name,
+                PartialTable {
+                    table,
+                    columns: Vec::new(),
+                },
+            );
+        }
+
+        for row in self.columns {
+            let name = qualified(&row.table_schema, &row.table_name);
+            let Some(partial) = tables.get_mut(&name) else {
+                continue; // Views, and tables that couldn't be mapped.
+            };
+            let item = format!("column {}", row.column_name);
+            if let Some(Err(e)) = enums.get(&(row.udt_schema.clone(), row.udt_name.clone())) {
+                report(&name, item, e);
+                continue;
+            }
+            let Some(column_type) = map_column_type(&row, &enums) else {
+                report(&name, item, &format!("unsupported type `{}`", row.udt_name));
+                continue;
+            };
+            if let Some(reason) = approximate_mapping(&row) {
+                report(&name, item.clone(), &reason);
+            }
+            let mut column = match TableColumn::new(&row.column_name, column_type, Vec::new()) {
+                Ok(column) => column,
+                Err(e) => {
+                    report(&name, item, &e);
+                    continue;
+                },
+            };
+            if !row.is_nullable {
+                column = column.non_null();
+            }
+            if row.is_identity {
+                column = column.identity();
+            }
+            match (row.generation_expression, row.column_default) {
+                (Some(expression), _) => column = column.generated(&expression),
+                (None, Some(expression)) => column = column.default(&expression),
+                (None, None) => {},
+            }
+            partial.columns.push(column);
+        }
+
+        // Single-column keys and constraints go on the column, the way the derive macros build them.
+        let mut table_constraints = Vec::new();
+        for row in self.constraints {
+            let name = qualified(&row.table_schema, &row.table_name);
+            let Some(partial) = tables.get_mut(&name) else {
+                continue;
+            };
+            let item = format!("constraint {}", row.name);
+            if !row
+                .columns
+                .iter()
+                .all(|column| partial.columns.iter().any(|c| *c.column_name == *column))
+            {
+                report(&name, item, "references a column that couldn't be mapped");
+                continue;
+            }
+            let column = match row.columns.as_slice() {
+                [column] => partial.columns.iter_mut().find(|c| *c.column_name == *column),
+                _ => None,
+            };
+            match (row.kind.as_str(), column) {
+                ("p", Some(column)) => *column = column.clone().pk(),
+                ("u", Some(column))
+                    if row.name == format!("{}_{}_key", row.table_name, column.column_name) =>
+                {
+                    *column = column.clone().unique()
+                },
+                ("f", Some(column)) => {
+                    let Some(fk) =
+                        map_foreign_key(&row, &mut |reason| report(&name, item.clone(), reason))
+                    else {
+                        continue;
+                    };
+                    *column = column.clone().foreign_key(ReferencesConstraint {
+                        ref_table: fk.ref_table,
+                        ref_column: fk.ref_columns.into_iter().next(),
+                        match_type: fk.match_type,
+                        on_delete_action: fk.on_delete_action,
+                        on_update_action: fk.on_update_action,
+                    });
+                },
+                ("c", Some(column))
+                    if row.name == format!("{}_{}_check", row.table_name, column.column_name) =>
+                {
+                    column.constraints.push(TableColumnConstraint::new(
+                        TableColumnConstraintDetail::Check(CheckExpressionConstraint {
+                            expression: row.check_expression.clone().unwrap_or_default(),
+                        }),
+                    ));
+                },
+                ("p" | "u" | "f" | "c", _) => table_constraints.push((name, row)),
+                // `NOT NULL` constraints (Postgres 18+) are already covered by the column's nullability.
+                ("n", _) => {},
+                (_, _) => report(
+                    &name,
+                    item,
+                    "exclusion constraints and constraint triggers aren't supported",
+                ),
+            }
+        }
+
+        let mut tables: HashMap<String, DatabaseTableDefinition> = tables
+            .into_iter()
+            .map(|(name, partial)| {
+                let table = partial
+                    .columns
+                    .into_iter()
+                    .fold(partial.table, |table, column| table.column(column));
+                (name, table)
+            })
+            .collect();
+
+        for (name, row) in table_constraints {
+            let table = tables.get_mut(&name).expect("Constraints are only kept for known tables");
+            let item = format!("constraint {}", row.name);
+            // Only constraints on mapped columns are kept, so they're all there.
+            let columns = row
+                .columns
+                .iter()
+                .filter_map(|column| {
+                    table.columns.values().find(|c| *c.column_name == *column).cloned()
+                })
+                .collect::<Vec<_>>();
+            let constraint_name = match Identifier::new(&row.name) {
+                Ok(constraint_name) => constraint_name,
+                Err(e) => {
+                    report(&name, item, &e);
+                    continue;
+                },
+            };
+            let constraint = match row.kind.as_str() {
+                "p" => TableConstraint::primary_key(columns),
+                "u" => TableConstraint::unique(constraint_name, columns),
+                "c" => TableConstraint {
+                    name: Some(constraint_name),
+                    detail: Arc::new(TableConstraintDetail::Check(CheckExpressionConstraint {
+                        expression: row.check_expression.clone().unwrap_or_default(),
+                    })),
+                },
+                _ => {
+                    let Some(mut fk) =
+                        map_foreign_key(&row, &mut |reason| report(&name, item.clone(), reason))
+                    else {
+                        continue;
+                    };
+                    fk.columns = columns;
+                    TableConstraint {
+                        name: Some(constraint_name),
+                        detail: Arc::new(TableConstraintDetail::ForeignKey(fk)),
+                    }
+                },
+            };
+            table.constraints.push(constraint);
+        }
+
+        for row in self.indexes {
+            let name = qualified(&row.table_schema, &row.table_name);
+            let Some(table) = tables.get_mut(&name) else {
+                continue;
+            };
+            let item = format!("index {}", row.name);
+            if row.is_unsupported {
+                report(&name, item, "expression indexes and INCLUDE columns aren't supported");
+                continue;
+            }
+            let method = match IndexMethod::try_from(row.method.as_str()) {
+                Ok(method) => method,
+                Err(e) => {
+                    report(&name, item, &e);
+                    continue;
+                },
+            };
+            let index_name = match Identifier::new(&row.name) {
+                Ok(index_name) => index_name,
+                Err(e) => {
+                    report(&name, item, &e);
+                    continue;
+                },
+            };
+            let columns = row.columns.iter().map(Identifier::new).collect::<Result<Vec<_>, _>>();
+            let Some(columns) = columns
+                .ok()
+                .filter(|columns| columns.iter().all(|c| table.columns.contains_key(c)))
+            else {
+                report(&name, item, "references a column that couldn't be mapped");
+                continue;
+            };
+            table.indexes.push(TableIndex {
+                name: index_name,
+                columns,
+                is_unique: row.is_unique,
+                method,
+                predicate: row.predicate,
+            });
+        }
+
+        let tables = order.into_iter().filter_map(|name| tables.remove(&name)).collect();
+        (tables, unmapped)
+    }
+}
+
+/// Maps a column's type, from `information_schema.columns`. `None` if there's no equivalent.
+fn map_column_type(
+    row: &ColumnRow,
+    enums: &HashMap<(String, String), Result<EnumType, String>>,
+) -> Option<DatabaseColumnType> {
+    type E = DatabaseColumnType;
+    let scalar = |udt_name: &str| match udt_name {
+        "bool" => Some(E::Boolean),
+        "int2" | "int4" => Some(E::Int),
+        "int8" => Some(E::BigInt),
+        "float4" | "float8" => Some(E::Float),
+        "varchar" | "text" | "bpchar" => Some(E::String),
+        "timestamp" => Some(E::Timestamp),
+        "timestamptz" => Some(E::TimestampTz),
+        "uuid" => Some(E::Uuid),
+        "json" | "jsonb" => Some(E::Json),
+        "numeric" => Some(E::Numeric),
+        "date" => Some(E::Date),
+        "time" => Some(E::Time),
+        "bytea" => Some(E::Bytea),
+        "interval" => Some(E::Interval),
+        _ => None,
+    };
+    match row.data_type.as_str() {
+        // Array types are named after their element type, prefixed with `_`.
+        "ARRAY" => {
+            let element_type = scalar(row.udt_name.strip_prefix('_')?)?;
+            match element_type {
+                E::Json | E::Bytea | E::Interval => None,
+                element_type => Some(E::Array(Box::new(element_type))),
+            }
+        },
+        "USER-DEFINED" => {
+            let enum_type = enums.get(&(row.udt_schema.clone(), row.udt_name.clone()))?;
+            enum_type.clone().ok().map(E::Enum)
+        },
+        _ => scalar(&row.udt_name),
+    }
+}
+
+/// Why a mapped column's type is only close to the database's, if it is - e.g. a `SMALLINT` read as `Int`. A
+/// migration from the definition would change the column to the mapped type.
+fn approximate_mapping(row: &ColumnRow) -> Option<String> {
+    let type_name = match row.data_type.as_str() {
+        "ARRAY" => row.udt_name.strip_prefix('_').unwrap_or(&row.udt_name),
+        _ => &row.udt_name,
+    };
+    match (type_name, row.max_length, row.numeric_precision) {
+        ("int2", _, _) => Some("`int2` is read as INT".to_string()),
+        ("float4", _, _) => Some("`float4` is read as FLOAT".to_string()),
+        ("json", _, _) => Some("`json` is read as JSONB".to_string()),
+        ("varchar" | "bpchar", Some(length), _) => {
+            Some(format!("the length of `{type_name}({length})` isn't kept"))
+        },
+        ("numeric", _, Some(precision)) => Some(format!(
+            "the precision of `numeric({precision}, {})` isn't kept",
+            row.numeric_scale.unwrap_or_default()
+        )),
+        _ => None,
+    }
+}
+
+/// Maps a `pg_constraint` foreign key, without its columns. `report` is called for any part that can't be mapped.
+fn map_foreign_key(
+    row: &ConstraintRow,
+    report: &mut impl FnMut(&str),
+) -> Option<ForeignKeyConstraint> {
+    let (Some(ref_schema), Some(ref_table)) = (&row.ref_schema, &row.ref_table) else {
+        report("the referenced table couldn't be found");
+        return None;
+    };
+    let identifiers =
+        |names: &[String]| names.iter().map(Identifier::new).collect::<Result<Vec<_>, _>>();
+    let (ref_table, ref_columns, columns) = match (
+        Identifier::new(qualified(ref_schema, ref_table)),
+        identifiers(&row.ref_columns),
+        identifiers(&row.columns),
+    ) {
+        (Ok(ref_table), Ok(ref_columns), Ok(columns)) => (ref_table, ref_columns, columns),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            report(&e);
+            return None;
+        },
+    };
+    let action = |action: &str, report: &mut dyn FnMut(&str)| match action {
+        "r" => Some(ReferentialAction::Restrict),
+        "c" => Some(ReferentialAction::Cascade),
+        // Postgres only accepts column lists for `ON DELETE`, where these are the foreign key's own columns.
+        "n" => Some(ReferentialAction::SetNull(columns.clone())),
+        "d" => Some(ReferentialAction::SetDefault(columns.clone())),
+        // `NO ACTION`, the default.
+        _ => {
+            if action != "a" {
+                report("unknown referential action");
+            }
+            None
+        },
+    };
+    let on_delete_action = action(&row.on_delete, report);
+    let on_update_action = match row.on_update.as_str() {
+        "n" | "d" => {
+            report("ON UPDATE SET NULL / SET DEFAULT aren't supported");
+            None
+        },
+        on_update => action(on_update, report),
+    };
+    Some(ForeignKeyConstraint {
+        ref_table,
+        ref_columns,
+        columns: Vec::new(),
+        match_type: match row.match_type.as_str() {
+            "f" => Some(ReferencesConstraintMatchType::Full),
+            "p" => Some(ReferencesConstraintMatchType::Partial),
+            _ => None,
+        },
+        on_delete_action,
+        on_update_action,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        data_definition::table::{DatabaseColumnType, EnumType, Identifier},
+        migration::CreateTable,
+        AsSql,
+    };
+
+    use super::{Catalog, ColumnRow, ConstraintRow, EnumRow, IndexRow, TableRow, UnmappedItem};
+
+    fn column(
+        table_name: &str,
+        column_name: &str,
+        udt_name: &str,
+        is_nullable: bool,
+    ) -> ColumnRow {
+        ColumnRow {
+            table_schema: "billing".to_string(),
+            table_name: table_name.to_string(),
+            column_name: column_name.to_string(),
+            data_type: udt_name.to_string(),
+            udt_schema: "pg_catalog".to_string(),
+            udt_name: udt_name.to_string(),
+            max_length: None,
+            numeric_precision: None,
+            numeric_scale: None,
+            is_nullable,
+            column_default: None,
+            is_identity: false,
+            generation_expression: None,
+        }
+    }
+
+    fn constraint(
+        name: &str,
+        kind: &str,
+        columns: &[&str],
+    ) -> ConstraintRow {
+        ConstraintRow {
+            table_schema: "billing".to_string(),
+            table_name: "invoice".to_string(),
+            name: name.to_string(),
+            kind: kind.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            ref_schema: None,
+            ref_table: None,
+            ref_columns: Vec::new(),
+            on_delete: "a".to_string(),
+            on_update: "a".to_string(),
+            match_type: "s".to_string(),
+            check_expression: None,
+        }
+    }
+
+    #[test]
+    fn catalog_rows_map_to_tables_and_report_the_rest() {
+        let mut account_fk = constraint("invoice_account_id_fkey", "f", &["account_id"]);
+        account_fk.ref_schema = Some("public".to_string());
+        account_fk.ref_table = Some("account".to_string());
+        account_fk.ref_columns = vec!["id".to_string()];
+        account_fk.on_delete = "c".to_string();
+        let mut total = column("invoice", "total", "numeric", false);
+        total.column_default = Some("0".to_string());
+        let mut number = column("invoice", "number", "int8", false);
+        number.is_identity = true;
+
+        let catalog = Catalog {
+            tables: vec![TableRow {
+                table_schema: "billing".to_string(),
+                table_name: "invoice".to_string(),
+            }],
+            columns: vec![
+                column("invoice", "id", "uuid", false),
+                column("invoice", "account_id", "uuid", false),
+                number,
+                total,
+                column("invoice", "location", "point", true),
+            ],
+            enums: Vec::new(),
+            constraints: vec![
+                constraint("invoice_pkey", "p", &["id"]),
+                account_fk,
+                constraint("invoice_account_number_key", "u", &["account_id", "number"]),
+            ],
+            indexes: vec![
+                IndexRow {
+                    table_schema: "billing".to_string(),
+                    table_name: "invoice".to_string(),
+                    name: "invoice_total_idx".to_string(),
+                    is_unique: false,
+                    method: "btree".to_string(),
+                    columns: vec!["total".to_string()],
+                    is_unsupported: false,
+                    predicate: None,
+                },
+                IndexRow {
+                    table_schema: "billing".to_string(),
+                    table_name: "invoice".to_string(),
+                    name: "invoice_lower_number_idx".to_string(),
+                    is_unique: false,
+                    method: "btree".to_string(),
+                    columns: Vec::new(),
+                    is_unsupported: true,
+                    predicate: None,
+                },
+            ],
+        };
+
+        let (tables, unmapped) = catalog.into_tables();
+
+        let [invoice] = tables.as_slice() else {
+            panic!("Expected exactly one table, got {}", tables.len());
+        };
+        assert_eq!(invoice.qualified_name().to_string(), "billing.invoice");
+        assert_eq!(
+            CreateTable::new(Arc::new(invoice.clone())).as_sql(),
+            "CREATE TABLE IF NOT EXISTS billing.invoice (\
+             account_id UUID NOT NULL REFERENCES account (id) ON DELETE CASCADE,\
+             id UUID NOT NULL PRIMARY KEY ,\
+             number BIGINT NOT NULL GENERATED BY DEFAULT AS IDENTITY,\
+             total NUMERIC NOT NULL DEFAULT 0, \
+             CONSTRAINT invoice_account_number_key UNIQUE (account_id, number));"
+        );
+        assert_eq!(invoice.indexes.len(), 1);
+        assert_eq!(
+            unmapped,
+            vec![
+                UnmappedItem {
+                    table: "billing.invoice".to_string(),
+                    item: "column location".to_string(),
+                    reason: "unsupported type `point`".to_string(),
+                },
+                UnmappedItem {
+                    table: "billing.invoice".to_string(),
+                    item: "index invoice_lower_number_idx".to_string(),
+                    reason: "expression indexes and INCLUDE columns aren't supported".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn enums_are_read_per_schema_and_bad_names_are_reported() {
+        let enum_row = |type_schema: &str, type_name: &str, variant: &str| EnumRow {
+            type_schema: type_schema.to_string(),
+            type_name: type_name.to_string(),
+            variant: variant.to_string(),
+        };
+        let mut status = column("invoice", "status", "status", false);
+        status.data_type = "USER-DEFINED".to_string();
+        status.udt_schema = "billing".to_string();
+        let mut code = column("invoice", "code", "varchar", false);
+        code.max_length = Some(20);
+
+        let catalog = Catalog {
+            tables: vec![TableRow {
+                table_schema: "billing".to_string(),
+                table_name: "invoice".to_string(),
+            }],
+            columns: vec![column("invoice", "id", "uuid", false), status, code],
+            enums: vec![
+                enum_row("public", "status", "Active"),
+                enum_row("billing", "status", "Draft"),
+                enum_row("billing", "status", "Paid"),
+            ],
+            constraints: vec![constraint("invoice-pkey", "p", &["id", "code"])],
+            indexes: Vec::new(),
+        };
+
+        let (tables, unmapped) = catalog.into_tables();
+
+        assert_eq!(
+            CreateTable::new(Arc::new(tables[0].clone())).as_sql(),
+            "CREATE TABLE IF NOT EXISTS billing.invoice (\
+             code VARCHAR NOT NULL,\
+             id UUID NOT NULL,\
+             status billing.status NOT NULL);"
+        );
+        let status = &tables[0].columns[&Identifier::new_unchecked("status")];
+        assert_eq!(
+            status.column_type,
+            DatabaseColumnType::Enum(EnumType::new(
+                Identifier::new_unchecked("billing.status"),
+                vec!["Draft".to_string(), "Paid".to_string()],
+            ))
+        );
+        assert_eq!(
+            unmapped,
+            vec![
+                UnmappedItem {
+                    table: "billing.invoice".to_string(),
+                    item: "column code".to_string(),
+                    reason: "the length of `varchar(20)` isn't kept".to_string(),
+                },
+                UnmappedItem {
+                    table: "billing.invoice".to_string(),
+                    item: "constraint invoice-pkey".to_string(),
+                    reason: "Identifier invoice-pkey contains invalid characters. [a-zA-Z0-9_.] are only allowed values.".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -1,5 +1,8 @@
-// pub mod database_definition;
+pub mod database_definition;
 pub mod exp_data_system;
+pub mod introspection;
+pub mod snapshot;
+pub mod validation;
 pub mod table;
 
 #[cfg(test)]
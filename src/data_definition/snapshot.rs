@@ -0,0 +1,251 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{
+    database_definition::{DatabaseDefinition, DatabaseDefinitionBuilder},
+    table::{DatabaseTableDefinition, Identifier, TableKey},
+};
+
+/// The version of the snapshot format written by `DatabaseDefinition::to_json()`. Older versions are upgraded on read.
+pub const SNAPSHOT_FORMAT_VERSION: u64 = 1;
+
+/// Upgrades a snapshot from format version `N` to `N + 1`, indexed by `N`.
+const UPGRADES: [fn(Value) -> Value; SNAPSHOT_FORMAT_VERSION as usize] = [upgrade_from_v0];
+
+/// The on-disk representation of a `DatabaseDefinition`.
+///
+/// Child tables (e.g. join tables) are written alongside their parents, and tables are sorted by their qualified
+/// name, so that the same definition always serializes to the same output and snapshots diff cleanly in git.
+#[derive(Serialize, Deserialize)]
+struct DatabaseSnapshot {
+    format_version: u64,
+    name: Identifier,
+    tables: Vec<SnapshotTable>,
+}
+
+/// A table in a snapshot, with its child tables written as links to the tables they name.
+#[derive(Serialize, Deserialize)]
+struct SnapshotTable {
+    #[serde(flatten)]
+    table: DatabaseTableDefinition,
+    /// The qualified names of the table's child tables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    child_tables: Vec<Identifier>,
+    /// Set for tables that are only child tables, rather than top-level tables of the definition.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    child_only: bool,
+}
+
+impl DatabaseDefinition {
+    /// Serializes the definition as a versioned, pretty-printed JSON snapshot.
+    ///
+    /// Child tables are written once each, and linked to their parents by name, so `from_json(to_json())` gives back
+    /// an equal definition.
+    pub fn to_json(&self) -> Result<String, crate::Error> {
+        let mut tables: Vec<SnapshotTable> = Vec::new();
+        let mut stack = self.tables.iter().map(|table| (table.clone(), false)).collect::<Vec<_>>();
+        while let Some((mut table, child_only)) = stack.pop() {
+            let mut children =
+                table.child_tables.drain().map(|(_, child)| *child).collect::<Vec<_>>();
+            children.sort_by_key(|child| child.qualified_name());
+            let child_tables = children.iter().map(|child| child.qualified_name()).collect();
+            stack.extend(children.into_iter().map(|child| (child, true)));
+            match tables.iter_mut().find(|t| t.table.qualified_name() == table.qualified_name()) {
+                Some(existing) => existing.child_only &= child_only,
+                None => tables.push(SnapshotTable {
+                    table,
+                    child_tables,
+                    child_only,
+                }),
+            }
+        }
+        tables.sort_by_key(|table| table.table.qualified_name());
+
+        let snapshot = DatabaseSnapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            name: self.name.clone(),
+            tables,
+        };
+        let mut json = serde_json::to_string_pretty(&snapshot)
+            .map_err(|e| crate::Error::Serialize(e.to_string()))?;
+        json.push('\n');
+        Ok(json)
+    }
+
+    /// Reads a snapshot written by `to_json()`, upgrading it from older format versions if needed.
+    pub fn from_json(json: &str) -> Result<Self, crate::Error> {
+        let mut value: Value =
+            serde_json::from_str(json).map_err(|e| crate::Error::Deserialize {
+                column: String::new(),
+                message: e.to_string(),
+            })?;
+
+        let mut version = match &value {
+            // Version 0 is the bare list of tables that `DataSystem` used to write.
+            Value::Array(_) => 0,
+            value => value.get("format_version").and_then(Value::as_u64).ok_or_else(|| {
+                crate::Error::Deserialize {
+                    column: "format_version".to_string(),
+                    message: "missing snapshot format version".to_string(),
+                }
+            })?,
+        };
+        if version > SNAPSHOT_FORMAT_VERSION {
+            return Err(crate::Error::Unsupported(format!(
+                "Snapshot format version {version} is newer than the latest supported version ({SNAPSHOT_FORMAT_VERSION})"
+            )));
+        }
+        while version < SNAPSHOT_FORMAT_VERSION {
+            value = UPGRADES[version as usize](value);
+            version += 1;
+        }
+
+        let snapshot: DatabaseSnapshot = serde_path_to_error::deserialize(value)?;
+        let by_name = snapshot
+            .tables
+            .iter()
+            .map(|table| (table.table.qualified_name(), table))
+            .collect::<HashMap<_, _>>();
+        let tables = snapshot
+            .tables
+            .iter()
+            .filter(|table| !table.child_only)
+            .map(|table| {
+                link_child_tables(&table.table.qualified_name(), &by_name, &mut Vec::new())
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(DatabaseDefinitionBuilder {
+            name: snapshot.name,
+            tables,
+        }
+        .into())
+    }
+
+    /// Writes the definition to `path` as a JSON snapshot (see `to_json()`), creating any missing parent directories.
+    pub fn to_file(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), crate::Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Reads a JSON snapshot written by `to_file()`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, crate::Error> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Rebuilds the table named `name`, with the child tables it links to. `path` holds the tables being rebuilt, so a table
+/// that's (indirectly) its own child isn't linked again.
+fn link_child_tables(
+    name: &Identifier,
+    tables: &HashMap<Identifier, &SnapshotTable>,
+    path: &mut Vec<Identifier>,
+) -> Result<DatabaseTableDefinition, crate::Error> {
+    let snapshot_table = tables[name];
+    let mut table = snapshot_table.table.clone();
+    path.push(name.clone());
+    for child in &snapshot_table.child_tables {
+        if path.contains(child) {
+            continue;
+        }
+        if !tables.contains_key(child) {
+            return Err(crate::Error::Deserialize {
+                column: "child_tables".to_string(),
+                message: format!(
+                    "{name} links to a child table that isn't in the snapshot: {child}"
+                ),
+            });
+        }
+        let child_table = link_child_tables(child, tables, path)?;
+        table.child_tables.insert(TableKey::Table(child.clone()), Box::new(child_table));
+    }
+    path.pop();
+    Ok(table)
+}
+
+fn upgrade_from_v0(tables: Value) -> Value {
+    json!({
+        "format_version": 1,
+        "name": "database",
+        "tables": tables,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_definition::{
+        database_definition::DatabaseDefinition,
+        table::{DatabaseTableDefinition, TableColumn, TableKey},
+    };
+
+    fn definition() -> DatabaseDefinition {
+        let mut invoice = DatabaseTableDefinition::new("invoice")
+            .unwrap()
+            .schema("billing")
+            .unwrap()
+            .column(TableColumn::new_uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::new_int("total").unwrap().non_null().default("0"));
+        let line_item = DatabaseTableDefinition::new("line_item")
+            .unwrap()
+            .column(TableColumn::new_uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::new_string("description").unwrap());
+        invoice.child_tables.insert(TableKey::of::<()>(), Box::new(line_item));
+        DatabaseDefinition::new_unchecked("shop").table(invoice).into()
+    }
+
+    #[test]
+    fn snapshots_round_trip_with_child_tables() {
+        let json = definition().to_json().unwrap();
+        let read_back = DatabaseDefinition::from_json(&json).unwrap();
+
+        assert_eq!(read_back, definition());
+        assert_eq!(read_back.tables.len(), 1);
+        assert_eq!(read_back.tables[0].child_tables.len(), 1);
+        assert_eq!(read_back.to_json().unwrap(), json);
+        assert!(json.contains("\"format_version\": 1"));
+    }
+
+    #[test]
+    fn snapshots_without_child_links_read_every_table_as_top_level() {
+        // Snapshots written before child tables were linked list each table on its own.
+        let unlinked = definition()
+            .to_json()
+            .unwrap()
+            .replace(",\n      \"child_tables\": [\n        \"line_item\"\n      ]", "")
+            .replace(",\n      \"child_only\": true", "");
+        let read_back = DatabaseDefinition::from_json(&unlinked).unwrap();
+
+        let table_names = read_back
+            .tables
+            .iter()
+            .map(|t| t.qualified_name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(table_names, vec!["billing.invoice", "line_item"]);
+    }
+
+    #[test]
+    fn older_snapshots_are_upgraded_and_newer_ones_rejected() {
+        // Version 0: a bare list of tables, with identifiers written as `{ "value": ... }`.
+        let v0 = r#"[{
+            "table_name": {"value": "line_item"},
+            "columns": {},
+            "constraints": []
+        }]"#;
+        let upgraded = DatabaseDefinition::from_json(v0).unwrap();
+        assert_eq!(upgraded.tables[0].table_name.as_str(), "line_item");
+
+        let newer = definition()
+            .to_json()
+            .unwrap()
+            .replace("\"format_version\": 1", "\"format_version\": 99");
+        assert!(matches!(DatabaseDefinition::from_json(&newer), Err(crate::Error::Unsupported(_))));
+    }
+}
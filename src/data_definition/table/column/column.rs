@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::Arc;
@@ -6,11 +7,14 @@ use serde::{Deserialize, Serialize};
 
 use crate::data_manager::GetTableDefinition;
 use crate::queries::Insertable;
+use crate::queries::{ArrayParam, Filter};
 use crate::BuildSql;
 
 use crate::data_definition::table::Identifier;
 
-use super::{TableColumnConstraint, TableColumnConstraintDetail};
+use super::{
+    DbEnum, EnumType, ReferencesConstraint, TableColumnConstraint, TableColumnConstraintDetail,
+};
 
 #[allow(unused)]
 trait ForeignKeyObject
@@ -23,57 +27,131 @@ pub(crate) type ObjectRepr = HashMap<Identifier, ColumnValue>;
 
 #[derive(Clone)]
 pub enum ColumnValue {
-    Boolean(bool),                    // BOOL or BOOLEAN
-    Int(i64),                         // INT
-    Float(f64),                       // FLOAT
-    String(String),                   // VARCHAR or TEXT
-    Timestamp(chrono::NaiveDateTime), // TIMESTAMP
-    Uuid(uuid::Uuid),                 // UUID
-    Json(String),                     // JSONB
+    Boolean(bool),                              // BOOL or BOOLEAN
+    Int(i64),                                   // INT or BIGINT
+    Float(f64),                                 // FLOAT
+    String(String),                             // VARCHAR or TEXT
+    Timestamp(chrono::NaiveDateTime),           // TIMESTAMP
+    Uuid(uuid::Uuid),                           // UUID
+    Json(String),                               // JSONB
+    Numeric(rust_decimal::Decimal),             // NUMERIC
+    Date(chrono::NaiveDate),                    // DATE
+    Time(chrono::NaiveTime),                    // TIME
+    TimestampTz(chrono::DateTime<chrono::Utc>), // TIMESTAMPTZ
+    Bytea(Vec<u8>),                             // BYTEA
+    Interval(std::time::Duration),              // INTERVAL
+    Array(ArrayParam),                          // e.g. INT[]
+    /// A `DbEnum` variant, cast to its Postgres type when bound.
+    Enum {
+        type_name: Identifier,
+        variant: &'static str,
+    },
+    /// The children reference the parent's key in their `parent_id` column.
     OneToMany {
         child_table: Identifier,
+        /// The schema of `child_table`, if it isn't in the default schema.
+        schema: Option<Identifier>,
+        /// The key columns of `child_table`.
+        primary_key: Vec<Identifier>,
         values: Vec<Box<ObjectRepr>>,
     },
     OneToOne {
         child_table: Identifier,
+        /// The schema of `child_table`, if it isn't in the default schema.
+        schema: Option<Identifier>,
+        /// The key columns of `child_table`, that the parent references the child by.
+        primary_key: Vec<Identifier>,
         value: Box<ObjectRepr>,
     },
+    /// The children are upserted, and linked to the parent through the join table.
+    /// The join table is in the parent's schema.
+    ManyToMany {
+        child_table: Identifier,
+        /// The schema of `child_table`, if it isn't in the default schema.
+        schema: Option<Identifier>,
+        /// The key columns of `child_table`, that the join table references the children by.
+        primary_key: Vec<Identifier>,
+        values: Vec<Box<ObjectRepr>>,
+    },
+}
+
+impl ColumnValue {
+    pub fn from_enum<T: DbEnum>(value: &T) -> Self {
+        ColumnValue::Enum {
+            type_name: T::enum_type().name,
+            variant: value.as_variant(),
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Hash)]
 pub enum DatabaseColumnType {
-    Boolean,   // BOOL or BOOLEAN
-    Int,       // INT
-    Float,     // FLOAT
-    String,    // VARCHAR or TEXT
-    Timestamp, // TIMESTAMP
-    Uuid,      // UUID
-    Json,      // JSONB
+    Boolean,     // BOOL or BOOLEAN
+    Int,         // INT
+    BigInt,      // BIGINT
+    Float,       // FLOAT
+    String,      // VARCHAR or TEXT
+    Timestamp,   // TIMESTAMP
+    Uuid,        // UUID
+    Json,        // JSONB
+    Numeric,     // NUMERIC
+    Date,        // DATE
+    Time,        // TIME
+    TimestampTz, // TIMESTAMPTZ
+    Bytea,       // BYTEA
+    /// `INTERVAL`, read as a `std::time::Duration`. Months don't have a fixed length, so they're read as 30 days
+    /// (as Postgres' `extract(epoch ...)` does), and negative intervals can't be read at all.
+    Interval,
+    /// An array of a scalar type, e.g. `BIGINT[]` for a `Vec<i64>`.
+    Array(Box<DatabaseColumnType>),
+    /// A Postgres `ENUM` type - see `DbEnum`.
+    Enum(EnumType),
 
     // These next few that define relationship types are a hacky way of building cross-table relationships -
     // I'm dealing with a consequence of deciding that tables would be locked once they were fully built, but this will require that I re-do them down the line.
     OneToMany(Identifier), // TODO: Impl this all the way through
     // ManyToOne(DatabaseTableDefinition),
-    ManyToMany(Identifier), // TODO: Will need to figure out how I want to represent JoinTables here
+    /// Stored in a `{parent}_{field}_to_{child}` join table - see `DatabaseTableDefinition::join_table()`.
+    ManyToMany(Identifier),
     OneToOne(Identifier), // TODO: With [inline] macro attribute, can make this inline JSON when needed. Depeneds on if we want sortability / searchability or not
+    /// A lazily loaded `Related<T>` field. Stored as the related object's key, and never joined - see `Related::load()`.
+    Related(Identifier),
 }
 
 impl DatabaseColumnType {
-    pub fn as_str(&self) -> &str {
-        match self {
+    pub fn as_str(&self) -> Cow<'_, str> {
+        let sql_type = match self {
             DatabaseColumnType::Boolean => "BOOL",
             DatabaseColumnType::Int => "INT",
+            DatabaseColumnType::BigInt => "BIGINT",
             DatabaseColumnType::Float => "FLOAT",
             DatabaseColumnType::String => "VARCHAR",
             DatabaseColumnType::Timestamp => "TIMESTAMP",
             DatabaseColumnType::Uuid => "UUID",
             DatabaseColumnType::Json => "JSONB",
-            DatabaseColumnType::OneToMany(_) => todo!(),
-            DatabaseColumnType::ManyToMany {
-                ..
-            } => todo!(),
-            DatabaseColumnType::OneToOne(_) => "UUID", // TODO: These types of relationships only work with id: uuid. This is a big debt.
-        }
+            DatabaseColumnType::Numeric => "NUMERIC",
+            DatabaseColumnType::Date => "DATE",
+            DatabaseColumnType::Time => "TIME",
+            DatabaseColumnType::TimestampTz => "TIMESTAMPTZ",
+            DatabaseColumnType::Bytea => "BYTEA",
+            DatabaseColumnType::Interval => "INTERVAL",
+            DatabaseColumnType::Array(element_type) => {
+                return Cow::Owned(format!("{}[]", element_type.as_str()))
+            },
+            DatabaseColumnType::Enum(enum_type) => &enum_type.name,
+            DatabaseColumnType::OneToMany(_) | DatabaseColumnType::ManyToMany(_) => {
+                panic!("{self:?} is stored in another table, and has no column type of its own")
+            },
+            // The related table's key, if it isn't resolved yet - see `TableColumnData::stored_type()`.
+            DatabaseColumnType::OneToOne(_) | DatabaseColumnType::Related(_) => "UUID",
+        };
+        Cow::Borrowed(sql_type)
+    }
+
+    /// True for relationships that are stored entirely in another table (the child table, or a
+    /// join table), and so have no column on this one.
+    pub fn is_stored_in_other_table(&self) -> bool {
+        matches!(self, DatabaseColumnType::OneToMany(_) | DatabaseColumnType::ManyToMany(_))
     }
 }
 
@@ -113,6 +191,10 @@ pub struct TableColumnData {
     // pub compression_method: Optional<CompressionMethod>, // TODO: Adds `COMPRESSION compression_method` after `data_type`
     // pub collation: Optional<Collation>, // TODO: Adds `COMPRESSION compression_method` after `data_type`
     pub constraints: Vec<TableColumnConstraint>,
+    /// For relationships stored on this table (`OneToOne` / `Related`), the type of the related table's key.
+    /// Filled in by `DatabaseTableDefinition::resolve_relation_keys()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_type: Option<DatabaseColumnType>,
 }
 
 impl From<TableColumnData> for TableColumn {
@@ -134,6 +216,58 @@ impl TableColumnData {
             matches!(&*constraint.detail, TableColumnConstraintDetail::PrimaryKey(_))
         })
     }
+    /// The column's `DEFAULT` expression, if it has one.
+    pub fn default_expression(&self) -> Option<&str> {
+        self.constraints.iter().find_map(|constraint| match &*constraint.detail {
+            TableColumnConstraintDetail::Default(expression) => Some(expression.as_str()),
+            _ => None,
+        })
+    }
+    /// The column's `GENERATED ALWAYS AS` expression, if it's a generated column.
+    pub fn generated_expression(&self) -> Option<&str> {
+        self.constraints.iter().find_map(|constraint| match &*constraint.detail {
+            TableColumnConstraintDetail::Generated(expression) => Some(expression.as_str()),
+            _ => None,
+        })
+    }
+    /// The column's `CHECK` expressions, as SQL.
+    pub fn check_expressions(&self) -> Vec<&str> {
+        self.constraints
+            .iter()
+            .filter_map(|constraint| match &*constraint.detail {
+                TableColumnConstraintDetail::Check(check) => Some(check.expression.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+    pub fn is_unique(&self) -> bool {
+        self.constraints
+            .iter()
+            .any(|constraint| matches!(*constraint.detail, TableColumnConstraintDetail::Unique(_)))
+    }
+    /// Generated columns are computed by the database, and can't be inserted or updated.
+    pub fn is_generated(&self) -> bool {
+        self.generated_expression().is_some()
+    }
+    /// Identity columns are numbered by the database, and are left out of inserts.
+    pub fn is_identity(&self) -> bool {
+        self.constraints
+            .iter()
+            .any(|constraint| matches!(*constraint.detail, TableColumnConstraintDetail::Identity))
+    }
+    /// The type of the values actually stored in the column. For relationships stored on this table, that's
+    /// the related table's key - `Uuid` until it's resolved (see `key_type`).
+    pub fn stored_type(&self) -> DatabaseColumnType {
+        match (&self.column_type, &self.key_type) {
+            (DatabaseColumnType::OneToOne(_) | DatabaseColumnType::Related(_), Some(key_type)) => {
+                key_type.clone()
+            },
+            (DatabaseColumnType::OneToOne(_) | DatabaseColumnType::Related(_), None) => {
+                DatabaseColumnType::Uuid
+            },
+            (column_type, _) => column_type.clone(),
+        }
+    }
 }
 
 impl TableColumnData {
@@ -150,6 +284,44 @@ impl TableColumnData {
         self.primary_key()
     }
 
+    pub fn unique(mut self) -> Self {
+        self.constraints.push(TableColumnConstraint::unique());
+        self
+    }
+
+    /// Sets the column's `DEFAULT`. The expression is raw SQL, e.g. `now()` or `'pending'`.
+    pub fn default(
+        mut self,
+        expression: &str,
+    ) -> Self {
+        self.constraints.push(TableColumnConstraint::default_value(expression));
+        self
+    }
+
+    /// Adds a `CHECK` constraint, e.g. `filters.price.gte(0)`. Values in the filter are written inline.
+    pub fn check(
+        mut self,
+        filter: &Filter,
+    ) -> Self {
+        self.constraints.push(TableColumnConstraint::check(filter));
+        self
+    }
+
+    /// Makes this a `GENERATED ALWAYS AS (...) STORED` column. The expression is raw SQL, e.g. `price * quantity`.
+    pub fn generated(
+        mut self,
+        expression: &str,
+    ) -> Self {
+        self.constraints.push(TableColumnConstraint::generated(expression));
+        self
+    }
+
+    /// Makes this a `GENERATED BY DEFAULT AS IDENTITY` column, numbered by the database on insert.
+    pub fn identity(mut self) -> Self {
+        self.constraints.push(TableColumnConstraint::identity());
+        self
+    }
+
     pub fn fk_to(
         self,
         ref_table: Identifier,
@@ -166,6 +338,16 @@ impl TableColumnData {
         self.constraints.push(TableColumnConstraint::foreign_key(ref_table, ref_column));
         self
     }
+
+    /// Adds a fully specified `REFERENCES` constraint, e.g. with an `ON DELETE` action.
+    pub fn foreign_key(
+        mut self,
+        references: ReferencesConstraint,
+    ) -> Self {
+        self.constraints
+            .push(TableColumnConstraint::new(TableColumnConstraintDetail::References(references)));
+        self
+    }
 }
 
 impl TableColumn {
@@ -184,12 +366,16 @@ impl TableColumn {
             column_name: Identifier::new(column_name)?,
             column_type,
             constraints,
+            key_type: None,
         })
     }
 
     pub fn int(column_name: &str) -> Result<TableColumnData, String> {
         Self::new_int(column_name)
     }
+    pub fn bigint(column_name: &str) -> Result<TableColumnData, String> {
+        Self::new(column_name, DatabaseColumnType::BigInt, vec![])
+    }
     pub fn timestamp(column_name: &str) -> Result<TableColumnData, String> {
         Self::new_timestamp(column_name)
     }
@@ -205,12 +391,38 @@ impl TableColumn {
     pub fn bool(column_name: &str) -> Result<TableColumnData, String> {
         Self::new_bool(column_name)
     }
+    pub fn numeric(column_name: &str) -> Result<TableColumnData, String> {
+        Self::new(column_name, DatabaseColumnType::Numeric, vec![])
+    }
+    pub fn date(column_name: &str) -> Result<TableColumnData, String> {
+        Self::new(column_name, DatabaseColumnType::Date, vec![])
+    }
+    pub fn time(column_name: &str) -> Result<TableColumnData, String> {
+        Self::new(column_name, DatabaseColumnType::Time, vec![])
+    }
+    pub fn timestamptz(column_name: &str) -> Result<TableColumnData, String> {
+        Self::new(column_name, DatabaseColumnType::TimestampTz, vec![])
+    }
+    pub fn bytea(column_name: &str) -> Result<TableColumnData, String> {
+        Self::new(column_name, DatabaseColumnType::Bytea, vec![])
+    }
+    pub fn interval(column_name: &str) -> Result<TableColumnData, String> {
+        Self::new(column_name, DatabaseColumnType::Interval, vec![])
+    }
+    /// An array column, e.g. `TableColumn::array("tags", DatabaseColumnType::String)` for `VARCHAR[]`.
+    pub fn array(
+        column_name: &str,
+        element_type: DatabaseColumnType,
+    ) -> Result<TableColumnData, String> {
+        Self::new(column_name, DatabaseColumnType::Array(Box::new(element_type)), vec![])
+    }
 
     pub fn new_int(column_name: &str) -> Result<TableColumnData, String> {
         Ok(TableColumnData {
             column_name: Identifier::new(column_name)?,
             column_type: DatabaseColumnType::Int,
             constraints: vec![],
+            key_type: None,
         })
     }
 
@@ -219,6 +431,7 @@ impl TableColumn {
             column_name: Identifier::new(column_name)?,
             column_type: DatabaseColumnType::String,
             constraints: vec![],
+            key_type: None,
         })
     }
 
@@ -227,6 +440,7 @@ impl TableColumn {
             column_name: Identifier::new(column_name)?,
             column_type: DatabaseColumnType::Timestamp,
             constraints: vec![],
+            key_type: None,
         })
     }
 
@@ -235,6 +449,7 @@ impl TableColumn {
             column_name: Identifier::new(column_name)?,
             column_type: DatabaseColumnType::Float,
             constraints: vec![],
+            key_type: None,
         })
     }
 
@@ -243,6 +458,7 @@ impl TableColumn {
             column_name: Identifier::new(column_name)?,
             column_type: DatabaseColumnType::Uuid,
             constraints: vec![],
+            key_type: None,
         })
     }
 
@@ -251,6 +467,7 @@ impl TableColumn {
             column_name: Identifier::new(column_name)?,
             column_type: DatabaseColumnType::Boolean,
             constraints: vec![],
+            key_type: None,
         })
     }
 }
@@ -261,7 +478,9 @@ impl BuildSql for TableColumn {
         sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
     ) {
         // sql.push_bind(self.column_name.to_string());
-        sql.push(self.column_name.to_string()).push(" ").push(self.column_type.as_str());
+        sql.push(self.column_name.to_string())
+            .push(" ")
+            .push(self.stored_type().as_str());
 
         let constraints_iter = self.constraints.iter();
         for constraint in constraints_iter {
@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     data_definition::table::{Identifier, TableColumn},
+    queries::Filter,
     BuildSql,
 };
 
@@ -24,27 +25,47 @@ impl BuildSql for PrimaryKeyColumnConstraint {
     }
 }
 
-#[allow(unused)]
-struct CheckExpressionConstraint {}
+/// `CHECK (<expression>)`. Used for both column and table constraints.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct CheckExpressionConstraint {
+    /// The rendered SQL, with any values written inline. Kept as SQL so that migrations can compare it.
+    pub expression: String,
+}
+
+impl CheckExpressionConstraint {
+    pub fn new(filter: &Filter) -> Self {
+        Self {
+            expression: filter.to_inline_sql(),
+        }
+    }
+}
 
 impl BuildSql for CheckExpressionConstraint {
     fn build_sql(
         &self,
-        _sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+        sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
     ) {
-        todo!()
+        sql.push(format!("CHECK ({})", self.expression));
     }
 }
 
+/// Extra parameters for the index backing a `UNIQUE` / `PRIMARY KEY` constraint.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default)]
-pub struct IndexParameters {}
+pub struct IndexParameters {
+    /// Non-key columns stored in the index, for index-only scans.
+    pub include: Vec<Identifier>,
+}
 
 impl BuildSql for IndexParameters {
     fn build_sql(
         &self,
-        _sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+        sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
     ) {
-        todo!()
+        if !self.include.is_empty() {
+            sql.push(" INCLUDE (");
+            self.include.build_sql(sql);
+            sql.push(")");
+        }
     }
 }
 
@@ -55,7 +76,8 @@ impl BuildSql for Vec<Identifier> {
     ) {
         let mut idents = self.iter().peekable();
         while let Some(col) = idents.next() {
-            builder.push_bind(col.to_string());
+            // Identifiers can't be bound as parameters, but are validated on creation.
+            builder.push(col);
             if idents.peek().is_some() {
                 builder.push(", ");
             }
@@ -78,12 +100,14 @@ impl BuildSql for UniqueColumnConstraint {
         if self.is_null_distinct {
             sql.push(" NULLS DISTINCT");
         }
-        // TODO: index_parameters impl
+        if let Some(params) = &self.index_parameters {
+            params.build_sql(sql);
+        }
     }
 }
 
 /// Foreign Key
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct ReferencesConstraint {
     pub ref_table: Identifier,
     pub ref_column: Option<Identifier>,
@@ -183,13 +207,14 @@ impl BuildSql for ReferencesConstraint {
             sql.push(')');
         }
         if let Some(match_type) = &self.match_type {
+            sql.push(' ');
             match_type.build_sql(sql);
         }
         if let Some(ref_action) = &self.on_delete_action {
             sql.push(" ON DELETE ");
             ref_action.build_sql(sql);
         }
-        if let Some(ref_action) = &self.on_delete_action {
+        if let Some(ref_action) = &self.on_update_action {
             sql.push(" ON UPDATE ");
             ref_action.build_sql(sql);
         }
@@ -256,6 +281,40 @@ impl TableColumnConstraint {
             detail: Arc::new(TableColumnConstraintDetail::NotNull),
         }
     }
+
+    /// Builds a `DEFAULT` constraint. The expression is raw SQL, e.g. `now()` or `'pending'`.
+    pub fn default_value(expression: &str) -> Self {
+        Self {
+            name: None,
+            detail: Arc::new(TableColumnConstraintDetail::Default(expression.to_string())),
+        }
+    }
+
+    /// Builds a `CHECK` constraint from a filter, e.g. `filters.price.gte(0)`.
+    pub fn check(filter: &Filter) -> Self {
+        Self {
+            name: None,
+            detail: Arc::new(TableColumnConstraintDetail::Check(CheckExpressionConstraint::new(
+                filter,
+            ))),
+        }
+    }
+
+    /// Builds a `GENERATED ALWAYS AS (...) STORED` constraint. The expression is raw SQL, e.g. `price * quantity`.
+    pub fn generated(expression: &str) -> Self {
+        Self {
+            name: None,
+            detail: Arc::new(TableColumnConstraintDetail::Generated(expression.to_string())),
+        }
+    }
+
+    /// Builds a `GENERATED BY DEFAULT AS IDENTITY` constraint.
+    pub fn identity() -> Self {
+        Self {
+            name: None,
+            detail: Arc::new(TableColumnConstraintDetail::Identity),
+        }
+    }
 }
 
 impl BuildSql for TableColumnConstraint {
@@ -265,8 +324,9 @@ impl BuildSql for TableColumnConstraint {
     ) {
         sql.push(" ");
         if let Some(name) = &self.name {
+            // Identifiers can't be bound as parameters, but are validated on creation.
             sql.push("CONSTRAINT ");
-            sql.push_bind(name.to_string());
+            sql.push(name);
             sql.push(" ");
         }
         self.detail.build_sql(sql)
@@ -277,9 +337,13 @@ impl BuildSql for TableColumnConstraint {
 pub enum TableColumnConstraintDetail {
     NotNull,
     Null,
-    // Check(CheckExpressionConstraint), // TODO
-    // Default(_), // TODO
-    // Generated(GeneratedConstraint), // TODO
+    Check(CheckExpressionConstraint),
+    /// `DEFAULT <expression>`. The expression is raw SQL, and is NOT escaped - only use trusted input.
+    Default(String),
+    /// `GENERATED ALWAYS AS (<expression>) STORED`. The expression is raw SQL, and is NOT escaped - only use trusted input.
+    Generated(String),
+    /// `GENERATED BY DEFAULT AS IDENTITY`, for keys numbered by the database.
+    Identity,
     Unique(UniqueColumnConstraint),
     PrimaryKey(PrimaryKeyColumnConstraint),
     References(ReferencesConstraint),
@@ -297,9 +361,16 @@ impl BuildSql for TableColumnConstraintDetail {
             TableColumnConstraintDetail::Null => {
                 sql.push("NULL".to_owned());
             },
-            // TableColumnConstraint::Check(check) => check.as_sql(),
-            // TableColumnConstraint::Default(_) => todo!(),
-            // TableColumnConstraint::Generated(_) => todo!(),
+            TableColumnConstraintDetail::Check(check) => check.build_sql(sql),
+            TableColumnConstraintDetail::Default(expression) => {
+                sql.push("DEFAULT ").push(expression);
+            },
+            TableColumnConstraintDetail::Generated(expression) => {
+                sql.push(format!("GENERATED ALWAYS AS ({expression}) STORED"));
+            },
+            TableColumnConstraintDetail::Identity => {
+                sql.push("GENERATED BY DEFAULT AS IDENTITY");
+            },
             TableColumnConstraintDetail::Unique(unique) => unique.build_sql(sql),
             TableColumnConstraintDetail::PrimaryKey(pk) => pk.build_sql(sql),
             TableColumnConstraintDetail::References(fk) => fk.build_sql(sql),
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{data_definition::table::Identifier, BuildSql};
+
+/// A fieldless Rust enum stored as a Postgres `ENUM` type. Derived with `#[derive(DbEnum)]`, and used on
+/// a field with `#[db_enum]`.
+///
+/// Variants are stored by name, matching serde's default representation, so that rows read through
+/// `to_json` deserialize the same way as rows decoded directly.
+pub trait DbEnum: Sized + 'static {
+    /// The Postgres type, with its variants in declaration order.
+    fn enum_type() -> EnumType;
+    fn as_variant(&self) -> &'static str;
+    fn from_variant(variant: &str) -> Option<Self>;
+}
+
+/// A Postgres `ENUM` type. Variants can be added by migrations, but never removed or reordered.
+/// [ref](https://www.postgresql.org/docs/current/datatype-enum.html)
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct EnumType {
+    pub name: Identifier,
+    pub variants: Vec<String>,
+}
+
+impl EnumType {
+    pub fn new(
+        name: Identifier,
+        variants: Vec<String>,
+    ) -> Self {
+        Self {
+            name,
+            variants,
+        }
+    }
+}
+
+/// `CREATE TYPE ... AS ENUM`. Postgres has no `IF NOT EXISTS` for types, so an existing type is ignored instead.
+impl BuildSql for EnumType {
+    fn build_sql(
+        &self,
+        sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    ) {
+        let variants = self
+            .variants
+            .iter()
+            .map(|variant| format!("'{}'", variant.replace('\'', "''")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        sql.push(format!(
+            "DO $$ BEGIN CREATE TYPE {} AS ENUM ({variants}); EXCEPTION WHEN duplicate_object THEN NULL; END $$;",
+            self.name
+        ));
+    }
+}
@@ -1,6 +1,8 @@
 #[allow(clippy::module_inception)]
 mod column;
 mod constraints;
+mod db_enum;
 
 pub use column::*;
 pub use constraints::*;
+pub use db_enum::*;
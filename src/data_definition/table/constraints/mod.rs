@@ -2,10 +2,11 @@ use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
-use crate::BuildSql;
+use crate::{queries::Filter, BuildSql};
 
 use super::{
-    Identifier, IndexParameters, ReferencesConstraintMatchType, ReferentialAction, TableColumn,
+    CheckExpressionConstraint, Identifier, IndexParameters, ReferencesConstraintMatchType,
+    ReferentialAction, TableColumn,
 };
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
@@ -20,17 +21,111 @@ impl BuildSql for TableConstraint {
         sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
     ) {
         if let Some(name) = &self.name {
-            sql.push("CONSTRAINT ").push_bind(name.to_string()).push(" ");
+            // Identifiers can't be bound as parameters, but are validated on creation.
+            sql.push("CONSTRAINT ").push(name).push(" ");
         }
         self.detail.build_sql(sql);
     }
 }
 
+impl TableConstraint {
+    /// A (possibly composite) primary key on `columns`.
+    pub fn primary_key(columns: Vec<TableColumn>) -> Self {
+        Self {
+            name: None,
+            detail: Arc::new(TableConstraintDetail::PrimaryKey(PrimaryKeyConstraint {
+                index_parameters: None,
+                columns,
+            })),
+        }
+    }
+
+    /// A named `UNIQUE` constraint across `columns`, e.g. an email that's unique per tenant.
+    pub fn unique(
+        name: Identifier,
+        columns: Vec<TableColumn>,
+    ) -> Self {
+        Self {
+            name: Some(name),
+            detail: Arc::new(TableConstraintDetail::Unique(UniqueConstraint {
+                is_null_distinct: false,
+                index_parameters: None,
+                columns,
+            })),
+        }
+    }
+
+    /// A named (possibly composite) `FOREIGN KEY`, from `columns` to `ref_columns` of `ref_table`.
+    pub fn foreign_key(
+        name: Identifier,
+        columns: Vec<TableColumn>,
+        ref_table: Identifier,
+        ref_columns: Vec<Identifier>,
+    ) -> Self {
+        Self {
+            name: Some(name),
+            detail: Arc::new(TableConstraintDetail::ForeignKey(ForeignKeyConstraint {
+                ref_table,
+                ref_columns,
+                columns,
+                match_type: None,
+                on_delete_action: None,
+                on_update_action: None,
+            })),
+        }
+    }
+
+    /// A named `CHECK` constraint across the table, e.g. `filters.discount.lte(50) | filters.on_sale.eq(false)`.
+    pub fn check(
+        name: Identifier,
+        filter: &Filter,
+    ) -> Self {
+        Self {
+            name: Some(name),
+            detail: Arc::new(TableConstraintDetail::Check(CheckExpressionConstraint::new(filter))),
+        }
+    }
+
+    /// Whether the two would be the same constraint in the database. Columns are compared by name only, so that
+    /// changing a column (e.g. its default) doesn't change the constraints on it.
+    pub fn is_same_as(
+        &self,
+        other: &Self,
+    ) -> bool {
+        let names = |columns: &[TableColumn]| {
+            columns.iter().map(|column| column.column_name.clone()).collect::<Vec<_>>()
+        };
+        self.name == other.name
+            && match (&*self.detail, &*other.detail) {
+                (TableConstraintDetail::Unique(a), TableConstraintDetail::Unique(b)) => {
+                    a.is_null_distinct == b.is_null_distinct
+                        && a.index_parameters == b.index_parameters
+                        && names(&a.columns) == names(&b.columns)
+                },
+                (TableConstraintDetail::PrimaryKey(a), TableConstraintDetail::PrimaryKey(b)) => {
+                    a.index_parameters == b.index_parameters
+                        && names(&a.columns) == names(&b.columns)
+                },
+                (TableConstraintDetail::ForeignKey(a), TableConstraintDetail::ForeignKey(b)) => {
+                    a.ref_table == b.ref_table
+                        && a.ref_columns == b.ref_columns
+                        && a.match_type == b.match_type
+                        && a.on_delete_action == b.on_delete_action
+                        && a.on_update_action == b.on_update_action
+                        && names(&a.columns) == names(&b.columns)
+                },
+                (TableConstraintDetail::Check(a), TableConstraintDetail::Check(b)) => a == b,
+                _ => false,
+            }
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
 pub enum TableConstraintDetail {
     Unique(UniqueConstraint),
     PrimaryKey(PrimaryKeyConstraint),
     ForeignKey(ForeignKeyConstraint),
+    Check(CheckExpressionConstraint),
 }
 
 impl BuildSql for TableConstraintDetail {
@@ -42,6 +137,7 @@ impl BuildSql for TableConstraintDetail {
             Self::ForeignKey(fk) => fk.build_sql(sql),
             Self::Unique(un) => un.build_sql(sql),
             Self::PrimaryKey(pk) => pk.build_sql(sql),
+            Self::Check(check) => check.build_sql(sql),
         };
     }
 }
@@ -50,7 +146,7 @@ impl BuildSql for TableConstraintDetail {
 pub struct UniqueConstraint {
     is_null_distinct: bool,
     index_parameters: Option<IndexParameters>,
-    columns: Vec<TableColumn>,
+    pub columns: Vec<TableColumn>,
 }
 
 impl BuildSql for UniqueConstraint {
@@ -62,8 +158,13 @@ impl BuildSql for UniqueConstraint {
         if self.is_null_distinct {
             sql.push("NULLS DISTINCT ");
         }
+        sql.push("(");
         let columns = self.columns.iter().map(|col| col.column_name.clone()).collect::<Vec<_>>();
         columns.build_sql(sql);
+        sql.push(")");
+        if let Some(params) = &self.index_parameters {
+            params.build_sql(sql);
+        }
     }
 }
 
@@ -78,11 +179,13 @@ impl BuildSql for PrimaryKeyConstraint {
         &self,
         sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
     ) {
-        sql.push("PRIMARY KEY ");
+        sql.push("PRIMARY KEY (");
         let columns = self.columns.iter().map(|col| col.column_name.clone()).collect::<Vec<_>>();
         columns.build_sql(sql);
-
-        // TODO: IndexParameters impl
+        sql.push(")");
+        if let Some(params) = &self.index_parameters {
+            params.build_sql(sql);
+        }
     }
 }
 
@@ -95,22 +198,35 @@ impl BuildSql for ForeignKeyConstraint {
             ref_table,
             ref_columns,
             columns,
-            ..
+            match_type,
+            on_delete_action,
+            on_update_action,
         } = self;
-        builder.push("FOREIGN_KEY(");
-        // TODO: Loop through columns
+        builder.push("FOREIGN KEY (");
         columns
             .iter()
             .map(|col| col.column_name.clone())
             .collect::<Vec<_>>()
             .build_sql(builder);
-        builder.push(") ").push("REFERENCES ").push(&**ref_table).push("(");
+        builder.push(") ").push("REFERENCES ").push(&**ref_table).push(" (");
         ref_columns.build_sql(builder);
         builder.push(")");
+        if let Some(match_type) = match_type {
+            builder.push(" ");
+            match_type.build_sql(builder);
+        }
+        if let Some(ref_action) = on_delete_action {
+            builder.push(" ON DELETE ");
+            ref_action.build_sql(builder);
+        }
+        if let Some(ref_action) = on_update_action {
+            builder.push(" ON UPDATE ");
+            ref_action.build_sql(builder);
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct ForeignKeyConstraint {
     pub ref_table: Identifier,
     pub ref_columns: Vec<Identifier>, // TODO: Yank this to a more definitive mapping from columns and ref_columns
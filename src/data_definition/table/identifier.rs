@@ -5,11 +5,44 @@ use serde::{Deserialize, Serialize};
 /// Represents a Database Identifier, for column names and table names.
 /// It's just a string under the hood, but forcing calls to use Identifier::new(String),
 /// we are able to perform field validation.
+///
+/// Serialized as a plain string, so that it can be used as a map key (e.g. `DatabaseTableDefinition.columns`).
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Serialize, Deserialize)]
+#[serde(try_from = "IdentifierRepr", into = "String")]
 pub struct Identifier {
     value: Arc<String>,
 }
 
+// Identifiers end up in SQL unescaped, so deserialized ones go through the same validation as `Identifier::new()`.
+// Older versions serialized them as `{ "value": "..." }`, which is still accepted.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IdentifierRepr {
+    Plain(String),
+    Legacy {
+        value: String,
+    },
+}
+
+impl TryFrom<IdentifierRepr> for Identifier {
+    type Error = String;
+
+    fn try_from(repr: IdentifierRepr) -> Result<Self, Self::Error> {
+        match repr {
+            IdentifierRepr::Plain(value)
+            | IdentifierRepr::Legacy {
+                value,
+            } => Identifier::new(value),
+        }
+    }
+}
+
+impl From<Identifier> for String {
+    fn from(identifier: Identifier) -> Self {
+        identifier.value.to_string()
+    }
+}
+
 impl Deref for Identifier {
     type Target = str;
 
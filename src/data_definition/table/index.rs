@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{queries::Filter, BuildSql};
+
+use super::Identifier;
+
+/// The index access method, i.e. `USING <method>`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum IndexMethod {
+    #[default]
+    BTree,
+    Hash,
+    Gin,
+    Gist,
+    Brin,
+}
+
+impl IndexMethod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            IndexMethod::BTree => "btree",
+            IndexMethod::Hash => "hash",
+            IndexMethod::Gin => "gin",
+            IndexMethod::Gist => "gist",
+            IndexMethod::Brin => "brin",
+        }
+    }
+}
+
+impl TryFrom<&str> for IndexMethod {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "btree" => Ok(IndexMethod::BTree),
+            "hash" => Ok(IndexMethod::Hash),
+            "gin" => Ok(IndexMethod::Gin),
+            "gist" => Ok(IndexMethod::Gist),
+            "brin" => Ok(IndexMethod::Brin),
+            other => Err(format!("Unsupported index method: {other}")),
+        }
+    }
+}
+
+/// An index on one or more columns of a table. Created / dropped by migrations, alongside the table.
+/// [ref](https://www.postgresql.org/docs/current/sql-createindex.html)
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct TableIndex {
+    pub name: Identifier,
+    pub columns: Vec<Identifier>,
+    pub is_unique: bool,
+    pub method: IndexMethod,
+    /// The `WHERE` clause of a partial index, with any values written inline.
+    pub predicate: Option<String>,
+}
+
+impl TableIndex {
+    /// A btree index on `columns`, named the way Postgres names unnamed indexes (`{table}_{columns}_idx`).
+    pub fn new(
+        table_name: &Identifier,
+        columns: Vec<Identifier>,
+    ) -> Self {
+        let column_names = columns.iter().map(|column| &**column).collect::<Vec<_>>().join("_");
+        Self {
+            name: Identifier::new_unchecked(format!("{table_name}_{column_names}_idx")),
+            columns,
+            is_unique: false,
+            method: IndexMethod::default(),
+            predicate: None,
+        }
+    }
+
+    pub fn named(
+        mut self,
+        name: Identifier,
+    ) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn unique(mut self) -> Self {
+        self.is_unique = true;
+        self
+    }
+
+    pub fn using(
+        mut self,
+        method: IndexMethod,
+    ) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Makes this a partial index, only covering the rows matching `filter`.
+    pub fn filter(
+        mut self,
+        filter: &Filter,
+    ) -> Self {
+        self.predicate = Some(filter.to_inline_sql());
+        self
+    }
+}
+
+/// `CREATE INDEX`. Use `concurrently` for tables that already exist, so that writes aren't blocked
+/// while the index is built. Concurrent builds can't run inside a transaction - see `Migration::run()`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CreateIndex {
+    pub table_name: Identifier,
+    pub index: TableIndex,
+    pub concurrently: bool,
+}
+
+impl BuildSql for CreateIndex {
+    fn build_sql(
+        &self,
+        sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    ) {
+        let index = &self.index;
+        sql.push("CREATE ");
+        if index.is_unique {
+            sql.push("UNIQUE ");
+        }
+        sql.push("INDEX ");
+        if self.concurrently {
+            sql.push("CONCURRENTLY ");
+        }
+        sql.push(format!(
+            "IF NOT EXISTS {} ON {} USING {} (",
+            index.name,
+            self.table_name,
+            index.method.as_str()
+        ));
+        index.columns.build_sql(sql);
+        sql.push(")");
+        if let Some(predicate) = &index.predicate {
+            sql.push(format!(" WHERE {predicate}"));
+        }
+        sql.push(";");
+    }
+}
+
+/// `DROP INDEX`. See `CreateIndex` for `concurrently`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DropIndex {
+    pub name: Identifier,
+    pub concurrently: bool,
+}
+
+impl BuildSql for DropIndex {
+    fn build_sql(
+        &self,
+        sql: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    ) {
+        sql.push("DROP INDEX ");
+        if self.concurrently {
+            sql.push("CONCURRENTLY ");
+        }
+        sql.push(format!("IF EXISTS {};", self.name));
+    }
+}
@@ -1,10 +1,12 @@
 mod column;
 mod identifier;
+mod index;
 #[allow(clippy::module_inception)]
 mod table;
 mod constraints;
 
 pub use column::*;
 pub use identifier::*;
+pub use index::*;
 pub use table::*;
 pub use constraints::*;
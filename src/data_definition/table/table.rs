@@ -5,7 +5,15 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
-use super::{Identifier, TableColumn, TableConstraint};
+use crate::{
+    data_manager::PrimaryKey,
+    queries::{Filter, FilterComparisonParam},
+};
+
+use super::{
+    DatabaseColumnType, Identifier, ReferencesConstraint, ReferentialAction, TableColumn,
+    TableConstraint, TableConstraintDetail, TableIndex,
+};
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub enum TableRelationship {
@@ -14,16 +22,41 @@ pub enum TableRelationship {
     OneToOne(Identifier),
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+/// Identifies a table definition: by the Rust type it's derived from, or by its qualified name for tables without one
+/// (e.g. child tables read back from a snapshot).
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TableKey {
+    Type(TypeId),
+    Table(Identifier),
+}
+
+impl TableKey {
+    pub fn of<T: 'static>() -> Self {
+        Self::Type(TypeId::of::<T>())
+    }
+}
+
+impl From<TypeId> for TableKey {
+    fn from(type_id: TypeId) -> Self {
+        Self::Type(type_id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DatabaseTableDefinition {
     pub table_name: Identifier,
+    /// The Postgres schema the table lives in, or `None` for the default (`public`).
+    #[serde(default)]
+    pub schema: Option<Identifier>,
     // TODO: Make it so that there can only be one ID column.
     // TODO: Composite keys, Constraints, etc.
     // pub columns: Vec<TableColumn>,
     pub columns: BTreeMap<Identifier, TableColumn>, // BTreeMap for testing reasons... yes it adds inefficiency, but shoudln't be enough to matter.
     #[serde(skip)]
-    pub child_tables: HashMap<TypeId, Box<DatabaseTableDefinition>>, // Used for auto-adding child tables without explicitly adding them to the Application.
+    pub child_tables: HashMap<TableKey, Box<DatabaseTableDefinition>>, // Used for auto-adding child tables without explicitly adding them to the Application.
     pub constraints: Vec<TableConstraint>,
+    #[serde(default)]
+    pub indexes: Vec<TableIndex>,
 }
 
 /// Experimental - we need a typeless vbersion of this data for building migrations appropriately.
@@ -32,14 +65,11 @@ pub struct DatabaseTableDefinition {
 ///   (b) loses some type association data. It would be a huuuuge refactor.
 /// (Interestingly, that was the way I *originally* did it, I think. Wish I'd just stuck that way...)
 pub(crate) mod raw_data {
-    use std::{
-        any::TypeId,
-        collections::{BTreeMap, HashMap},
-    };
+    use std::collections::{BTreeMap, HashMap};
 
-    use crate::data_definition::table::{Identifier, TableColumn, TableConstraint};
+    use crate::data_definition::table::{Identifier, TableColumn, TableConstraint, TableIndex};
 
-    use super::DatabaseTableDefinition;
+    use super::{DatabaseTableDefinition, TableKey};
     trait LockedTrait {}
     impl LockedTrait for DatabaseTableDefinition {}
 
@@ -49,9 +79,10 @@ pub(crate) mod raw_data {
         Self: LockedTrait,
     {
         fn table_name(&self) -> Identifier;
-        fn child_tables(&self) -> HashMap<TypeId, Box<DatabaseTableDefinition>>;
+        fn child_tables(&self) -> HashMap<TableKey, Box<DatabaseTableDefinition>>;
         fn constraints(&self) -> &Vec<TableConstraint>;
         fn columns(&self) -> &BTreeMap<Identifier, TableColumn>;
+        fn indexes(&self) -> &Vec<TableIndex>;
         fn add_column(
             &mut self,
             column: TableColumn,
@@ -67,7 +98,7 @@ pub(crate) mod raw_data {
             &self.constraints
         }
 
-        fn child_tables(&self) -> HashMap<TypeId, Box<DatabaseTableDefinition>> {
+        fn child_tables(&self) -> HashMap<TableKey, Box<DatabaseTableDefinition>> {
             self.child_tables.clone()
         }
 
@@ -75,6 +106,10 @@ pub(crate) mod raw_data {
             &self.columns
         }
 
+        fn indexes(&self) -> &Vec<TableIndex> {
+            &self.indexes
+        }
+
         fn add_column(
             &mut self,
             column: TableColumn,
@@ -84,14 +119,46 @@ pub(crate) mod raw_data {
     }
 }
 
+/// Child tables are compared regardless of how they're keyed, as a definition read back from a snapshot keys them by
+/// name rather than by type.
+impl PartialEq for DatabaseTableDefinition {
+    fn eq(
+        &self,
+        other: &Self,
+    ) -> bool {
+        fn children(table: &DatabaseTableDefinition) -> Vec<&DatabaseTableDefinition> {
+            let mut children =
+                table.child_tables.values().map(|child| &**child).collect::<Vec<_>>();
+            children.sort_by_key(|child| child.qualified_name());
+            children
+        }
+        self.table_name == other.table_name
+            && self.schema == other.schema
+            && self.columns == other.columns
+            && self.constraints == other.constraints
+            && self.indexes == other.indexes
+            && children(self) == children(other)
+    }
+}
+
+impl Eq for DatabaseTableDefinition {}
+
 impl DatabaseTableDefinition {
     pub fn new(table_name: &str) -> Result<Self, String> {
+        // Tables are qualified as `schema.table`, so a dot in the name would be read as a schema.
+        if table_name.contains('.') {
+            return Err(format!(
+                "Invalid table name: {table_name} - use `.schema()` to put a table in a schema"
+            ));
+        }
         Ok(Self {
             table_name: Identifier::new(table_name)?, // TODO: Clean this up ([2023-12-11] What's wrong with it / clean up in what way?)
+            schema: None,
             columns: BTreeMap::new(),
             child_tables: Default::default(),
             // columns: Vec::new(),
             constraints: Vec::new(),
+            indexes: Vec::new(),
         })
     }
 
@@ -111,6 +178,248 @@ impl DatabaseTableDefinition {
         self.columns.insert(column.column_name.clone(), column);
         // self.columns.push(column);
     }
+
+    /// Adds a table constraint, e.g. `TableConstraint::check(..)`.
+    pub fn constraint(
+        mut self,
+        constraint: TableConstraint,
+    ) -> Self {
+        self.constraints.push(constraint);
+        self
+    }
+
+    /// Places the table in the given Postgres schema, e.g. `billing`. Schema names can't contain `.`.
+    pub fn schema(
+        mut self,
+        schema: &str,
+    ) -> Result<Self, String> {
+        if schema.contains('.') {
+            return Err(format!("Invalid schema name: {schema}"));
+        }
+        self.schema = Some(Identifier::new(schema)?);
+        Ok(self)
+    }
+
+    /// Adds an index, e.g. `TableIndex::new(..)`. Indexes are created after the table itself.
+    pub fn index(
+        mut self,
+        index: TableIndex,
+    ) -> Self {
+        self.indexes.push(index);
+        self
+    }
+}
+
+impl DatabaseTableDefinition {
+    /// The name of the table as a relation, qualified with its schema if it has one (e.g. `billing.invoice`).
+    ///
+    /// Columns are still qualified with the bare `table_name` (e.g. `invoice.id`), as Postgres resolves those
+    /// against the unqualified name of a schema-qualified table.
+    pub fn qualified_name(&self) -> Identifier {
+        Self::qualify(self.schema.as_ref(), &self.table_name)
+    }
+
+    /// Qualifies `table_name` with `schema`, if there is one.
+    pub fn qualify(
+        schema: Option<&Identifier>,
+        table_name: &Identifier,
+    ) -> Identifier {
+        match schema {
+            Some(schema) => Identifier::new_unchecked(format!("{schema}.{table_name}")),
+            None => table_name.clone(),
+        }
+    }
+
+    /// Finds the table that `name` refers to, from a table in `from_schema`: the only table with that name, or
+    /// otherwise the one in the same schema. Qualified names (e.g. `billing.invoice`) are matched exactly. `None` if
+    /// there's no such table, or it's ambiguous.
+    pub fn resolve_table<'a>(
+        tables: impl IntoIterator<Item = &'a DatabaseTableDefinition>,
+        name: &Identifier,
+        from_schema: Option<&Identifier>,
+    ) -> Option<&'a DatabaseTableDefinition> {
+        let candidates = tables
+            .into_iter()
+            .filter(|table| &table.table_name == name || &table.qualified_name() == name)
+            .collect::<Vec<_>>();
+        match candidates.as_slice() {
+            [table] => Some(table),
+            _ => candidates.into_iter().find(|table| table.schema.as_ref() == from_schema),
+        }
+    }
+
+    /// The qualified name of `table_name`, if it is this table or one of its (possibly nested) child
+    /// tables. Tables that aren't found are assumed to be in the default schema.
+    pub fn qualified_name_of(
+        &self,
+        table_name: &Identifier,
+    ) -> Identifier {
+        self.find_schema_of(table_name)
+            .map(|schema| Self::qualify(Some(&schema), table_name))
+            .unwrap_or_else(|| table_name.clone())
+    }
+
+    fn find_schema_of(
+        &self,
+        table_name: &Identifier,
+    ) -> Option<Identifier> {
+        if &self.table_name == table_name {
+            return self.schema.clone();
+        }
+        self.child_tables.values().find_map(|child| child.find_schema_of(table_name))
+    }
+
+    /// The columns making up the primary key, in key order: from the table's `PRIMARY KEY` constraint
+    /// if it has one (i.e. a composite key), otherwise the column marked as the primary key.
+    pub fn primary_key_columns(&self) -> Vec<&TableColumn> {
+        let composite_key =
+            self.constraints.iter().find_map(|constraint| match &*constraint.detail {
+                TableConstraintDetail::PrimaryKey(pk) => Some(pk),
+                _ => None,
+            });
+        match composite_key {
+            Some(pk) => pk
+                .columns
+                .iter()
+                .filter_map(|column| self.columns.get(&column.column_name))
+                .collect(),
+            None => self.columns.values().filter(|column| column.is_pk()).collect(),
+        }
+    }
+
+    /// The names of the primary key columns, qualified with the table name (e.g. `account.tenant_id`).
+    pub fn qualified_primary_key(&self) -> Vec<Identifier> {
+        self.primary_key_columns()
+            .into_iter()
+            .map(|column| {
+                Identifier::new_unchecked(format!("{}.{}", self.table_name, column.column_name))
+            })
+            .collect()
+    }
+
+    /// Matches the row with the given primary key. Fails if `key` doesn't have a value for each key column.
+    pub fn primary_key_filter(
+        &self,
+        key: &impl PrimaryKey,
+    ) -> Result<Filter, crate::Error> {
+        let (columns, values) = (self.qualified_primary_key(), key.key_params());
+        if columns.len() != values.len() {
+            return Err(crate::Error::Validation(format!(
+                "{} has {} primary key column(s), but the key {} has {} value(s)",
+                self.table_name,
+                columns.len(),
+                key.to_key_string(),
+                values.len()
+            )));
+        }
+        Ok(Filter::And(
+            columns
+                .into_iter()
+                .zip(values)
+                .map(|(column, value)| {
+                    Filter::Equal(FilterComparisonParam::TableColumn(column), value)
+                })
+                .collect(),
+        ))
+    }
+
+    /// The key column other tables reference this one by in relationships. Relationships link a single column,
+    /// so this fails for tables without a key, or with a composite key.
+    pub fn relation_key(&self) -> Result<&TableColumn, String> {
+        match self.primary_key_columns().as_slice() {
+            [key] => Ok(key),
+            [] => Err(format!("{} has no primary key to relate it by", self.table_name)),
+            _ => Err(format!(
+                "{} has a composite primary key, and can't be used in a relationship",
+                self.table_name
+            )),
+        }
+    }
+
+    /// The child table named `table_name`, if it's one of this table's (direct) children.
+    ///
+    /// One-to-one relationships from older definitions name their target `{child}_id`, so that's matched too.
+    pub fn related_table(
+        &self,
+        table_name: &Identifier,
+    ) -> Option<&DatabaseTableDefinition> {
+        let bare_name = table_name.strip_suffix("_id");
+        self.child_tables.values().map(|child| &**child).find(|child| {
+            child.table_name == *table_name || Some(child.table_name.as_str()) == bare_name
+        })
+    }
+
+    /// Fills in the key type of the one-to-one and `Related<T>` columns stored on this table, from the related
+    /// tables in `child_tables`. See `TableColumnData::stored_type()`.
+    pub fn resolve_relation_keys(&mut self) {
+        let resolved = self
+            .columns
+            .values()
+            .filter_map(|column| match &column.column_type {
+                DatabaseColumnType::OneToOne(target) | DatabaseColumnType::Related(target) => {
+                    let key = self.related_table(target)?.relation_key().ok()?;
+                    let mut column = (**column).clone();
+                    column.key_type = Some(key.column_type.clone());
+                    Some(column)
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for column in resolved {
+            self.add_column(column);
+        }
+    }
+}
+
+impl DatabaseTableDefinition {
+    /// The name of the join table backing the many-to-many relationship `parent.field` -> `child`. The field is
+    /// part of the name, so that a table can have several many-to-many relationships to the same child table.
+    pub fn join_table_name(
+        parent: &Identifier,
+        field: &Identifier,
+        child: &Identifier,
+    ) -> Identifier {
+        Identifier::new_unchecked(format!("{parent}_{field}_to_{child}"))
+    }
+
+    /// Builds the join table for the many-to-many relationship `parent.field` -> `child`: one row per link,
+    /// keyed on `(parent_id, child_id)`, in the parent's schema. The columns aren't named after the tables, so that
+    /// a table can be linked to itself. Each references (and takes the type of) its side's `relation_key()`, and
+    /// links are deleted along with either side.
+    ///
+    /// Fails if either side doesn't have a single-column key.
+    pub fn join_table(
+        parent: &DatabaseTableDefinition,
+        field: &Identifier,
+        child: &DatabaseTableDefinition,
+    ) -> Result<Self, String> {
+        let link_column =
+            |side: &str, table: &DatabaseTableDefinition| -> Result<TableColumn, String> {
+                let key = table.relation_key()?;
+                Ok(TableColumn::new(&format!("{side}_id"), key.column_type.clone(), Vec::new())?
+                    .non_null()
+                    .foreign_key(ReferencesConstraint {
+                        ref_table: table.table_name.clone(),
+                        ref_column: Some(key.column_name.clone()),
+                        match_type: None,
+                        on_delete_action: Some(ReferentialAction::Cascade),
+                        on_update_action: None,
+                    })
+                    .into())
+            };
+        let (parent_column, child_column) =
+            (link_column("parent", parent)?, link_column("child", child)?);
+
+        let mut table =
+            Self::new(&Self::join_table_name(&parent.table_name, field, &child.table_name))?
+                .column(parent_column.clone())
+                .column(child_column.clone());
+        table.schema = parent.schema.clone();
+        table
+            .constraints
+            .push(TableConstraint::primary_key(vec![parent_column, child_column]));
+        Ok(table)
+    }
 }
 
 impl DatabaseTableDefinition {
@@ -0,0 +1,669 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use super::table::{
+    DatabaseColumnType, DatabaseTableDefinition, Identifier, TableColumnConstraintDetail,
+    TableConstraintDetail,
+};
+
+/// A problem with the tables in a data system, found by `DataSystemBuilder::build()`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Diagnostic {
+    /// A relationship or foreign key points at a table or column that isn't in the data system.
+    MissingRelationTarget {
+        table: Identifier,
+        column: Identifier,
+        target: String,
+    },
+    /// A relationship or foreign key names a table that's in more than one schema, none of them the referencing
+    /// table's.
+    AmbiguousRelationTarget {
+        table: Identifier,
+        column: Identifier,
+        target: Identifier,
+        candidates: Vec<Identifier>,
+    },
+    /// More than one type is stored in the same table.
+    DuplicateTableName {
+        table: Identifier,
+        count: usize,
+    },
+    /// A foreign key column's type doesn't match the column it references.
+    ForeignKeyTypeMismatch {
+        table: Identifier,
+        column: Identifier,
+        column_type: DatabaseColumnType,
+        target: String,
+        target_type: DatabaseColumnType,
+    },
+    MissingPrimaryKey {
+        table: Identifier,
+    },
+    /// A relationship to (or, for one-to-many and many-to-many, from) a table without a single-column primary
+    /// key. See `DatabaseTableDefinition::relation_key()`.
+    UnsupportedRelationKey {
+        table: Identifier,
+        column: Identifier,
+        reason: String,
+    },
+    /// A table, schema or column is named after a reserved SQL keyword. Identifiers are written unquoted, so
+    /// these break the generated SQL.
+    ReservedIdentifier {
+        table: Identifier,
+        identifier: Identifier,
+    },
+    /// Tables that own each other through one-to-many / one-to-one relationships, in order - e.g. `[a, b]` for
+    /// `a -> b -> a`. Owned children are written along with their parent, so these can never be saved. At least one
+    /// cycle is reported for each group of tables that own each other, though not necessarily every cycle.
+    OwnedRelationCycle {
+        tables: Vec<Identifier>,
+    },
+}
+
+impl Display for Diagnostic {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Self::MissingRelationTarget {
+                table,
+                column,
+                target,
+            } => write!(f, "{table}.{column} references {target}, which doesn't exist"),
+            Self::AmbiguousRelationTarget {
+                table,
+                column,
+                target,
+                candidates,
+            } => write!(
+                f,
+                "{table}.{column} references {target}, which could be any of {} - qualify it with a schema",
+                candidates.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::DuplicateTableName {
+                table,
+                count,
+            } => write!(f, "{count} types are stored in table {table}"),
+            Self::ForeignKeyTypeMismatch {
+                table,
+                column,
+                column_type,
+                target,
+                target_type,
+            } => write!(
+                f,
+                "{table}.{column} is {} but references {target}, which is {}",
+                column_type.as_str(),
+                target_type.as_str()
+            ),
+            Self::MissingPrimaryKey {
+                table,
+            } => write!(f, "{table} has no primary key"),
+            Self::UnsupportedRelationKey {
+                table,
+                column,
+                reason,
+            } => write!(f, "{table}.{column} can't be a relationship: {reason}"),
+            Self::ReservedIdentifier {
+                table,
+                identifier,
+            } => write!(f, "{identifier} (in {table}) is a reserved SQL keyword"),
+            Self::OwnedRelationCycle {
+                tables,
+            } => {
+                let cycle = tables.iter().chain(tables.first()).map(|t| t.as_str());
+                write!(
+                    f,
+                    "owned relations form a cycle: {}",
+                    cycle.collect::<Vec<_>>().join(" -> ")
+                )
+            },
+        }
+    }
+}
+
+/// Keywords Postgres reserves outright, including those only allowed as function or type names. None of these
+/// can be used as unquoted table or column names.
+const RESERVED_KEYWORDS: &[&str] = &[
+    "all",
+    "analyse",
+    "analyze",
+    "and",
+    "any",
+    "array",
+    "as",
+    "asc",
+    "asymmetric",
+    "authorization",
+    "binary",
+    "both",
+    "case",
+    "cast",
+    "check",
+    "collate",
+    "collation",
+    "column",
+    "concurrently",
+    "constraint",
+    "create",
+    "cross",
+    "current_catalog",
+    "current_date",
+    "current_role",
+    "current_schema",
+    "current_time",
+    "current_timestamp",
+    "current_user",
+    "default",
+    "deferrable",
+    "desc",
+    "distinct",
+    "do",
+    "else",
+    "end",
+    "except",
+    "false",
+    "fetch",
+    "for",
+    "foreign",
+    "freeze",
+    "from",
+    "full",
+    "grant",
+    "group",
+    "having",
+    "ilike",
+    "in",
+    "initially",
+    "inner",
+    "intersect",
+    "into",
+    "is",
+    "isnull",
+    "join",
+    "lateral",
+    "leading",
+    "left",
+    "like",
+    "limit",
+    "localtime",
+    "localtimestamp",
+    "natural",
+    "not",
+    "notnull",
+    "null",
+    "offset",
+    "on",
+    "only",
+    "or",
+    "order",
+    "outer",
+    "overlaps",
+    "placing",
+    "primary",
+    "references",
+    "returning",
+    "right",
+    "select",
+    "session_user",
+    "similar",
+    "some",
+    "symmetric",
+    "system_user",
+    "table",
+    "tablesample",
+    "then",
+    "to",
+    "trailing",
+    "true",
+    "union",
+    "unique",
+    "user",
+    "using",
+    "variadic",
+    "verbose",
+    "when",
+    "where",
+    "window",
+    "with",
+];
+
+/// Checks `tables` for problems that would otherwise only surface at runtime (or as a panic), and returns all of them.
+pub fn validate_tables<'a>(
+    tables: impl IntoIterator<Item = &'a DatabaseTableDefinition>
+) -> Vec<Diagnostic> {
+    let mut tables = tables.into_iter().collect::<Vec<_>>();
+    tables.sort_by_key(|table| table.qualified_name());
+
+    let mut diagnostics = Vec::new();
+    let mut seen: BTreeMap<Identifier, usize> = BTreeMap::new();
+    for table in &tables {
+        *seen.entry(table.qualified_name()).or_default() += 1;
+    }
+    diagnostics.extend(seen.into_iter().filter(|(_, count)| *count > 1).map(|(table, count)| {
+        Diagnostic::DuplicateTableName {
+            table,
+            count,
+        }
+    }));
+
+    // Relationships and foreign keys name their target unqualified, and migrations qualify them later - the same way
+    // as they're found here.
+    let find_table = |name: &Identifier, from_schema: Option<&Identifier>| {
+        DatabaseTableDefinition::resolve_table(tables.iter().copied(), name, from_schema)
+    };
+
+    for table in &tables {
+        let table_name = table.qualified_name();
+        let names = std::iter::once(&table.table_name)
+            .chain(&table.schema)
+            .chain(table.columns.keys());
+        for identifier in names {
+            if RESERVED_KEYWORDS.contains(&identifier.to_lowercase().as_str()) {
+                diagnostics.push(Diagnostic::ReservedIdentifier {
+                    table: table_name.clone(),
+                    identifier: identifier.clone(),
+                });
+            }
+        }
+
+        if table.primary_key_columns().is_empty() {
+            diagnostics.push(Diagnostic::MissingPrimaryKey {
+                table: table_name.clone(),
+            });
+        }
+
+        // (column, referenced table, referenced column) for every relationship and foreign key.
+        let mut references = Vec::new();
+        for column in table.columns.values() {
+            match &column.column_type {
+                DatabaseColumnType::OneToMany(target)
+                | DatabaseColumnType::ManyToMany(target)
+                | DatabaseColumnType::OneToOne(target)
+                | DatabaseColumnType::Related(target) => {
+                    references.push((column, target, None));
+                },
+                _ => {},
+            }
+            for constraint in &column.constraints {
+                if let TableColumnConstraintDetail::References(fk) = &*constraint.detail {
+                    references.push((column, &fk.ref_table, fk.ref_column.as_ref()));
+                }
+            }
+        }
+        for constraint in &table.constraints {
+            if let TableConstraintDetail::ForeignKey(fk) = &*constraint.detail {
+                for (column, ref_column) in fk.columns.iter().zip(&fk.ref_columns) {
+                    references.push((column, &fk.ref_table, Some(ref_column)));
+                }
+            }
+        }
+
+        for (column, target, ref_column) in references {
+            let missing = |target: String| Diagnostic::MissingRelationTarget {
+                table: table_name.clone(),
+                column: column.column_name.clone(),
+                target,
+            };
+            let Some(target_table) = find_table(target, table.schema.as_ref()) else {
+                let candidates = tables
+                    .iter()
+                    .filter(|t| t.table_name == *target)
+                    .map(|t| t.qualified_name())
+                    .collect::<Vec<_>>();
+                diagnostics.push(match candidates.len() {
+                    0 => missing(target.to_string()),
+                    _ => Diagnostic::AmbiguousRelationTarget {
+                        table: table_name.clone(),
+                        column: column.column_name.clone(),
+                        target: target.clone(),
+                        candidates,
+                    },
+                });
+                continue;
+            };
+            let Some(ref_column) = ref_column else {
+                // A relationship, linking the related table's key (and, from a collection, this table's).
+                let unsupported = |reason: String| Diagnostic::UnsupportedRelationKey {
+                    table: table_name.clone(),
+                    column: column.column_name.clone(),
+                    reason,
+                };
+                if column.column_type.is_stored_in_other_table() {
+                    if let Err(reason) = table.relation_key() {
+                        diagnostics.push(unsupported(reason));
+                    }
+                }
+                match target_table.relation_key() {
+                    Err(_) if matches!(column.column_type, DatabaseColumnType::OneToMany(_)) => {},
+                    Err(reason) => diagnostics.push(unsupported(reason)),
+                    Ok(key)
+                        if !column.column_type.is_stored_in_other_table()
+                            && column.stored_type() != key.column_type =>
+                    {
+                        diagnostics.push(Diagnostic::ForeignKeyTypeMismatch {
+                            table: table_name.clone(),
+                            column: column.column_name.clone(),
+                            column_type: column.stored_type(),
+                            target: format!("{target}.{}", key.column_name),
+                            target_type: key.column_type.clone(),
+                        })
+                    },
+                    Ok(_) => {},
+                }
+                continue;
+            };
+            let Some(target_column) = target_table.columns.get(ref_column) else {
+                diagnostics.push(missing(format!("{target}.{ref_column}")));
+                continue;
+            };
+            if column.column_type != target_column.column_type {
+                diagnostics.push(Diagnostic::ForeignKeyTypeMismatch {
+                    table: table_name.clone(),
+                    column: column.column_name.clone(),
+                    column_type: column.column_type.clone(),
+                    target: format!("{target}.{ref_column}"),
+                    target_type: target_column.column_type.clone(),
+                });
+            }
+        }
+    }
+
+    // Owned children are saved along with their parent, so a path back to the parent would recurse forever.
+    let owned_children = |table: &DatabaseTableDefinition| {
+        table
+            .columns
+            .values()
+            .filter_map(|column| match &column.column_type {
+                DatabaseColumnType::OneToMany(target) | DatabaseColumnType::OneToOne(target) => {
+                    find_table(target, table.schema.as_ref())
+                },
+                _ => None,
+            })
+            .map(|child| child.qualified_name())
+            .collect::<Vec<_>>()
+    };
+    let graph: BTreeMap<Identifier, Vec<Identifier>> = tables
+        .iter()
+        .map(|table| (table.qualified_name(), owned_children(table)))
+        .collect();
+    diagnostics.extend(find_cycles(&graph).into_iter().map(|tables| {
+        Diagnostic::OwnedRelationCycle {
+            tables,
+        }
+    }));
+
+    diagnostics
+}
+
+/// The cycles in `graph` closed by each edge back to a node that's still being visited, in a depth-first search. Each
+/// starts from its smallest node. Every strongly connected group of nodes has at least one, and finding them takes
+/// linear time - unlike listing every distinct cycle, which can take exponential time.
+fn find_cycles(graph: &BTreeMap<Identifier, Vec<Identifier>>) -> Vec<Vec<Identifier>> {
+    enum Colour {
+        Visiting,
+        Visited,
+    }
+
+    fn visit(
+        node: &Identifier,
+        graph: &BTreeMap<Identifier, Vec<Identifier>>,
+        colours: &mut BTreeMap<Identifier, Colour>,
+        path: &mut Vec<Identifier>,
+        cycles: &mut Vec<Vec<Identifier>>,
+    ) {
+        colours.insert(node.clone(), Colour::Visiting);
+        path.push(node.clone());
+        for child in graph.get(node).into_iter().flatten() {
+            match colours.get(child) {
+                None => visit(child, graph, colours, path, cycles),
+                Some(Colour::Visiting) => {
+                    let start = path.iter().position(|n| n == child).unwrap_or_default();
+                    let mut cycle = path[start..].to_vec();
+                    let smallest = (0..cycle.len()).min_by_key(|&i| &cycle[i]).unwrap_or_default();
+                    cycle.rotate_left(smallest);
+                    cycles.push(cycle);
+                },
+                Some(Colour::Visited) => {},
+            }
+        }
+        path.pop();
+        colours.insert(node.clone(), Colour::Visited);
+    }
+
+    let mut colours = BTreeMap::new();
+    let mut cycles = Vec::new();
+    for node in graph.keys() {
+        if !colours.contains_key(node) {
+            visit(node, graph, &mut colours, &mut Vec::new(), &mut cycles);
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_definition::table::{
+        DatabaseColumnType, DatabaseTableDefinition, Identifier, TableColumn, TableConstraint,
+        TableKey,
+    };
+
+    use super::{validate_tables, Diagnostic};
+
+    #[test]
+    fn all_problems_are_reported_at_once() {
+        let customer = DatabaseTableDefinition::new("customer")
+            .unwrap()
+            .column(TableColumn::new_uuid("id").unwrap().non_null().pk())
+            .column(
+                TableColumn::new(
+                    "orders",
+                    DatabaseColumnType::OneToMany(Identifier::new_unchecked("order")),
+                    vec![],
+                )
+                .unwrap(),
+            )
+            .column(
+                TableColumn::new(
+                    "wallet",
+                    DatabaseColumnType::OneToOne(Identifier::new_unchecked("wallet")),
+                    vec![],
+                )
+                .unwrap(),
+            );
+        let order = DatabaseTableDefinition::new("order")
+            .unwrap()
+            .column(TableColumn::new_uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::new_int("customer_id").unwrap().fk_to(
+                Identifier::new_unchecked("customer"),
+                TableColumn::new_uuid("id").unwrap().into(),
+            ))
+            .column(
+                TableColumn::new(
+                    "buyer",
+                    DatabaseColumnType::OneToOne(Identifier::new_unchecked("customer")),
+                    vec![],
+                )
+                .unwrap(),
+            );
+        let note = DatabaseTableDefinition::new("note")
+            .unwrap()
+            .column(TableColumn::new_uuid("id").unwrap().non_null().pk());
+        let audit_log = DatabaseTableDefinition::new("audit_log")
+            .unwrap()
+            .column(TableColumn::new_string("message").unwrap());
+
+        let diagnostics = validate_tables([&customer, &order, &note, &note, &audit_log]);
+
+        let customer = Identifier::new_unchecked("customer");
+        let order = Identifier::new_unchecked("order");
+        let note = Identifier::new_unchecked("note");
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::DuplicateTableName {
+                    table: note,
+                    count: 2,
+                },
+                Diagnostic::MissingPrimaryKey {
+                    table: Identifier::new_unchecked("audit_log"),
+                },
+                Diagnostic::MissingRelationTarget {
+                    table: customer.clone(),
+                    column: Identifier::new_unchecked("wallet"),
+                    target: "wallet".to_string(),
+                },
+                Diagnostic::ReservedIdentifier {
+                    table: order.clone(),
+                    identifier: order.clone(),
+                },
+                Diagnostic::ForeignKeyTypeMismatch {
+                    table: order.clone(),
+                    column: Identifier::new_unchecked("customer_id"),
+                    column_type: DatabaseColumnType::Int,
+                    target: "customer.id".to_string(),
+                    target_type: DatabaseColumnType::Uuid,
+                },
+                Diagnostic::OwnedRelationCycle {
+                    tables: vec![customer, order],
+                },
+            ]
+        );
+        assert_eq!(
+            diagnostics.last().unwrap().to_string(),
+            "owned relations form a cycle: customer -> order -> customer"
+        );
+    }
+    #[test]
+    fn relationships_need_a_single_column_key_of_the_right_type() {
+        let mut tag = DatabaseTableDefinition::new("tag")
+            .unwrap()
+            .column(TableColumn::new_int("tenant_id").unwrap().non_null())
+            .column(TableColumn::new_string("name").unwrap().non_null());
+        let key = tag.columns.values().cloned().collect();
+        tag.constraints.push(TableConstraint::primary_key(key));
+        let account = DatabaseTableDefinition::new("account")
+            .unwrap()
+            .column(TableColumn::new_string("code").unwrap().non_null().pk());
+        let mut invoice = DatabaseTableDefinition::new("invoice")
+            .unwrap()
+            .column(TableColumn::new_uuid("id").unwrap().non_null().pk())
+            .column(
+                TableColumn::new(
+                    "tags",
+                    DatabaseColumnType::ManyToMany(Identifier::new_unchecked("tag")),
+                    vec![],
+                )
+                .unwrap(),
+            )
+            .column(
+                TableColumn::new(
+                    "account_id",
+                    DatabaseColumnType::OneToOne(Identifier::new_unchecked("account")),
+                    vec![],
+                )
+                .unwrap(),
+            );
+
+        let invoice_name = Identifier::new_unchecked("invoice");
+        assert_eq!(
+            validate_tables([&invoice, &tag, &account]),
+            vec![
+                Diagnostic::ForeignKeyTypeMismatch {
+                    table: invoice_name.clone(),
+                    column: Identifier::new_unchecked("account_id"),
+                    column_type: DatabaseColumnType::Uuid,
+                    target: "account.code".to_string(),
+                    target_type: DatabaseColumnType::String,
+                },
+                Diagnostic::UnsupportedRelationKey {
+                    table: invoice_name.clone(),
+                    column: Identifier::new_unchecked("tags"),
+                    reason: "tag has a composite primary key, and can't be used in a relationship"
+                        .to_string(),
+                },
+            ]
+        );
+
+        // Resolved from the child tables, the one-to-one column takes the type of the account's key.
+        invoice.child_tables.insert(TableKey::of::<String>(), Box::new(account.clone()));
+        invoice.resolve_relation_keys();
+        assert_eq!(
+            invoice.columns[&Identifier::new_unchecked("account_id")].stored_type(),
+            DatabaseColumnType::String
+        );
+        assert_eq!(validate_tables([&invoice, &tag, &account]).len(), 1);
+    }
+
+    #[test]
+    fn ambiguous_targets_are_reported_and_same_schema_targets_preferred() {
+        let account = |schema: &str| {
+            DatabaseTableDefinition::new("account")
+                .unwrap()
+                .schema(schema)
+                .unwrap()
+                .column(TableColumn::new_uuid("id").unwrap().non_null().pk())
+        };
+        let owned_account = |table: &str, schema: &str| {
+            DatabaseTableDefinition::new(table)
+                .unwrap()
+                .schema(schema)
+                .unwrap()
+                .column(TableColumn::new_uuid("id").unwrap().non_null().pk())
+                .column(TableColumn::new_uuid("account_id").unwrap().fk_to(
+                    Identifier::new_unchecked("account"),
+                    TableColumn::new_uuid("id").unwrap().into(),
+                ))
+        };
+        let invoice = owned_account("invoice", "billing");
+        let ticket = owned_account("ticket", "support");
+
+        let diagnostics =
+            validate_tables([&account("billing"), &account("crm"), &invoice, &ticket]);
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::AmbiguousRelationTarget {
+                table: Identifier::new_unchecked("support.ticket"),
+                column: Identifier::new_unchecked("account_id"),
+                target: Identifier::new_unchecked("account"),
+                candidates: vec![
+                    Identifier::new_unchecked("billing.account"),
+                    Identifier::new_unchecked("crm.account"),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn cycles_are_found_without_listing_every_path() {
+        // Every table owns every other, so there are far too many distinct cycles to list.
+        let names = (0..30).map(|i| format!("table_{i:02}")).collect::<Vec<_>>();
+        let tables = names
+            .iter()
+            .map(|name| {
+                names.iter().filter(|other| *other != name).fold(
+                    DatabaseTableDefinition::new(name)
+                        .unwrap()
+                        .column(TableColumn::new_uuid("id").unwrap().non_null().pk()),
+                    |table, other| {
+                        table.column(
+                            TableColumn::new(
+                                &format!("{other}s"),
+                                DatabaseColumnType::OneToMany(Identifier::new_unchecked(other)),
+                                vec![],
+                            )
+                            .unwrap(),
+                        )
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let cycles = validate_tables(&tables)
+            .into_iter()
+            .filter(|diagnostic| matches!(diagnostic, Diagnostic::OwnedRelationCycle { .. }))
+            .count();
+        assert!((1..=30 * 29).contains(&cycles));
+    }
+}
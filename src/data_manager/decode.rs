@@ -0,0 +1,289 @@
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use sqlx::{
+    postgres::{types::PgInterval, PgRow},
+    Row, TypeInfo, ValueRef,
+};
+
+use crate::{data_definition::table::DbEnum, Error};
+
+use super::{rest_api::Id, Related};
+
+/// Decodes a row directly from its columns, without the `to_json` round trip that `execute()` uses.
+/// Derived with `#[derive(DecodeRow)]`, and used by `ExecutableQuery::execute_direct()`.
+///
+/// Flat columns are decoded natively. Relationships come back as one JSON column each (see `Query`),
+/// and are deserialized separately - or left at their default, if the query didn't load them.
+pub trait DecodeRow: Sized {
+    fn decode_row(row: &PgRow) -> Result<Self, Error>;
+}
+
+/// A value that can be decoded from a single (non-relationship) column.
+pub trait DecodeColumn: Sized {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error>;
+}
+
+fn decode_error(
+    column: &str,
+    error: impl std::fmt::Display,
+) -> Error {
+    Error::Deserialize {
+        column: column.to_string(),
+        message: error.to_string(),
+    }
+}
+
+macro_rules! impl_decode_column {
+    ($($type:ty),*) => {
+        $(impl DecodeColumn for $type {
+            fn decode_column(
+                row: &PgRow,
+                column: &str,
+            ) -> Result<Self, Error> {
+                row.try_get(column).map_err(|e| decode_error(column, e))
+            }
+        })*
+    };
+}
+impl_decode_column!(String, bool, f64, chrono::NaiveDateTime, uuid::Uuid);
+impl_decode_column!(chrono::NaiveDate, chrono::NaiveTime, chrono::DateTime<chrono::Utc>);
+impl_decode_column!(
+    Vec<String>,
+    Vec<bool>,
+    Vec<f64>,
+    Vec<uuid::Uuid>,
+    Vec<chrono::NaiveDateTime>,
+    Vec<chrono::NaiveDate>,
+    Vec<chrono::NaiveTime>,
+    Vec<chrono::DateTime<chrono::Utc>>
+);
+
+/// `Query` selects `NUMERIC` columns as text (see `Query::write_sql()`), but raw queries may not.
+impl DecodeColumn for rust_decimal::Decimal {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        let raw = row.try_get_raw(column).map_err(|e| decode_error(column, e))?;
+        match raw.type_info().name() {
+            "NUMERIC" => row.try_get(column).map_err(|e| decode_error(column, e)),
+            _ => String::decode_column(row, column)?.parse().map_err(|e| decode_error(column, e)),
+        }
+    }
+}
+
+impl DecodeColumn for Vec<rust_decimal::Decimal> {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        let raw = row.try_get_raw(column).map_err(|e| decode_error(column, e))?;
+        match raw.type_info().name() {
+            "NUMERIC[]" => row.try_get(column).map_err(|e| decode_error(column, e)),
+            _ => Vec::<String>::decode_column(row, column)?
+                .iter()
+                .map(|value| value.parse().map_err(|e| decode_error(column, e)))
+                .collect(),
+        }
+    }
+}
+
+/// `Query` selects `BYTEA` columns as an array of bytes (see `Query::write_sql()`), but raw queries may not.
+impl DecodeColumn for Vec<u8> {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        let raw = row.try_get_raw(column).map_err(|e| decode_error(column, e))?;
+        match raw.type_info().name() {
+            "BYTEA" => row.try_get(column).map_err(|e| decode_error(column, e)),
+            _ => decode_integer_array(row, column)?
+                .into_iter()
+                .map(|byte| u8::try_from(byte).map_err(|e| decode_error(column, e)))
+                .collect(),
+        }
+    }
+}
+
+/// The error for an `INTERVAL` that's negative, and so can't be read as a `Duration`. Also selected in its place by
+/// `Query`, so it's kept free of quotes.
+pub(crate) const NEGATIVE_INTERVAL: &str = "negative intervals can not be read as a Duration";
+
+/// `Query` selects `INTERVAL` columns as JSON (see `Query::write_sql()`), but raw queries may not.
+impl DecodeColumn for std::time::Duration {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        let raw = row.try_get_raw(column).map_err(|e| decode_error(column, e))?;
+        if raw.type_info().name() != "INTERVAL" {
+            return decode_json_column(row, column);
+        }
+        let interval: PgInterval = row.try_get(column).map_err(|e| decode_error(column, e))?;
+        // Months don't have a fixed length, so they're counted as 30 days - the same as Postgres' `extract(epoch ...)`.
+        let days = i64::from(interval.months) * 30 + i64::from(interval.days);
+        let microseconds = days * 86_400_000_000 + interval.microseconds;
+        u64::try_from(microseconds)
+            .map(std::time::Duration::from_micros)
+            .map_err(|_| decode_error(column, NEGATIVE_INTERVAL))
+    }
+}
+
+impl DecodeColumn for f32 {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        f64::decode_column(row, column).map(|value| value as f32)
+    }
+}
+
+/// Reads an integer column of any width. `INT` columns are 32 bits, but `Int` fields are often `i64`.
+fn decode_integer(
+    row: &PgRow,
+    column: &str,
+) -> Result<i64, Error> {
+    let raw = row.try_get_raw(column).map_err(|e| decode_error(column, e))?;
+    match raw.type_info().name() {
+        "INT2" => row.try_get::<i16, _>(column).map(i64::from),
+        "INT4" => row.try_get::<i32, _>(column).map(i64::from),
+        _ => row.try_get::<i64, _>(column),
+    }
+    .map_err(|e| decode_error(column, e))
+}
+
+macro_rules! impl_decode_integer {
+    ($($type:ty),*) => {
+        $(impl DecodeColumn for $type {
+            fn decode_column(
+                row: &PgRow,
+                column: &str,
+            ) -> Result<Self, Error> {
+                let value = decode_integer(row, column)?;
+                <$type>::try_from(value).map_err(|e| decode_error(column, e))
+            }
+        })*
+    };
+}
+impl_decode_integer!(i16, i32, i64, u32, u64, isize, usize);
+
+/// Reads an integer array column of any width, like `decode_integer()`.
+fn decode_integer_array(
+    row: &PgRow,
+    column: &str,
+) -> Result<Vec<i64>, Error> {
+    let raw = row.try_get_raw(column).map_err(|e| decode_error(column, e))?;
+    match raw.type_info().name() {
+        "INT2[]" => row
+            .try_get::<Vec<i16>, _>(column)
+            .map(|values| values.into_iter().map(i64::from).collect()),
+        "INT4[]" => row
+            .try_get::<Vec<i32>, _>(column)
+            .map(|values| values.into_iter().map(i64::from).collect()),
+        _ => row.try_get::<Vec<i64>, _>(column),
+    }
+    .map_err(|e| decode_error(column, e))
+}
+
+macro_rules! impl_decode_integer_array {
+    ($($type:ty),*) => {
+        $(impl DecodeColumn for Vec<$type> {
+            fn decode_column(
+                row: &PgRow,
+                column: &str,
+            ) -> Result<Self, Error> {
+                decode_integer_array(row, column)?
+                    .into_iter()
+                    .map(|value| <$type>::try_from(value).map_err(|e| decode_error(column, e)))
+                    .collect()
+            }
+        })*
+    };
+}
+impl_decode_integer_array!(i32, i64, u32);
+
+impl DecodeColumn for Vec<f32> {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        Vec::<f64>::decode_column(row, column)
+            .map(|values| values.into_iter().map(|value| value as f32).collect())
+    }
+}
+
+/// Enum labels are sent as text, but sqlx only checks text against the built-in string types.
+impl<T: DbEnum> DecodeColumn for T {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        let variant: &str = row.try_get_unchecked(column).map_err(|e| decode_error(column, e))?;
+        T::from_variant(variant)
+            .ok_or_else(|| decode_error(column, format!("unknown variant `{variant}`")))
+    }
+}
+
+/// Only the key is stored - see `Related::load()`.
+impl<T: Id> DecodeColumn for Related<T>
+where
+    T::Key: DecodeColumn,
+{
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        T::Key::decode_column(row, column).map(Related::new)
+    }
+}
+
+impl<T: DecodeColumn> DecodeColumn for Option<T> {
+    fn decode_column(
+        row: &PgRow,
+        column: &str,
+    ) -> Result<Self, Error> {
+        if row.try_get_raw(column).map_err(|e| decode_error(column, e))?.is_null() {
+            return Ok(None);
+        }
+        T::decode_column(row, column).map(Some)
+    }
+}
+
+/// Deserializes a JSON column, e.g. a `#[json]` field or a relationship aggregated by `Query`.
+pub fn decode_json_column<T: DeserializeOwned>(
+    row: &PgRow,
+    column: &str,
+) -> Result<T, Error> {
+    let value: Option<serde_json::Value> =
+        row.try_get(column).map_err(|e| decode_error(column, e))?;
+    serde_json::from_value(value.unwrap_or_default()).map_err(|e| decode_error(column, e))
+}
+
+/// Deserializes a relationship column, or returns the default if the relation wasn't loaded.
+pub fn decode_relation_column<T: DeserializeOwned + Default>(
+    row: &PgRow,
+    column: &str,
+) -> Result<T, Error> {
+    if row.try_column(column).is_err() {
+        return Ok(T::default());
+    }
+    decode_json_column(row, column)
+}
+
+/// Parses a text column, e.g. a `#[string]` field. Returns `None` for `NULL`.
+pub fn decode_parsed_column<T>(
+    row: &PgRow,
+    column: &str,
+) -> Result<Option<T>, Error>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    Option::<String>::decode_column(row, column)?
+        .map(|value| value.parse().map_err(|e| decode_error(column, e)))
+        .transpose()
+}
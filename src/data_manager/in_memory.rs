@@ -1,20 +1,19 @@
 use crate::{
-    data_manager::{rest_api::Id, traits::DataProvider},
+    data_manager::{rest_api::Id, traits::DataProvider, PrimaryKey},
     queries::filterable_types::Filterable,
 };
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
     sync::{Arc, Mutex},
 };
-use uuid::Uuid;
 
 /// InMemoryDataProvider - Wraps a HashMap in the DatProvider interface.
 /// Status: Prototype / Hacky
 /// Notes: Lots of data duplication, not memory efficient, and blocks on reads due to the Arc<Mutex>, which is required to fit the trait signtautres and enable Clone
 
 #[derive(Clone, Default)]
-pub struct InMemoryDataProvider<T> {
-    items: Arc<Mutex<HashMap<Uuid, T>>>,
+pub struct InMemoryDataProvider<T: Id> {
+    items: Arc<Mutex<HashMap<T::Key, T>>>,
 }
 
 impl<T: Clone + Id + Send + Default + Filterable> DataProvider<T> for InMemoryDataProvider<T> {
@@ -31,23 +30,30 @@ impl<T: Clone + Id + Send + Default + Filterable> DataProvider<T> for InMemoryDa
         &self,
         _predicate: impl Fn(T::FilterType) -> crate::queries::Filter,
     ) -> Result<Option<T>, crate::Error> {
-        let _items = self.items.lock().unwrap();
         // let item = items.get(&id).cloned();
         // Ok(item)
-        todo!()
+        Err(crate::Error::Unsupported(
+            "Filtered gets are not supported by InMemoryDataProvider".to_string(),
+        ))
     }
 
     async fn create(
         &self,
         item: Self::CreateRequest,
     ) -> Result<T, crate::Error> {
-        let mut items = self.items.lock().unwrap();
-        let id = item.id();
-        if items.contains_key(id) {
-            Err(format!("Already contains object with id ({})", id))?
-        } else {
-            items.insert(*item.id(), item.clone());
-            Ok(item)
+        let mut items = self.items.lock().map_err(|e| crate::Error::MutexLock(e.to_string()))?;
+        match items.entry(item.id()) {
+            Entry::Occupied(entry) => Err(crate::Error::UniqueViolation {
+                constraint: None,
+                message: format!(
+                    "Already contains object with id ({})",
+                    entry.key().to_key_string()
+                ),
+            }),
+            Entry::Vacant(entry) => {
+                entry.insert(item.clone());
+                Ok(item)
+            },
         }
     }
 
@@ -55,10 +61,13 @@ impl<T: Clone + Id + Send + Default + Filterable> DataProvider<T> for InMemoryDa
         &self,
         item: T,
     ) -> Result<(), crate::Error> {
-        let mut items = self.items.lock().unwrap();
-        match items.remove(item.id()) {
+        let mut items = self.items.lock().map_err(|e| crate::Error::MutexLock(e.to_string()))?;
+        match items.remove(&item.id()) {
             Some(_) => Ok(()),
-            None => Err("Item not found".to_string())?,
+            None => Err(crate::Error::NotFound(format!(
+                "No object with id ({})",
+                item.id().to_key_string()
+            ))),
         }
     }
 
@@ -66,13 +75,16 @@ impl<T: Clone + Id + Send + Default + Filterable> DataProvider<T> for InMemoryDa
         &self,
         item: &T,
     ) -> Result<(), crate::Error> {
-        let mut items = self.items.lock().unwrap();
+        let mut items = self.items.lock().map_err(|e| crate::Error::MutexLock(e.to_string()))?;
         let id = item.id();
-        if items.contains_key(id) {
-            items.insert(*item.id(), item.clone());
-            Ok(())
-        } else {
-            Err(format!("no object with id ({})", id))?
+        match items.get_mut(&id) {
+            Some(existing) => {
+                *existing = item.clone();
+                Ok(())
+            },
+            None => {
+                Err(crate::Error::NotFound(format!("No object with id ({})", id.to_key_string())))
+            },
         }
     }
 }
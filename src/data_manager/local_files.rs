@@ -1,5 +1,4 @@
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
 
 use crate::{
     data_definition::table::DatabaseTableDefinition,
@@ -8,7 +7,7 @@ use crate::{
 };
 use std::{fs, marker::PhantomData, path::PathBuf};
 
-use super::rest_api::Id;
+use super::{rest_api::Id, PrimaryKey};
 
 /// UNTESTED - USE AT YOUR OWN RISK
 #[derive(Clone)]
@@ -21,9 +20,9 @@ pub struct LocalFileDataProvider<T> {
 impl<T: Id> LocalFileDataProvider<T> {
     fn get_filepath(
         &self,
-        item_id: &Uuid,
+        item_id: &T::Key,
     ) -> PathBuf {
-        let filename = format!("{}.json", item_id);
+        let filename = format!("{}.json", item_id.to_key_string());
         let mut path = self.root_folder_path.clone();
         path.push(&filename);
         path.to_owned()
@@ -35,10 +34,17 @@ impl<T: Default + Sync + Send + Id + Serialize + for<'a> Deserialize<'a> + Filte
 {
     type CreateRequest = T;
 
-    #[allow(unreachable_code)]
     async fn all(&self) -> Result<impl Iterator<Item = T>, crate::Error> {
-        todo!();
-        Ok(Vec::new().into_iter())
+        let mut items = Vec::new();
+        for entry in fs::read_dir(&self.root_folder_path)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let contents = fs::read_to_string(path)?;
+                let mut deserializer = serde_json::Deserializer::from_str(&contents);
+                items.push(serde_path_to_error::deserialize(&mut deserializer)?);
+            }
+        }
+        Ok(items.into_iter())
     }
 
     async fn create(
@@ -47,9 +53,10 @@ impl<T: Default + Sync + Send + Id + Serialize + for<'a> Deserialize<'a> + Filte
     ) -> Result<T, crate::Error> {
         // let item: T = item.into();
 
-        let path = self.get_filepath(item.id());
-        let contents = serde_json::to_string(&item).unwrap();
-        std::fs::write(path, contents).unwrap();
+        let path = self.get_filepath(&item.id());
+        let contents =
+            serde_json::to_string(&item).map_err(|e| crate::Error::Serialize(e.to_string()))?;
+        std::fs::write(path, contents)?;
 
         Ok(item)
     }
@@ -58,7 +65,9 @@ impl<T: Default + Sync + Send + Id + Serialize + for<'a> Deserialize<'a> + Filte
         &self,
         _predicate: impl FnOnce(<T as Filterable>::FilterType) -> Filter,
     ) -> Result<Option<T>, crate::Error> {
-        todo!()
+        Err(crate::Error::Unsupported(
+            "Filtered gets are not supported by LocalFileDataProvider".to_string(),
+        ))
         // let path = self.get_filepath(&id);
         // let contents = std::fs::read_to_string(path).unwrap();
         // match serde_json::from_str::<T>(&contents) {
@@ -71,7 +80,7 @@ impl<T: Default + Sync + Send + Id + Serialize + for<'a> Deserialize<'a> + Filte
         &self,
         item: T,
     ) -> Result<(), crate::Error> {
-        let path = self.get_filepath(item.id());
+        let path = self.get_filepath(&item.id());
         // // For safety, make sure it's the right object:
         // self.get(*item.id()).await?;
         fs::remove_file(path)?;
@@ -82,9 +91,10 @@ impl<T: Default + Sync + Send + Id + Serialize + for<'a> Deserialize<'a> + Filte
         &self,
         item: &T,
     ) -> Result<(), crate::Error> {
-        let path = self.get_filepath(item.id());
-        let contents = serde_json::to_string(item).unwrap();
-        std::fs::write(path, contents).unwrap();
+        let path = self.get_filepath(&item.id());
+        let contents =
+            serde_json::to_string(item).map_err(|e| crate::Error::Serialize(e.to_string()))?;
+        std::fs::write(path, contents)?;
         Ok(())
     }
 }
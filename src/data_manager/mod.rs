@@ -1,6 +1,11 @@
+pub mod decode;
 pub mod local_storage_provider;
 mod postgres;
 pub use postgres::*;
+mod primary_key;
+pub use primary_key::{PrimaryKey, RelationKey};
+mod related;
+pub use related::Related;
 use rest_api::Id;
 use serde::{Deserialize, Serialize};
 use traits::DataProvider;
@@ -62,29 +67,37 @@ where
 
     async fn get(
         &self,
-        _predicate: impl Fn(T::FilterType) -> crate::queries::Filter,
+        predicate: impl Fn(T::FilterType) -> crate::queries::Filter,
     ) -> Result<Option<T>, crate::Error> {
-        todo!()
+        match &self.inner {
+            DataProviderType::Postgres(dp) => dp.get(predicate).await,
+        }
     }
 
     async fn create(
         &self,
-        _item: Self::CreateRequest,
+        item: Self::CreateRequest,
     ) -> Result<T, crate::Error> {
-        todo!()
+        match &self.inner {
+            DataProviderType::Postgres(dp) => dp.create(item).await,
+        }
     }
 
     async fn delete(
         &self,
-        _item: T, // You give it up when you ask to delete it!
+        item: T, // You give it up when you ask to delete it!
     ) -> Result<(), crate::Error> {
-        todo!()
+        match &self.inner {
+            DataProviderType::Postgres(dp) => dp.delete(item).await,
+        }
     }
 
     async fn update(
         &self,
-        _item: &T,
+        item: &T,
     ) -> Result<(), crate::Error> {
-        todo!()
+        match &self.inner {
+            DataProviderType::Postgres(dp) => dp.update(item).await,
+        }
     }
 }
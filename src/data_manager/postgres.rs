@@ -1,17 +1,18 @@
 use crate::{
-    data_definition::table::{DatabaseTableDefinition, Identifier},
+    data_definition::table::DatabaseTableDefinition,
     migration::Migration,
     queries::{
-        filterable_types::Filterable, Deleteable, Filter, Insertable, OrderDirection, Query,
-        Updateable,
+        filterable_types::{Filterable, Relation},
+        Aggregate, AggregateQuery, CacheableSql, Deleteable, Filter, FilterComparisonParam,
+        GroupKey, Insertable, OrderBy, Projection, Query, QueryPlan, RawSql, Updateable,
     },
-    BuildSql,
+    BuildSql, Error,
 };
 use sqlx::Row;
-use sqlx::{Error, Execute, Pool, Postgres, QueryBuilder};
+use sqlx::{Execute, Pool, Postgres, QueryBuilder};
 use std::{marker::PhantomData, sync::Arc};
 
-use super::{rest_api::Id, traits::WithFilter};
+use super::{decode::DecodeRow, rest_api::Id, traits::WithFilter};
 
 #[derive(Clone)]
 pub struct PostgresDataProvider<T: Insertable> {
@@ -34,6 +35,46 @@ where
             _t: PhantomData,
         }
     }
+
+    /// Fetches the object with the given primary key, or `None` if it doesn't exist.
+    pub async fn get_by_id(
+        &self,
+        id: T::Key,
+    ) -> Result<Option<T>, crate::Error>
+    where
+        T: Id + for<'d> serde::Deserialize<'d>,
+    {
+        let query = Query::<T>::new(self.table_definition.clone())
+            .filter(self.table_definition.primary_key_filter(&id)?)
+            .limit(1);
+        Ok(fetch_as_json(&query, &self.db_pool, None).await?.pop())
+    }
+
+    /// Runs hand-written SQL, for the things the query builder doesn't cover (CTEs, window functions, etc).
+    /// Each row is deserialized into `R` in the same way as `ExecutableQuery::execute()`, so the
+    /// columns need to line up with `R`'s fields.
+    ///
+    /// Values are passed through `binds`, and referenced as `$1`, `$2`, ... in `sql`:
+    /// ```ignore
+    /// let top: Vec<Product> = provider
+    ///     .query_raw(
+    ///         "WITH ranked AS (SELECT *, rank() OVER (PARTITION BY category ORDER BY sales DESC) AS rank FROM product)
+    ///          SELECT * FROM ranked WHERE rank <= $1",
+    ///         vec![3.into()],
+    ///     )
+    ///     .await?;
+    /// ```
+    pub async fn query_raw<R>(
+        &self,
+        sql: &str,
+        binds: Vec<FilterComparisonParam>,
+    ) -> Result<Vec<R>, crate::Error>
+    where
+        R: for<'d> serde::Deserialize<'d>,
+    {
+        let query = RawSql::new(sql, binds).map_err(Error::Validation)?;
+        fetch_as_json(&query, &self.db_pool, None).await
+    }
 }
 
 pub trait GetTableDefinition {
@@ -64,6 +105,8 @@ impl<T: GetTableDefinition> GetTableDefinition for Vec<T> {
 pub struct ExecutableQuery<T> {
     query: Query<T>,
     db_pool: Pool<Postgres>,
+    /// Builds a default `T` as JSON, to fill in relations that weren't loaded. Set by `include()` / `exclude_relations()`.
+    defaults: Option<fn() -> serde_json::Value>,
 }
 
 // impl<T: Insertable> Deref for ExecutableQuery<T> {
@@ -79,34 +122,129 @@ pub struct ExecutableQuery<T> {
 //     }
 // }
 
-impl<T> From<ExecutableQuery<T>> for Vec<T>
+impl<T> TryFrom<ExecutableQuery<T>> for Vec<T>
 where
-    T: Insertable + for<'r> serde::Deserialize<'r> + Send + Unpin,
+    T: for<'r> serde::Deserialize<'r> + Send + Unpin,
 {
-    fn from(val: ExecutableQuery<T>) -> Self {
-        futures::executor::block_on(val.execute()).unwrap()
+    type Error = Error;
+
+    fn try_from(val: ExecutableQuery<T>) -> Result<Self, Self::Error> {
+        futures::executor::block_on(val.execute())
     }
 }
 
-impl<T: Insertable + for<'d> serde::Deserialize<'d> + Send + Unpin> ExecutableQuery<T> {
-    pub async fn execute(self) -> Result<Vec<T>, Error> {
-        // We wrap the whole thing in a `to_json` on the DB side. This makes it supes easy to deserialize.
-        // Without his, it got really messy, because it seems that SQLX doesn't support nested deserialization on its own.
-        // A little bit more overhead, perhaps, but jeeeeez does it save on dvelopment. And postgres is probably
-        // not the limiting factor rn anyway.
-        let mut query_builder = QueryBuilder::new("SELECT to_json(r) as json_result FROM (");
-        // let mut query_builder = QueryBuilder::new("");
-        self.query.build_sql(&mut query_builder);
-        query_builder.push(") r");
-
-        log::debug!("SQL query: {}", query_builder.sql());
-        let result = query_builder.build().fetch_all(&self.db_pool).await?;
-        let results = result.into_iter().map(|row| {
-            let rowresult: serde_json::Value = row.get("json_result");
+// We wrap the whole thing in a `to_json` on the DB side. This makes it supes easy to deserialize.
+// Without his, it got really messy, because it seems that SQLX doesn't support nested deserialization on its own.
+// A little bit more overhead, perhaps, but jeeeeez does it save on dvelopment. And postgres is probably
+// not the limiting factor rn anyway.
+const JSON_QUERY_PREFIX: &str = "SELECT to_json(r) as json_result FROM (";
+const JSON_QUERY_SUFFIX: &str = ") r";
+
+/// Pushes `query` into `query_builder`, wrapped so that each row comes back as a single JSON value.
+fn push_json_query(
+    query: &impl BuildSql,
+    query_builder: &mut QueryBuilder<'_, Postgres>,
+) {
+    query_builder.push(JSON_QUERY_PREFIX);
+    query.build_sql(query_builder);
+    query_builder.push(JSON_QUERY_SUFFIX);
+}
+
+fn default_json<T: Default + serde::Serialize>() -> serde_json::Value {
+    serde_json::to_value(T::default()).unwrap_or_default()
+}
+
+/// Runs `query`, deserializing each row into `R`. The SQL is cached per query shape.
+///
+/// Any fields missing from a row (i.e. relations that weren't loaded) are filled in from `defaults`.
+async fn fetch_as_json<R>(
+    query: &impl CacheableSql,
+    db_pool: &Pool<Postgres>,
+    defaults: Option<fn() -> serde_json::Value>,
+) -> Result<Vec<R>, Error>
+where
+    R: for<'d> serde::Deserialize<'d>,
+{
+    let defaults = defaults.map(|defaults| defaults());
+    let statement = query.cached_sql().wrap(JSON_QUERY_PREFIX, JSON_QUERY_SUFFIX);
+
+    log::debug!("SQL query: {}", statement.sql);
+    let result = statement.query().fetch_all(db_pool).await?;
+    result
+        .into_iter()
+        .map(|row| {
+            let mut rowresult: serde_json::Value = row.try_get("json_result")?;
+            if let (Some(serde_json::Value::Object(defaults)), Some(row)) =
+                (&defaults, rowresult.as_object_mut())
+            {
+                for (field, default) in defaults {
+                    row.entry(field).or_insert_with(|| default.clone());
+                }
+            }
             log::debug!("Result: {:?}", rowresult.to_string());
-            serde_json::from_value::<T>(rowresult).unwrap()
-        });
-        Ok(results.collect())
+            // Tracks the path through the object, so that errors point at the column that failed.
+            Ok(serde_path_to_error::deserialize::<_, R>(rowresult)?)
+        })
+        .collect()
+}
+
+/// Runs `query` without the `to_json` wrapper, decoding each row's columns directly into `R`.
+async fn fetch_decoded<R: DecodeRow>(
+    query: &impl CacheableSql,
+    db_pool: &Pool<Postgres>,
+) -> Result<Vec<R>, Error> {
+    let statement = query.cached_sql();
+
+    log::debug!("SQL query: {}", statement.sql);
+    let result = statement.query().fetch_all(db_pool).await?;
+    result.iter().map(R::decode_row).collect()
+}
+
+/// Runs `EXPLAIN (FORMAT JSON)` against exactly the SQL that `fetch_as_json` would run for `query`.
+async fn explain_json_query(
+    query: &impl BuildSql,
+    db_pool: &Pool<Postgres>,
+    analyze: bool,
+) -> Result<QueryPlan, Error> {
+    let mut query_builder = QueryBuilder::new(match analyze {
+        true => "EXPLAIN (FORMAT JSON, ANALYZE) ",
+        false => "EXPLAIN (FORMAT JSON) ",
+    });
+    push_json_query(query, &mut query_builder);
+
+    log::debug!("SQL query: {}", query_builder.sql());
+    let row = query_builder.build().fetch_one(db_pool).await?;
+    let plan: serde_json::Value = row.try_get(0)?;
+    let mut plans = serde_path_to_error::deserialize::<_, Vec<QueryPlan>>(plan)?;
+    plans
+        .pop()
+        .ok_or_else(|| Error::NotFound("EXPLAIN returned no plan".to_string()))
+}
+
+impl<T: for<'d> serde::Deserialize<'d> + Send + Unpin> ExecutableQuery<T> {
+    pub async fn execute(self) -> Result<Vec<T>, Error> {
+        fetch_as_json(&self.query, &self.db_pool, self.defaults).await
+    }
+
+    /// Returns the Postgres query plan for this query, exactly as `execute()` would run it.
+    ///
+    /// With `analyze`, the query is actually run (and the results discarded), so that the plan
+    /// includes actual row counts and timings.
+    pub async fn explain(
+        &self,
+        analyze: bool,
+    ) -> Result<QueryPlan, Error> {
+        explain_json_query(&self.query, &self.db_pool, analyze).await
+    }
+}
+
+impl<T: DecodeRow + Send + Unpin> ExecutableQuery<T> {
+    /// Like `execute()`, but decodes flat columns directly from the row (and each relationship from its
+    /// own JSON column), instead of serializing whole rows to JSON in Postgres. Faster for large reads.
+    ///
+    /// Requires `#[derive(DecodeRow)]` on `T`.
+    pub async fn execute_direct(self) -> Result<Vec<T>, Error> {
+        fetch_decoded(&self.query, &self.db_pool).await
     }
 }
 
@@ -123,27 +261,174 @@ impl<T: Filterable> ExecutableQuery<T> {
         self
     }
 
-    pub fn order_by(
+    /// Sorts the results by one or more columns. Keys are applied in the order given, and
+    /// subsequent calls append to the existing keys.
+    ///
+    /// Example:
+    /// ```ignore
+    /// provider.with_filter(|f| f.active.eq(true))
+    ///     .order_by(|f| [f.created_at.desc().nulls_last(), f.name.asc()])
+    /// ```
+    pub fn order_by<F, I>(
+        mut self,
+        derive_order: F,
+    ) -> Self
+    where
+        F: Fn(T::FilterType) -> I,
+        I: IntoIterator<Item = OrderBy>,
+    {
+        let order_by = derive_order(T::FilterType::default());
+        self.query = self.query.order_by(order_by);
+        self
+    }
+    pub fn limit(
         mut self,
-        col_name: Identifier,
-        direction: OrderDirection,
+        limit: usize,
     ) -> Self {
-        self.query = self.query.order_by(col_name, direction);
+        self.query = self.query.limit(limit);
         self
     }
 
-    pub fn order_asc(
-        self,
-        col_name: Identifier,
-    ) -> Self {
-        self.order_by(col_name, OrderDirection::Ascending)
+    /// Loads only the given relation (call again to include more), instead of every relation.
+    /// Relations that aren't loaded are left at their default.
+    ///
+    /// Example:
+    /// ```ignore
+    /// // Loads each order's customer, but not its line items.
+    /// let orders = provider
+    ///     .with_filter(|f| f.status.eq("open"))
+    ///     .include(|f| f.customer)
+    ///     .execute()
+    ///     .await?;
+    /// ```
+    pub fn include<F, R>(
+        mut self,
+        derive_relation: F,
+    ) -> Self
+    where
+        F: Fn(T::FilterType) -> R,
+        R: Relation,
+        T: Default + serde::Serialize,
+    {
+        let relation = derive_relation(T::FilterType::default());
+        self.query = self.query.include(relation.table_name().clone());
+        self.defaults = Some(default_json::<T>);
+        self
     }
-    pub fn order_desc(
+
+    /// Loads no relations, other than those added with `include()`. Relations that aren't loaded
+    /// are left at their default.
+    pub fn exclude_relations(mut self) -> Self
+    where
+        T: Default + serde::Serialize,
+    {
+        self.query = self.query.exclude_relations();
+        self.defaults = Some(default_json::<T>);
+        self
+    }
+
+    /// Selects only the columns named by the projection `P`, and deserializes the results into `P`.
+    /// Relations are only joined if `P` includes them.
+    ///
+    /// Since `P` is not `Filterable`, this should be the last call before `execute()`:
+    /// ```ignore
+    /// let summaries: Vec<OrderSummary> = provider
+    ///     .with_filter(|f| f.status.eq("open"))
+    ///     .order_by(|f| f.created_at.desc())
+    ///     .select::<OrderSummary>()
+    ///     .execute()
+    ///     .await?;
+    /// ```
+    pub fn select<P>(self) -> ExecutableQuery<P>
+    where
+        P: Projection<Source = T>,
+    {
+        ExecutableQuery {
+            query: self.query.select(P::fields()),
+            db_pool: self.db_pool,
+            defaults: None,
+        }
+    }
+}
+
+/// Wraps an `AggregateQuery<T>` alongside a DB Pool. Created with `ExecutableQuery::group_by()`.
+pub struct ExecutableAggregateQuery<T> {
+    query: AggregateQuery<T>,
+    db_pool: Pool<Postgres>,
+}
+
+impl<T: Filterable> ExecutableQuery<T> {
+    /// Groups the results by one or more keys. Follow up with `aggregate()` to add aggregate columns,
+    /// and `execute::<R>()` to deserialize each group into `R`.
+    ///
+    /// Example:
+    /// ```ignore
+    /// #[derive(Deserialize)]
+    /// struct DailyRevenue {
+    ///     day: NaiveDateTime,
+    ///     region: String,
+    ///     revenue: f64,
+    /// }
+    ///
+    /// let report: Vec<DailyRevenue> = provider
+    ///     .with_filter(|f| f.status.eq("paid"))
+    ///     .group_by(|f| [f.created_at.truncate(DatePart::Day).alias("day").unwrap(), f.region.group()])
+    ///     .aggregate(|f| f.amount.sum().alias("revenue").unwrap())
+    ///     .having(|f| f.amount.sum().gt(1000.0))
+    ///     .execute()
+    ///     .await?;
+    /// ```
+    pub fn group_by<F, I>(
         self,
-        col_name: Identifier,
-    ) -> Self {
-        self.order_by(col_name, OrderDirection::Desending)
+        derive_keys: F,
+    ) -> ExecutableAggregateQuery<T>
+    where
+        F: Fn(T::FilterType) -> I,
+        I: IntoIterator<Item = GroupKey>,
+    {
+        ExecutableAggregateQuery {
+            query: self.query.group_by(derive_keys(T::FilterType::default())),
+            db_pool: self.db_pool,
+        }
     }
+}
+
+impl<T: Filterable> ExecutableAggregateQuery<T> {
+    pub fn aggregate<F, I>(
+        mut self,
+        derive_aggregates: F,
+    ) -> Self
+    where
+        F: Fn(T::FilterType) -> I,
+        I: IntoIterator<Item = Aggregate>,
+    {
+        self.query = self.query.aggregate(derive_aggregates(T::FilterType::default()));
+        self
+    }
+
+    pub fn having<F>(
+        mut self,
+        derive_filter: F,
+    ) -> Self
+    where
+        F: Fn(T::FilterType) -> Filter,
+    {
+        self.query = self.query.having(derive_filter(T::FilterType::default()));
+        self
+    }
+
+    pub fn order_by<F, I>(
+        mut self,
+        derive_order: F,
+    ) -> Self
+    where
+        F: Fn(T::FilterType) -> I,
+        I: IntoIterator<Item = OrderBy>,
+    {
+        self.query = self.query.order_by(derive_order(T::FilterType::default()));
+        self
+    }
+
     pub fn limit(
         mut self,
         limit: usize,
@@ -151,6 +436,21 @@ impl<T: Filterable> ExecutableQuery<T> {
         self.query = self.query.limit(limit);
         self
     }
+
+    pub async fn execute<R>(self) -> Result<Vec<R>, Error>
+    where
+        R: for<'d> serde::Deserialize<'d>,
+    {
+        fetch_as_json(&self.query, &self.db_pool, None).await
+    }
+
+    /// Returns the Postgres query plan for this query. See `ExecutableQuery::explain()`.
+    pub async fn explain(
+        &self,
+        analyze: bool,
+    ) -> Result<QueryPlan, Error> {
+        explain_json_query(&self.query, &self.db_pool, analyze).await
+    }
 }
 
 // Migration Handling
@@ -162,26 +462,16 @@ where
         Migration::compare(
             None, // TODO: Need to get the old migration
             vec![self.table_definition.clone()],
-        );
-        todo!()
+        )
     }
-    pub async fn run_migrations(&self) -> Result<(), String> {
+    pub async fn run_migrations(&self) -> Result<(), Error> {
         log::info!("[DATABASE] Running Migrations");
         let migration = self.build_migration();
         if let Some(migration) = migration {
-            let mut sql = sqlx::QueryBuilder::new("");
-            migration.build_sql(&mut sql);
-
-            let sql = sql.into_sql();
-            let mut transaction = self.db_pool.begin().await.unwrap();
-            match sqlx::query(dbg!(&sql)).execute(&mut *transaction).await {
-                Ok(_) => {},
-                Err(e) => {
-                    log::error!("Failed to run migrations");
-                    return Err(format!("{}", e));
-                },
+            if let Err(e) = migration.run(&self.db_pool).await {
+                log::error!("Failed to run migrations");
+                return Err(e);
             }
-            transaction.commit().await.unwrap();
         } else {
             log::info!("[DATABASE] No Migrations");
         }
@@ -212,35 +502,30 @@ where
         &self,
         predicate: impl Fn(<T as Filterable>::FilterType) -> crate::queries::Filter,
     ) -> Result<Option<T>, crate::Error> {
-        let query = Query::<T> {
-            table: self.table_definition.clone(),
-            filter: Some(predicate(<T as Filterable>::FilterType::default())),
-            limit: Some(2),
-            order_by: None,
-            _t: Default::default(),
-        };
+        let query = Query::<T>::new(self.table_definition.clone())
+            .filter(predicate(<T as Filterable>::FilterType::default()))
+            .limit(2);
         let query = ExecutableQuery {
             query,
             db_pool: self.db_pool.clone(),
+            defaults: None,
         };
         let mut results = query.execute().await?;
         if results.len() > 1 {
-            return Err(crate::Error::DataIntegrity("Multiple items found for ID {}".to_string()));
+            return Err(crate::Error::DataIntegrity(format!(
+                "Multiple items found in {} for a single get",
+                self.table_definition.table_name
+            )));
         }
         Ok(results.pop())
     }
 
     async fn all(&self) -> Result<impl Iterator<Item = T>, crate::Error> {
-        let query = Query::<T> {
-            table: self.table_definition.clone(),
-            filter: None,
-            limit: None,
-            order_by: None,
-            _t: Default::default(),
-        };
+        let query = Query::<T>::new(self.table_definition.clone());
         let query = ExecutableQuery {
             query,
             db_pool: self.db_pool.clone(),
+            defaults: None,
         };
         Ok(query.execute().await?.into_iter())
     }
@@ -249,15 +534,13 @@ where
         &self,
         item: Self::CreateRequest,
     ) -> Result<T, crate::Error> {
-        let item = item.into();
+        let mut item: T = item.into();
         let insert_statement = item.get_insert_statement();
 
         let pool = self.db_pool.clone();
         let mut transaction = pool.begin().await?;
-        let mut builder: QueryBuilder<'_, Postgres> = QueryBuilder::new("");
-        insert_statement.build_sql(&mut builder);
-
-        builder.build().execute(&mut *transaction).await?;
+        let row = insert_statement.cached_sql().query().fetch_one(&mut *transaction).await?;
+        item.set_generated_keys(&row)?;
         transaction.commit().await?;
 
         Ok(item)
@@ -270,12 +553,24 @@ where
         let mut builder: QueryBuilder<'_, Postgres> = sqlx::QueryBuilder::new("");
         item.get_delete_statement().build_sql(&mut builder);
         let query = builder.build();
-        dbg!(&query.sql());
-        if query.execute(&self.db_pool).await?.rows_affected() > 1 {
-            panic!("Deleted more than one row in a Delete operation. This should not happen.");
-        }
+        log::debug!("SQL query: {}", query.sql());
 
-        Ok(())
+        // In a transaction, so that a delete that matches more than one row can be undone.
+        let mut transaction = self.db_pool.begin().await?;
+        match query.execute(&mut *transaction).await?.rows_affected() {
+            0 => Err(Error::NotFound(format!(
+                "No {} found to delete",
+                self.table_definition.table_name
+            ))),
+            1 => Ok(transaction.commit().await?),
+            rows => {
+                transaction.rollback().await?;
+                Err(Error::DataIntegrity(format!(
+                    "Delete would have removed {rows} rows from {} - rolled back",
+                    self.table_definition.table_name
+                )))
+            },
+        }
     }
 
     async fn update(
@@ -285,9 +580,7 @@ where
         let mut transaction = self.db_pool.begin().await?;
         let update_statement = item.get_update_statement();
 
-        let mut builder: QueryBuilder<'_, Postgres> = QueryBuilder::new("");
-        update_statement.build_sql(&mut builder);
-        builder.build().execute(&mut *transaction).await?;
+        update_statement.cached_sql().query().execute(&mut *transaction).await?;
         transaction.commit().await?;
 
         Ok(())
@@ -303,16 +596,12 @@ where
         &self,
         predicate: impl Fn(<T as Filterable>::FilterType) -> crate::queries::Filter,
     ) -> Self::R {
-        let query = Query::<T> {
-            table: self.table_definition.clone(),
-            filter: Some(predicate(T::FilterType::default())),
-            limit: None,
-            order_by: None,
-            _t: Default::default(),
-        };
+        let query = Query::<T>::new(self.table_definition.clone())
+            .filter(predicate(T::FilterType::default()));
         ExecutableQuery {
             query,
             db_pool: self.db_pool.clone(),
+            defaults: None,
         }
     }
 }
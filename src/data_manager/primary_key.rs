@@ -0,0 +1,90 @@
+use std::{fmt::Debug, hash::Hash};
+
+use crate::{data_definition::table::ColumnValue, queries::FilterComparisonParam};
+
+/// The value of a primary key - a single column, or a tuple of columns for a composite key.
+///
+/// Keys are selected with `#[primary_key]` on one or more fields (defaulting to the `id` field), and
+/// the columns are listed in the same order as the fields. See `DatabaseTableDefinition::primary_key_columns()`.
+///
+/// Relationships reference a single key column, so only single-column keys implement `RelationKey`.
+pub trait PrimaryKey: Clone + Eq + Hash + Debug + Send + Sync {
+    /// The value of each key column, in order.
+    fn key_params(&self) -> Vec<FilterComparisonParam>;
+
+    /// A readable form of the key, e.g. for file names and error messages. Composite keys are joined with `-`.
+    fn to_key_string(&self) -> String;
+}
+
+/// A single-column key, that other objects can be related to (e.g. with `Related<T>`).
+pub trait RelationKey: PrimaryKey {
+    /// The key, as stored in the column referencing it.
+    fn column_value(&self) -> ColumnValue;
+}
+
+macro_rules! impl_primary_key {
+    ($($type:ty => $param:ident, $value:ident),*) => {
+        $(impl PrimaryKey for $type {
+            fn key_params(&self) -> Vec<FilterComparisonParam> {
+                vec![FilterComparisonParam::$param(self.clone().into())]
+            }
+
+            fn to_key_string(&self) -> String {
+                self.to_string()
+            }
+        }
+
+        impl RelationKey for $type {
+            fn column_value(&self) -> ColumnValue {
+                ColumnValue::$value(self.clone().into())
+            }
+        })*
+    };
+}
+impl_primary_key!(
+    uuid::Uuid => Uuid, Uuid,
+    String => String, String,
+    i64 => Integer, Int,
+    i32 => Integer, Int,
+    i16 => Integer, Int,
+    u32 => Integer, Int
+);
+
+macro_rules! impl_composite_primary_key {
+    ($(($($name:ident),*)),*) => {
+        $(impl<$($name: PrimaryKey),*> PrimaryKey for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn key_params(&self) -> Vec<FilterComparisonParam> {
+                let ($($name,)*) = self;
+                let mut params = Vec::new();
+                $(params.extend($name.key_params());)*
+                params
+            }
+
+            #[allow(non_snake_case)]
+            fn to_key_string(&self) -> String {
+                let ($($name,)*) = self;
+                [$($name.to_key_string()),*].join("-")
+            }
+        })*
+    };
+}
+impl_composite_primary_key!((A, B), (A, B, C), (A, B, C, D));
+
+#[cfg(test)]
+mod tests {
+    use crate::queries::FilterComparisonParam;
+
+    use super::PrimaryKey;
+
+    #[test]
+    fn composite_keys_flatten_in_order() {
+        let key = (42_i64, "acme".to_string());
+
+        assert!(matches!(
+            &key.key_params()[..],
+            [FilterComparisonParam::Integer(42), FilterComparisonParam::String(tenant)] if tenant == "acme"
+        ));
+        assert_eq!(key.to_key_string(), "42-acme");
+    }
+}
@@ -0,0 +1,198 @@
+use std::{fmt::Debug, sync::OnceLock};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{data_definition::exp_data_system::DataSystem, queries::Insertable, Error};
+
+use super::{rest_api::Id, PrimaryKey};
+
+/// A lazily loaded relationship. Only the related object's key is stored with (and read from) the
+/// parent's table - the object itself is fetched through the `DataSystem` on first access, and cached.
+///
+/// An alternative to a plain one-to-one field (which is always joined) for rarely used relations:
+/// ```ignore
+/// #[derive(..)]
+/// struct Order {
+///     id: Uuid,
+///     customer: Related<Customer>,
+/// }
+///
+/// let customer = order.customer.load(&data_system).await?;
+/// ```
+///
+/// `T` can be keyed by any single-column key (see `RelationKey`). Serializes as the related object's key.
+pub struct Related<T: Id> {
+    id: T::Key,
+    value: OnceLock<T>,
+}
+
+impl<T: Id> Related<T> {
+    pub fn new(id: T::Key) -> Self {
+        Self {
+            id,
+            value: OnceLock::new(),
+        }
+    }
+
+    pub fn id(&self) -> &T::Key {
+        &self.id
+    }
+
+    /// The related object, if it has already been loaded.
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    /// Returns the related object, fetching it on first access.
+    pub async fn load(
+        &self,
+        data_system: &DataSystem,
+    ) -> Result<&T, Error>
+    where
+        T: Clone + Insertable + for<'d> Deserialize<'d> + Send + 'static,
+    {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let provider = data_system.get::<T>().ok_or_else(|| {
+            Error::Unsupported(format!(
+                "{} is not a resource in this DataSystem",
+                std::any::type_name::<T>()
+            ))
+        })?;
+        let value = provider.get_by_id(self.id.clone()).await?.ok_or_else(|| {
+            Error::NotFound(format!(
+                "No {} found with id {}",
+                provider.table_definition.table_name,
+                self.id.to_key_string()
+            ))
+        })?;
+        // If another caller got here first, theirs wins - it's the same object either way.
+        Ok(self.value.get_or_init(|| value))
+    }
+}
+
+/// Relates to an object that's already in hand, so it doesn't need to be loaded again. Use `Related::new()` to
+/// relate by key.
+impl<T: Id> From<T> for Related<T> {
+    fn from(value: T) -> Self {
+        Self {
+            id: value.id(),
+            value: OnceLock::from(value),
+        }
+    }
+}
+
+impl<T: Id + Clone> Clone for Related<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T: Id> Default for Related<T>
+where
+    T::Key: Default,
+{
+    fn default() -> Self {
+        Self::new(T::Key::default())
+    }
+}
+
+impl<T: Id> Debug for Related<T> {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.debug_struct("Related")
+            .field("id", &self.id)
+            .field("loaded", &self.value.get().is_some())
+            .finish()
+    }
+}
+
+impl<T: Id> PartialEq for Related<T> {
+    fn eq(
+        &self,
+        other: &Self,
+    ) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T: Id> Serialize for Related<T>
+where
+    T::Key: Serialize,
+{
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.id.serialize(serializer)
+    }
+}
+
+impl<'de, T: Id> Deserialize<'de> for Related<T>
+where
+    T::Key: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::Key::deserialize(deserializer).map(Self::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::data_manager::rest_api::Id;
+
+    use super::Related;
+
+    struct Customer {
+        id: Uuid,
+    }
+    impl Id for Customer {
+        type Key = Uuid;
+
+        fn id(&self) -> Uuid {
+            self.id
+        }
+    }
+
+    struct Country {
+        code: String,
+    }
+    impl Id for Country {
+        type Key = String;
+
+        fn id(&self) -> String {
+            self.code.clone()
+        }
+    }
+
+    #[test]
+    fn related_round_trips_as_its_id() {
+        let id = Uuid::new_v4();
+        let related = Related::<Customer>::new(id);
+
+        let json = serde_json::to_string(&related).unwrap();
+        assert_eq!(json, format!("\"{id}\""));
+        let round_tripped: Related<Customer> = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped.id(), &id);
+        assert!(round_tripped.get().is_none());
+    }
+
+    #[test]
+    fn related_objects_can_have_any_key() {
+        let related = Related::from(Country {
+            code: "NO".to_string(),
+        });
+
+        assert_eq!(related.id(), "NO");
+        assert_eq!(serde_json::to_string(&related).unwrap(), "\"NO\"");
+        assert!(related.get().is_some());
+    }
+}
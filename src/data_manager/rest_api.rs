@@ -1,11 +1,10 @@
 use reqwest::{self};
 use serde::{Deserialize, Serialize};
 use std::marker::PhantomData;
-use uuid::Uuid;
 
 use crate::queries::filterable_types::Filterable;
 
-use super::traits::DataProvider;
+use super::{traits::DataProvider, PrimaryKey};
 
 /// Creates a DataProvider that will fetch the given type from the provided REST endpoint.
 /// Makes specific assumptions about the way the REST endpoint works, and implements this for the base-case
@@ -34,7 +33,10 @@ where
 }
 
 pub trait Id {
-    fn id(&self) -> &Uuid;
+    /// `Uuid` for the default `id` field - or the type of the `#[primary_key]` field(s), as a tuple for composite keys.
+    type Key: PrimaryKey;
+
+    fn id(&self) -> Self::Key;
 }
 
 impl<T> DataProvider<T> for RestApiDataProvider<T>
@@ -49,11 +51,10 @@ where
             .http_client
             .get(&self.endpoint)
             .send()
-            .await
-            .unwrap()
+            .await?
+            .error_for_status()?
             .json::<Vec<T>>()
-            .await
-            .unwrap()
+            .await?
             .into_iter())
     }
 
@@ -68,14 +69,21 @@ where
         // // } else {
         // // }
         // Ok(response.json::<Option<T>>().await?)
-        todo!()
+        Err(crate::Error::Unsupported(
+            "Filtered gets are not supported by RestApiDataProvider".to_string(),
+        ))
     }
 
     async fn create(
         &self,
         item: Self::CreateRequest,
     ) -> Result<T, crate::Error> {
-        let _response = self.http_client.post(&self.endpoint).json(&item).send().await?;
+        self.http_client
+            .post(&self.endpoint)
+            .json(&item)
+            .send()
+            .await?
+            .error_for_status()?;
         Ok(item)
     }
 
@@ -85,7 +93,7 @@ where
     ) -> Result<(), crate::Error> {
         // let url = format!("{}/{}", &self.endpoint, &item.id());
         let url = &self.endpoint;
-        let _response = self.http_client.delete(url).json(&item).send().await.unwrap();
+        self.http_client.delete(url).json(&item).send().await?.error_for_status()?;
         Ok(())
     }
 
@@ -95,7 +103,7 @@ where
     ) -> Result<(), crate::Error> {
         // let url = format!("{}/{}", &self.endpoint, &item.id());
         let url = &self.endpoint;
-        let _response = self.http_client.patch(url).json(item).send().await.unwrap();
+        self.http_client.patch(url).json(item).send().await?.error_for_status()?;
         Ok(())
     }
 }
@@ -18,6 +18,31 @@ pub enum Error {
     MutexLock(String),
     IoError(std::io::Error),
     InvalidPath,
+    /// The requested object doesn't exist (or no rows were affected).
+    NotFound(String),
+    /// A unique or primary key constraint was violated, i.e. the object already exists. SQLSTATE `23505`.
+    UniqueViolation {
+        constraint: Option<String>,
+        message: String,
+    },
+    /// A referenced object doesn't exist, or is still referenced. SQLSTATE `23503`.
+    ForeignKeyViolation {
+        constraint: Option<String>,
+        message: String,
+    },
+    /// A value couldn't be deserialized. `column` is the path to the offending field (e.g. `address.zip_code`).
+    Deserialize {
+        column: String,
+        message: String,
+    },
+    /// A value couldn't be serialized.
+    Serialize(String),
+    /// The data was rejected, e.g. by a `NOT NULL` or `CHECK` constraint, or an invalid input value.
+    Validation(String),
+    /// The operation isn't supported by this provider.
+    Unsupported(String),
+    /// The tables in a data system are inconsistent - see `DataSystemBuilder::build()`.
+    InvalidSchema(Vec<data_definition::validation::Diagnostic>),
 }
 pub type OrmError = Error;
 pub type OrmResult<T> = Result<T, OrmError>;
@@ -30,7 +55,6 @@ macro_rules! impl_from {
         }
     };
 }
-impl_from!(Error::Sqlx(sqlx::Error));
 impl_from!(Error::IoError(std::io::Error));
 impl_from!(Error::Unknown(String));
 impl From<&str> for Error {
@@ -38,7 +62,59 @@ impl From<&str> for Error {
         Self::Unknown(val.into())
     }
 }
-impl_from!(Error::HttpError(reqwest::Error));
+
+impl From<sqlx::Error> for Error {
+    fn from(val: sqlx::Error) -> Self {
+        match &val {
+            sqlx::Error::RowNotFound => Self::NotFound(val.to_string()),
+            sqlx::Error::Database(db_error) => {
+                let constraint = db_error.constraint().map(str::to_string);
+                let message = db_error.message().to_string();
+                match db_error.code().as_deref() {
+                    Some("23505") => Self::UniqueViolation {
+                        constraint,
+                        message,
+                    },
+                    Some("23503") => Self::ForeignKeyViolation {
+                        constraint,
+                        message,
+                    },
+                    // not_null_violation, check_violation, and the whole data_exception class (bad input values)
+                    Some("23502" | "23514") => Self::Validation(message),
+                    Some(code) if code.starts_with("22") => Self::Validation(message),
+                    _ => Self::Sqlx(val),
+                }
+            },
+            _ => Self::Sqlx(val),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(val: reqwest::Error) -> Self {
+        match val.status() {
+            Some(reqwest::StatusCode::NOT_FOUND) => Self::NotFound(val.to_string()),
+            Some(reqwest::StatusCode::CONFLICT) => Self::UniqueViolation {
+                constraint: None,
+                message: val.to_string(),
+            },
+            Some(reqwest::StatusCode::BAD_REQUEST | reqwest::StatusCode::UNPROCESSABLE_ENTITY) => {
+                Self::Validation(val.to_string())
+            },
+            _ => Self::HttpError(val),
+        }
+    }
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for Error {
+    fn from(val: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        Self::Deserialize {
+            column: val.path().to_string(),
+            message: val.into_inner().to_string(),
+        }
+    }
+}
+
 impl Display for Error {
     fn fmt(
         &self,
@@ -48,6 +124,8 @@ impl Display for Error {
     }
 }
 
+impl std::error::Error for Error {}
+
 // impl<'a, T> From<LockResult<MutexGuard<'a, T>>> for Error {
 //     fn from(value: LockResult<MutexGuard<'a, T>>) -> Self {
 //         Self::MutexLock(value.to_string())
@@ -6,6 +6,8 @@ use crate::{
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum AlterTableAction {
     Rename(Identifier),
+    /// Moves the table to another schema (`public` for the default).
+    SetSchema(Identifier),
     AddColumn(TableColumn),   // TODO
     DropColumn(Identifier),   // TODO
     AlterColumn(AlterColumn), // TODO
@@ -27,6 +29,9 @@ impl BuildSql for AlterTableAction {
             E::Rename(ident) => {
                 sql.push("RENAME TO ").push(ident.to_string());
             },
+            E::SetSchema(schema) => {
+                sql.push("SET SCHEMA ").push(schema.to_string());
+            },
             E::AddColumn(table_column) => {
                 sql.push("ADD COLUMN IF NOT EXISTS ");
                 table_column.build_sql(sql);
@@ -85,15 +90,22 @@ impl BuildSql for AlterTable {
 pub enum AlterColumnAction {
     SetType(DatabaseColumnType), // TODO: Look at the other options here
     SetNullability(bool),        // True if nullable, False if not.
-                                 // _DropExpression,            // TODO: Unsupported yet
-                                 // _AddGenerated(),            // TODO: Unsupported yet
-                                 // _SetGenerated(),            // TODO: Unsupported yet
-                                 // _DropIdentity,              // TODO: Unsupported yet Always use IF EXISTS
-                                 // _SetStatistics(i64),        // TODO: Unsupported yet
-                                 // _SetAttribute(),            // TODO: Unsupported yet
-                                 // _Reset(),                   // TODO: Unsupported yet
-                                 // _SetStorage(StorageType),   // TODO: Unsupported yet
-                                 // _SetCompression(_CompressionMethod), // TODO: Unsupported yet
+    /// `SET DEFAULT <expression>`. The expression is raw SQL.
+    SetDefault(String),
+    DropDefault,
+    /// `SET EXPRESSION AS (<expression>)`, for changing a generated column. Requires PostgreSQL 17+.
+    SetExpression(String),
+    /// Turns a generated column into a regular one, keeping its current values.
+    DropExpression,
+    /// `ADD GENERATED BY DEFAULT AS IDENTITY`. Numbering starts at 1, so existing values may need the sequence restarted.
+    AddIdentity,
+    /// Turns an identity column into a regular one, keeping its current values.
+    DropIdentity,
+    // _SetStatistics(i64),        // TODO: Unsupported yet
+    // _SetAttribute(),            // TODO: Unsupported yet
+    // _Reset(),                   // TODO: Unsupported yet
+    // _SetStorage(StorageType),   // TODO: Unsupported yet
+    // _SetCompression(_CompressionMethod), // TODO: Unsupported yet
 }
 
 impl BuildSql for AlterColumnAction {
@@ -111,6 +123,14 @@ impl BuildSql for AlterColumnAction {
                     sql.push("SET NOT NULL")
                 }
             },
+            AlterColumnAction::SetDefault(expression) => sql.push("SET DEFAULT ").push(expression),
+            AlterColumnAction::DropDefault => sql.push("DROP DEFAULT"),
+            AlterColumnAction::SetExpression(expression) => {
+                sql.push(format!("SET EXPRESSION AS ({expression})"))
+            },
+            AlterColumnAction::DropExpression => sql.push("DROP EXPRESSION IF EXISTS"),
+            AlterColumnAction::AddIdentity => sql.push("ADD GENERATED BY DEFAULT AS IDENTITY"),
+            AlterColumnAction::DropIdentity => sql.push("DROP IDENTITY IF EXISTS"),
         };
     }
 }
@@ -130,6 +150,26 @@ impl BuildSql for AlterColumn {
         while let Some(action) = actions.next() {
             sql.push("ALTER COLUMN ").push(self.column_name.to_string()).push(" ");
             action.build_sql(sql);
+            type E = DatabaseColumnType;
+            match action {
+                // Nothing is implicitly cast to an enum, so existing values are converted through their text.
+                AlterColumnAction::SetType(E::Enum(enum_type)) => {
+                    sql.push(format!(" USING {}::text::{}", self.column_name, enum_type.name));
+                },
+                // Most conversions to these (e.g. from VARCHAR) need an explicit cast.
+                AlterColumnAction::SetType(
+                    column_type @ (E::Numeric
+                    | E::Date
+                    | E::Time
+                    | E::TimestampTz
+                    | E::Bytea
+                    | E::Interval
+                    | E::Array(_)),
+                ) => {
+                    sql.push(format!(" USING {}::{}", self.column_name, column_type.as_str()));
+                },
+                _ => {},
+            }
             if actions.peek().is_some() {
                 sql.push(", ");
             }
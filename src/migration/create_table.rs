@@ -5,7 +5,7 @@ use crate::{
 
 #[derive(Clone)]
 pub struct CreateTable {
-    table_definition: TableDef,
+    pub(crate) table_definition: TableDef,
 }
 
 impl CreateTable {
@@ -23,19 +23,13 @@ impl BuildSql for CreateTable {
     ) {
         sql.push("CREATE TABLE IF NOT EXISTS ");
         // sql.push_bind(self.table_definition.table_name.to_string());
-        sql.push(self.table_definition.table_name().to_string());
+        sql.push(self.table_definition.qualified_name().to_string());
         sql.push(" (");
         let mut columns = self
             .table_definition
             .columns()
             .values()
-            .filter(|col| match &col.column_type {
-                crate::data_definition::table::DatabaseColumnType::OneToMany(_identifier)
-                | crate::data_definition::table::DatabaseColumnType::ManyToMany(_identifier) => {
-                    false
-                },
-                _ => true,
-            })
+            .filter(|col| !col.column_type.is_stored_in_other_table())
             .peekable();
         while let Some(column) = columns.next() {
             column.build_sql(sql);
@@ -43,6 +37,10 @@ impl BuildSql for CreateTable {
                 sql.push(",");
             }
         }
+        for constraint in self.table_definition.constraints() {
+            sql.push(", ");
+            constraint.build_sql(sql);
+        }
         sql.push(");");
     }
 }
@@ -62,8 +60,11 @@ mod test {
     use create_table::CreateTable;
 
     use crate::{
-        data_definition::table::{DatabaseTableDefinition, Identifier, TableColumn},
+        data_definition::table::{
+            DatabaseTableDefinition, Identifier, TableColumn, TableConstraint,
+        },
         migration::create_table,
+        queries::filterable_types::{FilterEq, FilterPartialEq, FilterableType},
         AsSql,
     };
 
@@ -106,6 +107,124 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn create_join_table_links_both_sides() {
+        let article = DatabaseTableDefinition::new("article")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk());
+        let tag = DatabaseTableDefinition::new("tag")
+            .unwrap()
+            .column(TableColumn::string("name").unwrap().non_null().pk());
+        let join_table =
+            DatabaseTableDefinition::join_table(&article, &Identifier::new_unchecked("tags"), &tag)
+                .unwrap();
+        let create_table = CreateTable {
+            table_definition: Arc::new(join_table),
+        };
+
+        #[rustfmt::skip]
+        let expected_query = ["CREATE TABLE IF NOT EXISTS article_tags_to_tag (",
+                "child_id VARCHAR NOT NULL REFERENCES tag (name) ON DELETE CASCADE,",
+                "parent_id UUID NOT NULL REFERENCES article (id) ON DELETE CASCADE, ",
+                "PRIMARY KEY (parent_id, child_id)",
+            ");"].join("");
+
+        assert_eq!(create_table.as_sql(), expected_query);
+    }
+
+    #[test]
+    fn self_referential_join_tables_have_distinct_columns() {
+        let category = DatabaseTableDefinition::new("category")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk());
+        let related = DatabaseTableDefinition::join_table(
+            &category,
+            &Identifier::new_unchecked("related"),
+            &category,
+        )
+        .unwrap();
+        let see_also = DatabaseTableDefinition::join_table(
+            &category,
+            &Identifier::new_unchecked("see_also"),
+            &category,
+        )
+        .unwrap();
+
+        assert_eq!(related.columns.len(), 2);
+        assert_ne!(related.table_name, see_also.table_name);
+        #[rustfmt::skip]
+        let expected_query = ["CREATE TABLE IF NOT EXISTS category_related_to_category (",
+                "child_id UUID NOT NULL REFERENCES category (id) ON DELETE CASCADE,",
+                "parent_id UUID NOT NULL REFERENCES category (id) ON DELETE CASCADE, ",
+                "PRIMARY KEY (parent_id, child_id)",
+            ");"].join("");
+        let create_table = CreateTable {
+            table_definition: Arc::new(related),
+        };
+        assert_eq!(create_table.as_sql(), expected_query);
+    }
+
+    #[test]
+    fn create_table_renders_check_constraints_inline() {
+        let quantity = FilterableType::<i64>::new(Identifier::new_unchecked("line_item.quantity"));
+        let status = FilterableType::<String>::new(Identifier::new_unchecked("line_item.status"));
+        let table = DatabaseTableDefinition::new("line_item")
+            .unwrap()
+            .column(TableColumn::int("quantity").unwrap().non_null().check(&quantity.gt(0)))
+            .column(TableColumn::string("status").unwrap())
+            .constraint(TableConstraint::check(
+                Identifier::new_unchecked("line_item_status_check"),
+                &(status.eq("it's open") | status.eq("closed")),
+            ));
+        let create_table = CreateTable {
+            table_definition: Arc::new(table),
+        };
+
+        #[rustfmt::skip]
+        let expected_query = ["CREATE TABLE IF NOT EXISTS line_item (",
+                "quantity INT NOT NULL CHECK (line_item.quantity > 0),",
+                "status VARCHAR, ",
+                "CONSTRAINT line_item_status_check CHECK ((line_item.status = 'it''s open' OR line_item.status = 'closed'))",
+            ");"].join("");
+
+        assert_eq!(create_table.as_sql(), expected_query);
+    }
+
+    #[test]
+    fn create_table_renders_unique_and_foreign_key_constraints() {
+        let tenant_id: TableColumn = TableColumn::uuid("tenant_id").unwrap().non_null().into();
+        let email: TableColumn = TableColumn::string("email").unwrap().non_null().into();
+        let table = DatabaseTableDefinition::new("member")
+            .unwrap()
+            .column(tenant_id.clone())
+            .column(email.clone())
+            .column(TableColumn::string("handle").unwrap().unique())
+            .constraint(TableConstraint::unique(
+                Identifier::new_unchecked("member_tenant_id_email_key"),
+                vec![tenant_id.clone(), email],
+            ))
+            .constraint(TableConstraint::foreign_key(
+                Identifier::new_unchecked("member_tenant_id_fkey"),
+                vec![tenant_id],
+                Identifier::new_unchecked("tenant"),
+                vec![Identifier::new_unchecked("id")],
+            ));
+        let create_table = CreateTable {
+            table_definition: Arc::new(table),
+        };
+
+        #[rustfmt::skip]
+        let expected_query = ["CREATE TABLE IF NOT EXISTS member (",
+                "email VARCHAR NOT NULL,",
+                "handle VARCHAR UNIQUE,",
+                "tenant_id UUID NOT NULL, ",
+                "CONSTRAINT member_tenant_id_email_key UNIQUE (tenant_id, email), ",
+                "CONSTRAINT member_tenant_id_fkey FOREIGN KEY (tenant_id) REFERENCES tenant (id)",
+            ");"].join("");
+
+        assert_eq!(create_table.as_sql(), expected_query);
+    }
+
     //     #[test]
     //     fn create_table_one_to_one_works() -> Result<(), String> {
     //         let child_table: DatabaseTableDefinition<()> =
@@ -6,8 +6,10 @@ use crate::{
     data_definition::{
         exp_data_system::TableDef,
         table::{
-            raw_data::TableDefinition, ForeignKeyConstraint, Identifier, TableColumn,
-            TableConstraint, TableConstraintDetail,
+            raw_data::TableDefinition, CheckExpressionConstraint, CreateIndex, DatabaseColumnType,
+            DatabaseTableDefinition, DropIndex, EnumType, ForeignKeyConstraint, Identifier,
+            ReferencesConstraint, TableColumn, TableColumnConstraintDetail, TableConstraint,
+            TableConstraintDetail,
         },
     },
     migration::{AlterColumn, AlterColumnAction, AlterTableAction},
@@ -16,11 +18,56 @@ use crate::{
 
 use super::{AlterTable, CreateTable};
 
+/// A column's checks, as a table constraint. Multiple checks on the same column are combined.
+fn column_check(
+    name: Identifier,
+    expressions: &[&str],
+) -> TableConstraint {
+    let expression = match expressions {
+        [expression] => expression.to_string(),
+        _ => expressions.iter().map(|e| format!("({e})")).collect::<Vec<_>>().join(" AND "),
+    };
+    TableConstraint {
+        name: Some(name),
+        detail: Arc::new(TableConstraintDetail::Check(CheckExpressionConstraint {
+            expression,
+        })),
+    }
+}
+
 #[derive(Clone)]
 pub enum MigrationAction {
+    /// `CREATE SCHEMA IF NOT EXISTS`. Schemas are never dropped, as they may still hold other tables.
+    CreateSchema(Identifier),
     AlterTable(AlterTable),
     CreateTable(CreateTable),
     DropTable(Identifier),
+    CreateIndex(CreateIndex),
+    DropIndex(DropIndex),
+    CreateEnum(EnumType),
+    /// `ALTER TYPE ... ADD VALUE`, placed after `after` (or first, if `None`) to keep the declaration order.
+    AddEnumValue {
+        type_name: Identifier,
+        variant: String,
+        after: Option<String>,
+    },
+}
+
+impl MigrationAction {
+    /// Whether the action has to run outside of a transaction, i.e. `CREATE INDEX CONCURRENTLY`.
+    pub fn is_concurrent(&self) -> bool {
+        match self {
+            MigrationAction::CreateIndex(create_index) => create_index.concurrently,
+            MigrationAction::DropIndex(drop_index) => drop_index.concurrently,
+            _ => false,
+        }
+    }
+
+    /// Whether the action adds a value to an existing enum type. A new value can't be used in the transaction that
+    /// adds it, so these are added before the rest of the migration, one at a time.
+    pub fn is_enum_value(&self) -> bool {
+        matches!(self, MigrationAction::AddEnumValue { .. })
+    }
 }
 
 impl BuildSql for MigrationAction {
@@ -29,11 +76,32 @@ impl BuildSql for MigrationAction {
         builder: &mut sqlx::QueryBuilder<'_, Postgres>,
     ) {
         match self {
+            MigrationAction::CreateSchema(schema) => {
+                builder.push(format!("CREATE SCHEMA IF NOT EXISTS {schema};"));
+            },
             MigrationAction::AlterTable(alter_table) => alter_table.build_sql(builder),
             MigrationAction::CreateTable(create_table) => create_table.build_sql(builder),
             MigrationAction::DropTable(table_ident) => {
                 builder.push("DROP TABLE IF EXISTS ").push(&**table_ident);
             },
+            MigrationAction::CreateIndex(create_index) => create_index.build_sql(builder),
+            MigrationAction::DropIndex(drop_index) => drop_index.build_sql(builder),
+            MigrationAction::CreateEnum(enum_type) => enum_type.build_sql(builder),
+            MigrationAction::AddEnumValue {
+                type_name,
+                variant,
+                after,
+            } => {
+                let quote = |variant: &str| format!("'{}'", variant.replace('\'', "''"));
+                builder.push(format!(
+                    "ALTER TYPE {type_name} ADD VALUE IF NOT EXISTS {}",
+                    quote(variant)
+                ));
+                match after {
+                    Some(after) => builder.push(format!(" AFTER {};", quote(after))),
+                    None => builder.push(";"),
+                };
+            },
         };
     }
 }
@@ -44,13 +112,40 @@ pub struct Migration {
 }
 
 impl Migration {
+    /// Runs the migration. Everything runs in a single transaction, except for new enum values and concurrent index
+    /// changes. New enum values run first, so that the transaction can use them, and concurrent index changes run
+    /// afterwards, as Postgres refuses to run those in a transaction. Both run one at a time.
     pub async fn run(
         self,
         db_pool: &Pool<Postgres>,
     ) -> Result<(), crate::Error> {
-        let mut builder = sqlx::QueryBuilder::new("");
-        self.build_sql(&mut builder);
-        builder.build().execute(db_pool).await?;
+        let (enum_values, actions): (Vec<_>, Vec<_>) =
+            self.actions.into_iter().partition(MigrationAction::is_enum_value);
+        let (concurrent, transactional): (Vec<_>, Vec<_>) =
+            actions.into_iter().partition(MigrationAction::is_concurrent);
+
+        for action in enum_values {
+            let mut builder = sqlx::QueryBuilder::new("");
+            action.build_sql(&mut builder);
+            log::debug!("SQL query: {}", builder.sql());
+            sqlx::raw_sql(builder.sql()).execute(db_pool).await?;
+        }
+
+        let mut transaction = db_pool.begin().await?;
+        for action in transactional {
+            let mut builder = sqlx::QueryBuilder::new("");
+            action.build_sql(&mut builder);
+            log::debug!("SQL query: {}", builder.sql());
+            sqlx::raw_sql(builder.sql()).execute(&mut *transaction).await?;
+        }
+        transaction.commit().await?;
+
+        for action in concurrent {
+            let mut builder = sqlx::QueryBuilder::new("");
+            action.build_sql(&mut builder);
+            log::debug!("SQL query: {}", builder.sql());
+            sqlx::raw_sql(builder.sql()).execute(db_pool).await?;
+        }
 
         Ok(())
     }
@@ -73,11 +168,18 @@ impl Migration {
         before: Option<Vec<TableDef>>,
         after: Vec<TableDef>,
     ) -> Option<Self> {
-        let mut actions: Vec<MigrationAction> = Vec::new();
+        let before = before.map(Self::qualify_references);
+        let after = Self::qualify_references(after);
+
+        // Schemas and types are created first, so that the tables can use them.
+        let mut actions: Vec<MigrationAction> =
+            Self::compare_schemas(before.as_deref().unwrap_or_default(), &after);
+        actions.append(&mut Self::compare_enums(before.as_deref().unwrap_or_default(), &after));
 
+        // Keyed on the qualified name, as tables in different schemas can share a name.
         fn build_table_map(db_def: Vec<TableDef>) -> HashMap<Identifier, TableDef> {
             let map = db_def.iter().fold(HashMap::new(), |mut acc, table| {
-                acc.insert(table.table_name(), table.clone());
+                acc.insert(table.qualified_name(), table.clone());
                 acc
             });
             map
@@ -86,9 +188,32 @@ impl Migration {
         if let Some(before) = before {
             // Build a map for quick lookup of after_tables, then compare each
             let mut after_tables = build_table_map(after);
+            // A table that moved schema is matched on its bare name - as long as only one table, before and after,
+            // has that name.
+            let moved_tables = before
+                .iter()
+                .filter(|table| !after_tables.contains_key(&table.qualified_name()))
+                .filter(|table| {
+                    before.iter().filter(|other| other.table_name == table.table_name).count() == 1
+                })
+                .filter_map(|table| {
+                    let mut same_name =
+                        after_tables.values().filter(|after| after.table_name == table.table_name);
+                    match (same_name.next(), same_name.next()) {
+                        (Some(after), None) => {
+                            Some((table.qualified_name(), after.qualified_name()))
+                        },
+                        _ => None,
+                    }
+                })
+                .collect::<HashMap<_, _>>();
             for table_before in before {
+                let after_name = moved_tables
+                    .get(&table_before.qualified_name())
+                    .cloned()
+                    .unwrap_or_else(|| table_before.qualified_name());
                 match after_tables
-                    .remove(&table_before.table_name())
+                    .remove(&after_name)
                     .map(|after_table| Self::compare_tables(table_before.clone(), after_table))
                 {
                     Some(None) => {
@@ -106,7 +231,7 @@ impl Migration {
                         println!("[DELETE TABLE] {}", table_before.table_name());
                         // Table was not found in new tables, meaning it was deleted
                         // TODO: At the end, compare any deleted/added tables to see if there was just some renaming done.
-                        let action = MigrationAction::DropTable(table_before.table_name().clone());
+                        let action = MigrationAction::DropTable(table_before.qualified_name());
                         actions.push(action);
                     },
                 };
@@ -114,20 +239,40 @@ impl Migration {
 
             // Any remaining tables are new - add the CreateTable actions
             actions.append(
-                &mut after_tables
-                    .values()
-                    .map(|table| MigrationAction::CreateTable(CreateTable::new(table.clone()))) //(*table).clone())))
+                &mut Self::order_by_references(after_tables.into_values().collect())
+                    .into_iter()
+                    .map(|table| MigrationAction::CreateTable(CreateTable::new(table))) //(*table).clone())))
                     .collect(),
-            )
+            );
         } else {
             // New database - only creates!
-            let mut create_table_actions = after
-                .iter()
-                .map(|t| MigrationAction::CreateTable(CreateTable::new(t.clone())))
+            let mut create_table_actions = Self::order_by_references(after)
+                .into_iter()
+                .map(|t| MigrationAction::CreateTable(CreateTable::new(t)))
                 .collect();
             actions.append(&mut create_table_actions);
         }
 
+        // New tables are empty, so their indexes don't need to be built concurrently.
+        let mut create_index_actions = actions
+            .iter()
+            .filter_map(|action| match action {
+                MigrationAction::CreateTable(create_table) => Some(create_table),
+                _ => None,
+            })
+            .flat_map(|create_table| {
+                let table = &create_table.table_definition;
+                table.indexes().iter().map(|index| {
+                    MigrationAction::CreateIndex(CreateIndex {
+                        table_name: table.qualified_name(),
+                        index: index.clone(),
+                        concurrently: false,
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        actions.append(&mut create_index_actions);
+
         if !actions.is_empty() {
             Some(Self {
                 actions,
@@ -137,6 +282,142 @@ impl Migration {
         }
     }
 
+    /// Creates any schemas that tables in `after` are in, but no tables in `before` were.
+    fn compare_schemas(
+        before: &[TableDef],
+        after: &[TableDef],
+    ) -> Vec<MigrationAction> {
+        let mut schemas = after
+            .iter()
+            .filter_map(|table| table.schema.clone())
+            .filter(|schema| !before.iter().any(|table| table.schema.as_ref() == Some(schema)))
+            .collect::<Vec<_>>();
+        schemas.sort();
+        schemas.dedup();
+        schemas.into_iter().map(MigrationAction::CreateSchema).collect()
+    }
+
+    /// Qualifies the tables referenced by foreign keys with their schema, for any of `tables` that are in one.
+    /// References are resolved as in `DatabaseTableDefinition::resolve_table()`, and left as they are if they can't be.
+    fn qualify_references(tables: Vec<TableDef>) -> Vec<TableDef> {
+        if tables.iter().all(|table| table.schema.is_none()) {
+            return tables;
+        }
+        let definitions = tables.clone();
+
+        tables
+            .into_iter()
+            .map(|table| {
+                let qualify = |ref_table: &Identifier| {
+                    DatabaseTableDefinition::resolve_table(
+                        definitions.iter().map(|table| &**table),
+                        ref_table,
+                        table.schema.as_ref(),
+                    )
+                    .map(DatabaseTableDefinition::qualified_name)
+                    .unwrap_or_else(|| ref_table.clone())
+                };
+                let mut table = (*table).clone();
+                for column in table.columns.values_mut() {
+                    let mut data = (**column).clone();
+                    for constraint in data.constraints.iter_mut() {
+                        if let TableColumnConstraintDetail::References(fk) = &*constraint.detail {
+                            constraint.detail = Arc::new(TableColumnConstraintDetail::References(
+                                ReferencesConstraint {
+                                    ref_table: qualify(&fk.ref_table),
+                                    ..fk.clone()
+                                },
+                            ));
+                        }
+                    }
+                    *column = data.into();
+                }
+                for constraint in table.constraints.iter_mut() {
+                    if let TableConstraintDetail::ForeignKey(fk) = &*constraint.detail {
+                        constraint.detail =
+                            Arc::new(TableConstraintDetail::ForeignKey(ForeignKeyConstraint {
+                                ref_table: qualify(&fk.ref_table),
+                                ..fk.clone()
+                            }));
+                    }
+                }
+                Arc::new(table)
+            })
+            .collect()
+    }
+
+    /// Creates new enum types, and adds any new variants to existing ones. Variants are never removed, as
+    /// Postgres can't drop them, and types are never dropped, as they may still be used elsewhere.
+    fn compare_enums(
+        before: &[TableDef],
+        after: &[TableDef],
+    ) -> Vec<MigrationAction> {
+        fn enum_types(tables: &[TableDef]) -> Vec<EnumType> {
+            let mut enum_types: Vec<EnumType> = Vec::new();
+            let columns = tables.iter().flat_map(|table| table.columns().values());
+            for column in columns {
+                if let DatabaseColumnType::Enum(enum_type) = &column.column_type {
+                    if !enum_types.iter().any(|e| e.name == enum_type.name) {
+                        enum_types.push(enum_type.clone());
+                    }
+                }
+            }
+            enum_types.sort_by(|a, b| a.name.cmp(&b.name));
+            enum_types
+        }
+
+        let before = enum_types(before);
+        let mut actions = Vec::new();
+        for enum_type in enum_types(after) {
+            let Some(existing) = before.iter().find(|e| e.name == enum_type.name) else {
+                actions.push(MigrationAction::CreateEnum(enum_type));
+                continue;
+            };
+            for (i, variant) in enum_type.variants.iter().enumerate() {
+                if !existing.variants.contains(variant) {
+                    actions.push(MigrationAction::AddEnumValue {
+                        type_name: enum_type.name.clone(),
+                        variant: variant.clone(),
+                        after: i.checked_sub(1).map(|i| enum_type.variants[i].clone()),
+                    });
+                }
+            }
+        }
+        actions
+    }
+
+    /// Orders new tables so that each is created after any of the others it references (e.g. join tables
+    /// after both sides of the relationship). Tables outside of `tables` are assumed to already exist.
+    fn order_by_references(mut tables: Vec<TableDef>) -> Vec<TableDef> {
+        fn references(table: &TableDef) -> Vec<Identifier> {
+            table
+                .columns()
+                .values()
+                .flat_map(|column| column.constraints.iter())
+                .filter_map(|constraint| match &*constraint.detail {
+                    TableColumnConstraintDetail::References(fk) => Some(fk.ref_table.clone()),
+                    _ => None,
+                })
+                .filter(|ref_table| ref_table != &table.qualified_name())
+                .collect()
+        }
+
+        // Sorted first, so that the order doesn't depend on HashMap iteration.
+        tables.sort_by_key(|table| table.table_name());
+        let mut ordered: Vec<TableDef> = Vec::with_capacity(tables.len());
+        while !tables.is_empty() {
+            let is_ready = |table: &TableDef| {
+                references(table)
+                    .iter()
+                    .all(|ref_table| !tables.iter().any(|t| &t.qualified_name() == ref_table))
+            };
+            // A reference cycle can't be ordered - create the rest as-is, and let Postgres report it.
+            let next = tables.iter().position(is_ready).unwrap_or(0);
+            ordered.push(tables.remove(next));
+        }
+        ordered
+    }
+
     /// Returns `Some<Migration>` representing the steps required to go from `before` to `after`, or None if the inputs are the same.
     ///
     /// # Arguments
@@ -151,6 +432,17 @@ impl Migration {
         after: TableDef,
     ) -> Option<Self> {
         let mut actions = Vec::<AlterTableAction>::new();
+        let mut migration_actions = Vec::new();
+
+        // Schema changed - moved on its own, so that the other changes can use the new name.
+        if before.schema != after.schema {
+            let schema =
+                after.schema.clone().unwrap_or_else(|| Identifier::new_unchecked("public"));
+            migration_actions.push(MigrationAction::AlterTable(AlterTable {
+                table_name: before.qualified_name(),
+                actions: vec![AlterTableAction::SetSchema(schema)],
+            }));
+        }
 
         // Name changed
         if before.table_name() != after.table_name() {
@@ -158,15 +450,24 @@ impl Migration {
         }
 
         // Build a map for quick lookup of after_tables, then compare each
-        let mut after_columns: HashMap<&Identifier, &TableColumn> =
-            after.columns().iter().collect();
-        for old_column in before.columns().values() {
+        // Relationships stored in another table have no column to migrate.
+        let mut after_columns: HashMap<&Identifier, &TableColumn> = after
+            .columns()
+            .iter()
+            .filter(|(_, column)| !column.column_type.is_stored_in_other_table())
+            .collect();
+        for old_column in before
+            .columns()
+            .values()
+            .filter(|column| !column.column_type.is_stored_in_other_table())
+        {
             match after_columns.remove(&old_column.column_name) {
                 Some(new_column) => {
                     let mut alter_column_actions = Vec::new();
-                    if !old_column.column_type.eq(&new_column.column_type) {
+                    // Compared by what's stored, so that resolving a relationship's key type isn't a change.
+                    if old_column.stored_type() != new_column.stored_type() {
                         alter_column_actions
-                            .push(AlterColumnAction::SetType(new_column.column_type.clone()));
+                            .push(AlterColumnAction::SetType(new_column.stored_type()));
                     }
 
                     // * NONNULL calculation - Compares `NotNull`
@@ -191,6 +492,82 @@ impl Migration {
                         }
                     }
 
+                    // * DEFAULT calculation
+                    match (old_column.default_expression(), new_column.default_expression()) {
+                        (old, Some(new)) if old != Some(new) => alter_column_actions
+                            .push(AlterColumnAction::SetDefault(new.to_string())),
+                        (Some(_), None) => {
+                            alter_column_actions.push(AlterColumnAction::DropDefault)
+                        },
+                        _ => {},
+                    }
+
+                    // * GENERATED calculation
+                    match (old_column.generated_expression(), new_column.generated_expression()) {
+                        (Some(old), Some(new)) if old != new => alter_column_actions
+                            .push(AlterColumnAction::SetExpression(new.to_string())),
+                        (Some(_), None) => {
+                            alter_column_actions.push(AlterColumnAction::DropExpression)
+                        },
+                        (None, Some(_)) => {
+                            // Existing columns can't be made generated. The values are computed anyway, so nothing is lost by re-adding it.
+                            actions
+                                .push(AlterTableAction::DropColumn(old_column.column_name.clone()));
+                            actions.push(AlterTableAction::AddColumn(new_column.clone()));
+                            continue;
+                        },
+                        _ => {},
+                    }
+
+                    // * IDENTITY calculation
+                    match (old_column.is_identity(), new_column.is_identity()) {
+                        (false, true) => alter_column_actions.push(AlterColumnAction::AddIdentity),
+                        (true, false) => alter_column_actions.push(AlterColumnAction::DropIdentity),
+                        _ => {},
+                    }
+
+                    // * CHECK calculation
+                    // Column checks can only be written inline when the column is created, so changes are
+                    // made through a table constraint - named the way Postgres names column checks.
+                    let (old_checks, new_checks) =
+                        (old_column.check_expressions(), new_column.check_expressions());
+                    if old_checks != new_checks {
+                        let name = Identifier::new_unchecked(format!(
+                            "{}_{}_check",
+                            after.table_name(),
+                            new_column.column_name
+                        ));
+                        if !old_checks.is_empty() {
+                            actions.push(AlterTableAction::DropConstraint(column_check(
+                                name.clone(),
+                                &old_checks,
+                            )));
+                        }
+                        if !new_checks.is_empty() {
+                            actions.push(AlterTableAction::AddConstraint(column_check(
+                                name,
+                                &new_checks,
+                            )));
+                        }
+                    }
+
+                    // * UNIQUE calculation
+                    // Like checks, changes are made through a table constraint named the way Postgres names it.
+                    if old_column.is_unique() != new_column.is_unique() {
+                        let constraint = TableConstraint::unique(
+                            Identifier::new_unchecked(format!(
+                                "{}_{}_key",
+                                after.table_name(),
+                                new_column.column_name
+                            )),
+                            vec![new_column.clone()],
+                        );
+                        actions.push(match new_column.is_unique() {
+                            true => AlterTableAction::AddConstraint(constraint),
+                            false => AlterTableAction::DropConstraint(constraint),
+                        });
+                    }
+
                     // * TODO: Foreign Key Changes
                     // We compare the columns, and add the _TABLE'S_ FK constraint, because we can't add the constraint to column except at creation.
                     // TODO: Do another pass for the FK constraints.
@@ -208,7 +585,11 @@ impl Migration {
                             (None, Some(new_fk)) => {
                                 // FK was added
                                 Some(AlterTableAction::AddConstraint(TableConstraint {
-                                    name: None, // TODO: Infer name. I might need to rework how these constraints work :facepalm:
+                                    name: Some(Identifier::new_unchecked(format!(
+                                        "{}_{}_fkey",
+                                        after.table_name(),
+                                        new_column.column_name
+                                    ))),
                                     detail: Arc::new(TableConstraintDetail::ForeignKey(
                                         ForeignKeyConstraint {
                                             ref_table: new_fk.ref_table.clone(),
@@ -255,12 +636,61 @@ impl Migration {
             actions.push(AlterTableAction::AddColumn((*column).clone()));
         }
 
+        // Table constraints can't be altered, so any change is a drop and re-add. Only named constraints
+        // can be dropped - primary keys are left alone, as changing them means rewriting the table anyway.
+        let is_migrated = |constraint: &&TableConstraint| {
+            constraint.name.is_some()
+                && !matches!(&*constraint.detail, TableConstraintDetail::PrimaryKey(_))
+        };
+        for old_constraint in before.constraints().iter().filter(is_migrated) {
+            if !after
+                .constraints()
+                .iter()
+                .any(|constraint| constraint.is_same_as(old_constraint))
+            {
+                actions.push(AlterTableAction::DropConstraint(old_constraint.clone()));
+            }
+        }
+        for new_constraint in after.constraints().iter().filter(is_migrated) {
+            if !before
+                .constraints()
+                .iter()
+                .any(|constraint| constraint.is_same_as(new_constraint))
+            {
+                actions.push(AlterTableAction::AddConstraint(new_constraint.clone()));
+            }
+        }
+
         if !actions.is_empty() {
+            migration_actions.push(MigrationAction::AlterTable(AlterTable {
+                table_name: after.qualified_name(),
+                actions,
+            }));
+        }
+
+        // Indexes can't be altered either. The table already has data, so they're rebuilt concurrently.
+        for old_index in before.indexes() {
+            if !after.indexes().contains(old_index) {
+                migration_actions.push(MigrationAction::DropIndex(DropIndex {
+                    // Indexes move along with their table, so by now it's in the new schema.
+                    name: DatabaseTableDefinition::qualify(after.schema.as_ref(), &old_index.name),
+                    concurrently: true,
+                }));
+            }
+        }
+        for new_index in after.indexes() {
+            if !before.indexes().contains(new_index) {
+                migration_actions.push(MigrationAction::CreateIndex(CreateIndex {
+                    table_name: after.qualified_name(),
+                    index: new_index.clone(),
+                    concurrently: true,
+                }));
+            }
+        }
+
+        if !migration_actions.is_empty() {
             Some(Self {
-                actions: vec![MigrationAction::AlterTable(AlterTable {
-                    table_name: after.table_name().clone(),
-                    actions,
-                })],
+                actions: migration_actions,
             })
         } else {
             None
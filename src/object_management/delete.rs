@@ -17,8 +17,11 @@ impl<T> BuildSql for DeleteStatement<T> {
         &self,
         builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
     ) {
-        builder.push(format!("DELETE FROM {} WHERE ", &self.table_def.table_name));
-        self.filter.build_sql(builder);
+        builder.push(format!("DELETE FROM {} WHERE ", &self.table_def.qualified_name()));
+        self.filter
+            .clone()
+            .qualify_tables(&|table_name| self.table_def.qualified_name_of(table_name))
+            .build_sql(builder);
     }
 }
 
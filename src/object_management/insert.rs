@@ -1,36 +1,78 @@
+use std::hash::Hash;
+
 use sqlx::Postgres;
 
 use crate::{
-    data_definition::table::{ColumnValue, Identifier, ObjectRepr},
+    data_definition::table::{ColumnValue, DatabaseTableDefinition, Identifier, ObjectRepr},
+    queries::{CacheableSql, FilterComparisonParam, ShapeHasher, SqlSink},
     BuildSql,
 };
 
-use super::update::UpdateStatement;
+use super::{
+    first_upsert, hash_object_shape, linked_one_to_one, relation_key_value, select_relation_key,
+    sorted_columns, unique_children, update::UpdateStatement, upserted_by, Upserted,
+};
 
 #[derive(Clone)]
 pub struct InsertStatement {
     pub(crate) table_name: Identifier,
+    /// The schema of the table, if it isn't in the default schema.
+    pub(crate) schema: Option<Identifier>,
     // TODO: Make this a little more specific? Good enough for now (probably), but needs to be thoroughly tested
     pub(crate) object_repr: ObjectRepr,
+    /// The primary key columns, used to detect conflicts when upserting.
+    pub(crate) primary_key: Vec<Identifier>,
 }
 
 impl InsertStatement {
+    /// Creates an insert for a table keyed on `id`. See `with_primary_key()` for other keys.
     pub fn new(
         table_name: Identifier,
         object_map: ObjectRepr,
     ) -> Self {
         Self {
             table_name,
+            schema: None,
             object_repr: object_map,
+            primary_key: vec![Identifier::new_unchecked("id")],
         }
     }
 
+    /// Sets the primary key columns, e.g. from `DatabaseTableDefinition::primary_key_columns()`.
+    pub fn with_primary_key(
+        mut self,
+        columns: Vec<Identifier>,
+    ) -> Self {
+        self.primary_key = columns;
+        self
+    }
+
+    /// Sets the schema of the table, e.g. from `DatabaseTableDefinition::schema`.
+    pub fn with_schema(
+        mut self,
+        schema: Option<Identifier>,
+    ) -> Self {
+        self.schema = schema;
+        self
+    }
+
     pub fn table_name(&self) -> Identifier {
         self.table_name.clone()
     }
+    pub fn schema(&self) -> Option<Identifier> {
+        self.schema.clone()
+    }
     pub fn object_repr(&self) -> &ObjectRepr {
         &self.object_repr
     }
+    pub fn primary_key(&self) -> &[Identifier] {
+        &self.primary_key
+    }
+
+    /// The table, qualified with its schema.
+    fn relation(&self) -> Identifier {
+        DatabaseTableDefinition::qualify(self.schema.as_ref(), &self.table_name)
+    }
 }
 
 // REF:
@@ -47,29 +89,23 @@ impl InsertStatement {
 // inner join onemanychild3 omc2 on omc2.parent_id = mypar.id;
 
 impl InsertStatement {
+    /// Writes the inserts for this object and its children. A child that `upserted` already holds is linked to by its
+    /// key, but not upserted again - see `first_upsert()`.
     fn build_consecutive_inserts(
         &self,
         prefix: &str,
         upsert: bool,
-        builder: &mut sqlx::QueryBuilder<'_, Postgres>,
+        upserted: &mut Upserted,
+        builder: &mut impl SqlSink,
     ) {
-        // This block is a hacky way of propogating my ID to the OneToMany tables later - I need to update this to actually build a SQL for a FK relationship to an existing object.
-        let parent_id = {
-            // TODO: `parent_id` is assumed - eventually I need to make this actually parse the FK relationships.
-            // ^^^^^ A(nother) big refactor is probably due to pass around references to the entire column instead of just the column name. Shouldn't have too much overhead, since the columns are already Arc'd under the hood.
-            // TODO: THis is still very hacky. Need to handle a "Parent" type of column relationship.
-            let Some(ColumnValue::Uuid(parent_id)) =
-                self.object_repr.get(&Identifier::new_unchecked("id"))
-            else {
-                panic!("ID field required for parent/child relationships.")
-            };
-            parent_id.clone()
-        };
+        // Children reference this object by its key. See `relation_key_value()`.
+        let parent_key = || relation_key_value(&self.object_repr, &self.primary_key);
 
         let mut raw_values: Vec<(Identifier, &ColumnValue)> = Vec::new();
         let mut one_to_one_inserts = Vec::new();
         let mut one_to_many_inserts = Vec::new();
-        for (column, value) in &self.object_repr {
+        let mut many_to_many_inserts = Vec::new();
+        for (column, value) in sorted_columns(&self.object_repr) {
             match value {
                 ColumnValue::OneToOne {
                     ..
@@ -79,59 +115,72 @@ impl InsertStatement {
                 ColumnValue::OneToMany {
                     ..
                 } => one_to_many_inserts.push((column, value)),
+                ColumnValue::ManyToMany {
+                    ..
+                } => many_to_many_inserts.push((column, value)),
                 _ => raw_values.push((column.clone(), value)),
             }
         }
 
         // Build ONETOONE children
-        let mut one_to_one_iter = one_to_one_inserts.into_iter().peekable();
-        while let Some((child_col_name, col_value)) = one_to_one_iter.next() {
+        for (child_col_name, col_value) in one_to_one_inserts {
             let ColumnValue::OneToOne {
                 child_table,
+                schema,
+                primary_key,
                 value,
             } = col_value
             else {
                 panic!("Wrong value type received when building one_to_one insert tables. This should not happen.")
             };
-            InsertStatement {
-                table_name: child_table.clone(),
-                object_repr: *value.clone(),
+            let child = InsertStatement::new(child_table.clone(), *value.clone())
+                .with_schema(schema.clone())
+                .with_primary_key(primary_key.clone());
+            if !first_upsert(upserted, child.relation(), &child.object_repr, &child.primary_key) {
+                raw_values.push((child_col_name.clone(), linked_one_to_one(col_value)));
+                continue;
             }
-            .build_consecutive_inserts(prefix, true, builder);
+            child.build_consecutive_inserts(prefix, true, upserted, builder);
             raw_values.push((child_col_name.clone(), col_value));
-            // if one_to_one_iter.peek().is_some() {
             builder.push(", ");
-            // }
         }
 
         // Build MYSELF
         {
             let table_name = &self.table_name;
+            let relation = DatabaseTableDefinition::qualify(self.schema.as_ref(), table_name);
             let col_names_joined =
                 raw_values.iter().map(|entry| &*(entry.0)).collect::<Vec<_>>().join(", ");
             builder.push(format!(
-                "{prefix}{table_name} as (INSERT INTO {table_name} ({col_names_joined}) VALUES (",
+                "{prefix}{table_name} as (INSERT INTO {relation} ({col_names_joined}) VALUES (",
             ));
             // Begin insert values
             let mut values_iter = raw_values.iter().peekable();
             while let Some((_col_name, value)) = values_iter.next() {
                 match value {
-                    ColumnValue::Boolean(val) => builder.push_bind(*val),
-                    ColumnValue::Int(val) => builder.push_bind(*val),
-                    ColumnValue::Float(val) => builder.push_bind(*val),
-                    ColumnValue::String(val) | ColumnValue::Json(val) => {
-                        builder.push_bind(val.to_string())
-                    },
-                    ColumnValue::Timestamp(val) => builder.push_bind(*val),
-                    ColumnValue::Uuid(val) => builder.push_bind(*val),
+                    ColumnValue::Boolean(_)
+                    | ColumnValue::Int(_)
+                    | ColumnValue::Float(_)
+                    | ColumnValue::String(_)
+                    | ColumnValue::Json(_)
+                    | ColumnValue::Enum {
+                        ..
+                    }
+                    | ColumnValue::Timestamp(_)
+                    | ColumnValue::Uuid(_)
+                    | ColumnValue::Numeric(_)
+                    | ColumnValue::Date(_)
+                    | ColumnValue::Time(_)
+                    | ColumnValue::TimestampTz(_)
+                    | ColumnValue::Bytea(_)
+                    | ColumnValue::Interval(_)
+                    | ColumnValue::Array(_) => builder
+                        .push_bind(value.as_bind_param().expect("Scalar values are bindable")),
                     ColumnValue::OneToOne {
-                        child_table: col_name,
-                        value: _,
-                    } => {
-                        let fk_name = &Identifier::new_unchecked("id"); // TODO: This is the ONLY FK supported for now. Eventually replace with dynamic FKs.
-                        builder.push(format!("(SELECT {fk_name} FROM {col_name})"))
-                        // Safe to inject directly, because `Identifier` is validated at runtime.
-                    },
+                        child_table,
+                        primary_key,
+                        ..
+                    } => builder.push(select_relation_key(child_table, primary_key)),
                     _ => todo!("This type of insert is not supported yet."),
                 };
                 if values_iter.peek().is_some() {
@@ -141,15 +190,19 @@ impl InsertStatement {
             // End insert values
             builder.push(")");
             if upsert {
-                // TODO: Another hardcode of `id`. Also this isn't modeled out as Postgres tokens, I've kinda given up on that idea.
+                // TODO: This isn't modeled out as Postgres tokens, I've kinda given up on that idea.
                 let update_statement = UpdateStatement {
                     table_name: self.table_name.clone(),
+                    schema: self.schema.clone(),
                     object_repr: raw_values
                         .into_iter()
                         .map(|(ident, val)| (ident.clone(), val.clone()))
                         .collect(),
+                    primary_key: self.primary_key.clone(),
                 };
-                builder.push(" ON CONFLICT (id) DO ");
+                let primary_key =
+                    self.primary_key.iter().map(|column| &**column).collect::<Vec<_>>().join(", ");
+                builder.push(format!(" ON CONFLICT ({primary_key}) DO "));
                 update_statement.build_sql_no_build_children(builder);
                 // builder.push()
             }
@@ -163,25 +216,224 @@ impl InsertStatement {
             // TODO: [PERFORMANCE] Some ugly overhead with the clone here...
             let ColumnValue::OneToMany {
                 child_table,
+                schema,
+                primary_key,
                 values,
             } = stmt.clone()
             else {
                 panic!("Wrong value type received when building one_to_many insert tables. This should not happen.");
             };
             // TODO: I'm faking it here by adding a parent_id to the insert. In future, this should be able to pull from the INSERT result, as above.
-            let insert_stmts = values.into_iter().map(|row| InsertStatement {
-                table_name: child_table.clone(),
-                object_repr: *row,
+            let insert_stmts = values.into_iter().map(|row| {
+                InsertStatement::new(child_table.clone(), *row)
+                    .with_schema(schema.clone())
+                    .with_primary_key(primary_key.clone())
             });
 
             for (i, mut insert_stmt) in insert_stmts.enumerate() {
-                insert_stmt
-                    .object_repr
-                    .insert(Identifier::new_unchecked("parent_id"), ColumnValue::Uuid(parent_id));
+                if !first_upsert(
+                    upserted,
+                    insert_stmt.relation(),
+                    &insert_stmt.object_repr,
+                    &insert_stmt.primary_key,
+                ) {
+                    continue;
+                }
+                // Left unset without a single-column key (see `relation_key_value()`), for the database to reject.
+                if let [key] = self.primary_key.as_slice() {
+                    if let Some(parent_key) = self.object_repr.get(key) {
+                        insert_stmt
+                            .object_repr
+                            .insert(Identifier::new_unchecked("parent_id"), parent_key.clone());
+                    }
+                }
 
                 builder.push(", "); // This should ALWAYS have at least one statement before it.
                 let prefix = format!("{}_{}", &prefix, i);
-                insert_stmt.build_consecutive_inserts(&prefix, true, builder);
+                insert_stmt.build_consecutive_inserts(&prefix, true, upserted, builder);
+            }
+        }
+
+        // Build MANYTOMANY children, then link them through the join table
+        for (column, value) in many_to_many_inserts {
+            let ColumnValue::ManyToMany {
+                child_table,
+                schema,
+                primary_key,
+                values,
+            } = value
+            else {
+                panic!("Wrong value type received when building many_to_many insert tables. This should not happen.");
+            };
+            let join_table =
+                DatabaseTableDefinition::join_table_name(&self.table_name, column, child_table);
+            let join_relation = DatabaseTableDefinition::qualify(self.schema.as_ref(), &join_table);
+            let (parent_column, child_column) = ("parent_id", "child_id");
+
+            // Children are shared, so they're upserted rather than owned.
+            let children = unique_children(values, primary_key);
+            let child_ids = children.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>();
+            for (i, (_, row)) in children.into_iter().enumerate() {
+                let child = InsertStatement::new(child_table.clone(), row.clone())
+                    .with_schema(schema.clone())
+                    .with_primary_key(primary_key.clone());
+                if !first_upsert(upserted, child.relation(), &child.object_repr, &child.primary_key)
+                {
+                    continue;
+                }
+                builder.push(", ");
+                child.build_consecutive_inserts(
+                    &format!("{prefix}_{column}_{i}"),
+                    true,
+                    upserted,
+                    builder,
+                );
+            }
+
+            // On update, drop the links to any children no longer in the list.
+            if upsert {
+                builder
+                    .push(format!(
+                        ", {prefix}{join_table}_unlinked as (DELETE FROM {join_relation} WHERE {parent_column} = "
+                    ))
+                    .push_bind(parent_key());
+                if !child_ids.is_empty() {
+                    builder.push(format!(" AND {child_column} NOT IN ("));
+                    let mut ids = child_ids.iter().peekable();
+                    while let Some(id) = ids.next() {
+                        builder.push_bind(id.clone());
+                        if ids.peek().is_some() {
+                            builder.push(", ");
+                        }
+                    }
+                    builder.push(")");
+                }
+                builder.push(")");
+            }
+
+            if !child_ids.is_empty() {
+                builder.push(format!(
+                    ", {prefix}{join_table} as (INSERT INTO {join_relation} ({parent_column}, {child_column}) VALUES "
+                ));
+                let mut ids = child_ids.iter().peekable();
+                while let Some(id) = ids.next() {
+                    builder
+                        .push("(")
+                        .push_bind(parent_key())
+                        .push(", ")
+                        .push_bind(id.clone())
+                        .push(")");
+                    if ids.peek().is_some() {
+                        builder.push(", ");
+                    }
+                }
+                builder.push(" ON CONFLICT DO NOTHING RETURNING *)");
+            }
+        }
+    }
+
+    /// Collects the values `build_consecutive_inserts()` binds for `object_repr`, in the same order, without rendering
+    /// any SQL. See `CacheableSql::collect_binds()`.
+    pub(crate) fn collect_consecutive_binds(
+        object_repr: &ObjectRepr,
+        primary_key: &[Identifier],
+        upsert: bool,
+        upserted: &mut Upserted,
+        binds: &mut Vec<FilterComparisonParam>,
+    ) {
+        let columns = sorted_columns(object_repr);
+        let mut raw_values = columns
+            .iter()
+            .filter(|(_, value)| value.as_bind_param().is_some())
+            .copied()
+            .collect::<Vec<_>>();
+
+        // ONETOONE children
+        for (column, value) in &columns {
+            if let ColumnValue::OneToOne {
+                child_table,
+                schema,
+                primary_key: child_key,
+                value: child,
+            } = value
+            {
+                let relation = DatabaseTableDefinition::qualify(schema.as_ref(), child_table);
+                if first_upsert(upserted, relation, child, child_key) {
+                    Self::collect_consecutive_binds(child, child_key, true, upserted, binds);
+                    raw_values.push((column, value));
+                } else {
+                    raw_values.push((column, linked_one_to_one(value)));
+                }
+            }
+        }
+
+        // MYSELF, then the upsert's update of every column outside the key
+        binds.extend(raw_values.iter().filter_map(|(_, value)| value.as_bind_param()));
+        if upsert {
+            let mut updates = raw_values
+                .into_iter()
+                .filter(|(column, _)| !primary_key.contains(column))
+                .peekable();
+            if updates.peek().is_some() {
+                let mut updates = updates.collect::<Vec<_>>();
+                updates.sort_by_key(|(column, _)| *column);
+                binds.extend(updates.iter().filter_map(|(_, value)| value.as_bind_param()));
+                binds.extend(primary_key.iter().filter_map(|column| {
+                    object_repr.get(column).and_then(ColumnValue::as_bind_param)
+                }));
+            }
+        }
+
+        // ONETOMANY children, each with the parent's key
+        for (_, value) in &columns {
+            if let ColumnValue::OneToMany {
+                child_table,
+                schema,
+                primary_key: child_key,
+                values,
+            } = value
+            {
+                let relation = DatabaseTableDefinition::qualify(schema.as_ref(), child_table);
+                for row in values {
+                    if !first_upsert(upserted, relation.clone(), row, child_key) {
+                        continue;
+                    }
+                    let mut row = (**row).clone();
+                    if let [key] = primary_key {
+                        if let Some(parent_key) = object_repr.get(key) {
+                            row.insert(Identifier::new_unchecked("parent_id"), parent_key.clone());
+                        }
+                    }
+                    Self::collect_consecutive_binds(&row, child_key, true, upserted, binds);
+                }
+            }
+        }
+
+        // MANYTOMANY children, then their links
+        let parent_key = relation_key_value(object_repr, primary_key);
+        for (_, value) in &columns {
+            if let ColumnValue::ManyToMany {
+                child_table,
+                schema,
+                primary_key: child_key,
+                values,
+            } = value
+            {
+                let relation = DatabaseTableDefinition::qualify(schema.as_ref(), child_table);
+                let children = unique_children(values, child_key);
+                for (_, row) in &children {
+                    if first_upsert(upserted, relation.clone(), row, child_key) {
+                        Self::collect_consecutive_binds(row, child_key, true, upserted, binds);
+                    }
+                }
+                if upsert {
+                    parent_key.collect_binds(binds);
+                    children.iter().for_each(|(id, _)| id.collect_binds(binds));
+                }
+                for (id, _) in &children {
+                    parent_key.collect_binds(binds);
+                    id.collect_binds(binds);
+                }
             }
         }
     }
@@ -189,11 +441,12 @@ impl InsertStatement {
     pub(crate) fn build_insert_sql(
         &self,
         upsert: bool,
-        builder: &mut sqlx::QueryBuilder<'_, Postgres>,
+        builder: &mut impl SqlSink,
     ) {
         let table_name = self.table_name.clone();
         builder.push("WITH ");
-        self.build_consecutive_inserts("", upsert, builder);
+        let upserted = &mut upserted_by(self.relation(), &self.object_repr, &self.primary_key);
+        self.build_consecutive_inserts("", upsert, upserted, builder);
         // Need *something* after the "WITH _ as (INSERT ....) statements"
         builder.push(format!("SELECT * FROM {table_name};"));
     }
@@ -207,3 +460,98 @@ impl BuildSql for InsertStatement {
         self.build_insert_sql(false, builder);
     }
 }
+
+impl CacheableSql for InsertStatement {
+    fn hash_shape(
+        &self,
+        state: &mut ShapeHasher,
+    ) {
+        (&self.table_name, &self.schema).hash(state);
+        self.primary_key.hash(state);
+        let upserted = &mut upserted_by(self.relation(), &self.object_repr, &self.primary_key);
+        hash_object_shape(&self.object_repr, state, upserted);
+    }
+
+    fn write_sql(
+        &self,
+        sink: &mut impl SqlSink,
+    ) {
+        self.build_insert_sql(false, sink);
+    }
+
+    fn collect_binds(
+        &self,
+        binds: &mut Vec<FilterComparisonParam>,
+    ) {
+        let upserted = &mut upserted_by(self.relation(), &self.object_repr, &self.primary_key);
+        Self::collect_consecutive_binds(
+            &self.object_repr,
+            &self.primary_key,
+            false,
+            upserted,
+            binds,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        data_definition::table::{ColumnValue, Identifier, ObjectRepr},
+        queries::CacheableSql,
+        BuildSql,
+    };
+
+    use super::InsertStatement;
+
+    #[test]
+    fn children_shared_between_relations_are_upserted_once() {
+        let key = vec![Identifier::new_unchecked("id")];
+        let row = |id: &str| -> ObjectRepr {
+            HashMap::from([(Identifier::new_unchecked("id"), ColumnValue::String(id.to_string()))])
+        };
+        let one_to_one = |value: ObjectRepr| ColumnValue::OneToOne {
+            child_table: Identifier::new_unchecked("address"),
+            schema: None,
+            primary_key: key.clone(),
+            value: Box::new(value),
+        };
+        let many_to_many = |values: Vec<ObjectRepr>| ColumnValue::ManyToMany {
+            child_table: Identifier::new_unchecked("tag"),
+            schema: None,
+            primary_key: key.clone(),
+            values: values.into_iter().map(Box::new).collect(),
+        };
+        let order = HashMap::from([
+            (Identifier::new_unchecked("id"), ColumnValue::String("order_1".to_string())),
+            (Identifier::new_unchecked("billing_address"), one_to_one(row("home"))),
+            (Identifier::new_unchecked("shipping_address"), one_to_one(row("home"))),
+            (Identifier::new_unchecked("featured"), many_to_many(vec![row("sale")])),
+            (Identifier::new_unchecked("tags"), many_to_many(vec![row("new"), row("sale")])),
+        ]);
+        let statement = InsertStatement::new(Identifier::new_unchecked("orders"), order);
+
+        let mut builder = sqlx::QueryBuilder::new("");
+        statement.build_sql(&mut builder);
+        let sql = builder.sql();
+
+        assert_eq!(sql.matches("INSERT INTO address ").count(), 1);
+        assert_eq!(sql.matches("INSERT INTO tag ").count(), 2);
+        // The second address is linked to by its key, as it isn't inserted again.
+        assert!(sql.contains(
+            "INSERT INTO orders (id, billing_address, shipping_address) VALUES ($2, (SELECT id FROM address), $3)"
+        ));
+        // Both join tables still link the shared tag.
+        assert!(sql
+            .contains("INSERT INTO orders_featured_to_tag (parent_id, child_id) VALUES ($5, $6)"));
+        assert!(sql.contains(
+            "INSERT INTO orders_tags_to_tag (parent_id, child_id) VALUES ($8, $9), ($10, $11)"
+        ));
+
+        let cached = statement.cached_sql();
+        assert_eq!(&*cached.sql, sql);
+        assert_eq!(cached.binds.len(), 11);
+    }
+}
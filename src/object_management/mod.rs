@@ -3,6 +3,249 @@ pub mod insert;
 pub mod update;
 // pub mod upsert;
 
+use std::{collections::HashSet, hash::Hash};
+
+use crate::{
+    data_definition::table::{ColumnValue, DatabaseTableDefinition, Identifier, ObjectRepr},
+    queries::{cache::InlineSql, FilterComparisonParam, ShapeHasher, SqlSink},
+};
+
+/// The columns of `object_repr`, sorted by name - so that the same type of object always renders the same SQL.
+pub(crate) fn sorted_columns(object_repr: &ObjectRepr) -> Vec<(&Identifier, &ColumnValue)> {
+    let mut columns = object_repr.iter().collect::<Vec<_>>();
+    columns.sort_by_key(|(column, _)| *column);
+    columns
+}
+
+/// Hashes the columns and value types of `object_repr`, recursing into child objects. See `CacheableSql::hash_shape()`.
+///
+/// Children are visited in the order `InsertStatement` writes them, as whether each one is upserted (see
+/// `first_upsert()`) changes the SQL.
+pub(crate) fn hash_object_shape(
+    object_repr: &ObjectRepr,
+    state: &mut ShapeHasher,
+    upserted: &mut Upserted,
+) {
+    let columns = sorted_columns(object_repr);
+    for (column, value) in &columns {
+        column.hash(state);
+        std::mem::discriminant(*value).hash(state);
+        match value {
+            // The cast is part of the SQL.
+            ColumnValue::Enum {
+                type_name,
+                ..
+            } => type_name.hash(state),
+            ColumnValue::Array(val) => std::mem::discriminant(val).hash(state),
+            ColumnValue::OneToOne {
+                child_table,
+                schema,
+                primary_key,
+                ..
+            }
+            | ColumnValue::OneToMany {
+                child_table,
+                schema,
+                primary_key,
+                ..
+            }
+            | ColumnValue::ManyToMany {
+                child_table,
+                schema,
+                primary_key,
+                ..
+            } => (child_table, schema, primary_key).hash(state),
+            _ => {},
+        }
+    }
+
+    fn hash_child(
+        (child_table, schema, primary_key): (&Identifier, &Option<Identifier>, &[Identifier]),
+        value: &ObjectRepr,
+        state: &mut ShapeHasher,
+        upserted: &mut Upserted,
+    ) {
+        let relation = DatabaseTableDefinition::qualify(schema.as_ref(), child_table);
+        let first = first_upsert(upserted, relation, value, primary_key);
+        first.hash(state);
+        if first {
+            hash_object_shape(value, state, upserted);
+        }
+    }
+    for (_, value) in &columns {
+        if let ColumnValue::OneToOne {
+            child_table,
+            schema,
+            primary_key,
+            value,
+        } = value
+        {
+            hash_child((child_table, schema, primary_key), value, state, upserted);
+        }
+    }
+    for (_, value) in &columns {
+        if let ColumnValue::OneToMany {
+            child_table,
+            schema,
+            primary_key,
+            values,
+        } = value
+        {
+            values.len().hash(state);
+            for value in values {
+                hash_child((child_table, schema, primary_key), value, state, upserted);
+            }
+        }
+    }
+    for (_, value) in &columns {
+        if let ColumnValue::ManyToMany {
+            child_table,
+            schema,
+            primary_key,
+            values,
+        } = value
+        {
+            let children = unique_children(values, primary_key);
+            children.len().hash(state);
+            for (_, value) in children {
+                hash_child((child_table, schema, primary_key), value, state, upserted);
+            }
+        }
+    }
+}
+
+/// The rows a statement has upserted so far, by table and key. Postgres rejects a statement that upserts the same row
+/// twice (e.g. a child shared by two relationships), so later copies of a row are only linked to, not upserted.
+pub(crate) type Upserted = HashSet<(Identifier, String)>;
+
+/// The rows a statement upserts before any of its children: the object it inserts (or updates) into `relation`.
+pub(crate) fn upserted_by(
+    relation: Identifier,
+    object_repr: &ObjectRepr,
+    primary_key: &[Identifier],
+) -> Upserted {
+    let mut upserted = Upserted::new();
+    first_upsert(&mut upserted, relation, object_repr, primary_key);
+    upserted
+}
+
+/// Records `object_repr` as upserted into `relation`, returning `false` if it already was. Rows without their whole
+/// key set can't be told apart, so they're always upserted.
+pub(crate) fn first_upsert(
+    upserted: &mut Upserted,
+    relation: Identifier,
+    object_repr: &ObjectRepr,
+    primary_key: &[Identifier],
+) -> bool {
+    // Params aren't `Eq` (floats), so keys are compared by how they're written out.
+    let mut key = InlineSql::default();
+    for column in primary_key {
+        match object_repr.get(column).and_then(ColumnValue::as_bind_param) {
+            Some(value) => key.push_bind(value).push(", "),
+            None => return true,
+        };
+    }
+    upserted.insert((relation, key.sql))
+}
+
+/// The value a one-to-one column takes when its child was already upserted elsewhere in the statement: the child's
+/// key, rather than a select from the child's insert (which isn't there). See `first_upsert()`.
+pub(crate) fn linked_one_to_one(value: &ColumnValue) -> &ColumnValue {
+    if let ColumnValue::OneToOne {
+        primary_key,
+        value: child,
+        ..
+    } = value
+    {
+        if let [key] = primary_key.as_slice() {
+            if let Some(key_value) = child.get(key) {
+                return key_value;
+            }
+        }
+    }
+    value
+}
+
+/// The value of `object_repr`'s key, for relating it to other objects. Relationships need a single-column key
+/// (see `DatabaseTableDefinition::relation_key()`), so this is `NULL` for any other key - and the database
+/// rejects the link, rather than it being linked to the wrong row.
+pub(crate) fn relation_key_value(
+    object_repr: &ObjectRepr,
+    primary_key: &[Identifier],
+) -> FilterComparisonParam {
+    match primary_key {
+        [key] => object_repr.get(key).and_then(ColumnValue::as_bind_param),
+        _ => None,
+    }
+    .unwrap_or(FilterComparisonParam::Null)
+}
+
+/// Selects the key of the one-to-one child just inserted as `child_table` (by the `WITH` clause of the insert),
+/// or `NULL` if it doesn't have a single-column key. See `relation_key_value()`.
+pub(crate) fn select_relation_key(
+    child_table: &Identifier,
+    primary_key: &[Identifier],
+) -> String {
+    // Safe to inject directly, because `Identifier` is validated at runtime.
+    match primary_key {
+        [key] => format!("(SELECT {key} FROM {child_table})"),
+        _ => "NULL".to_string(),
+    }
+}
+
+/// The many-to-many children in `values`, with their keys, skipping any listed more than once.
+pub(crate) fn unique_children<'a>(
+    values: &'a [Box<ObjectRepr>],
+    primary_key: &[Identifier],
+) -> Vec<(FilterComparisonParam, &'a ObjectRepr)> {
+    let mut seen = HashSet::new();
+    values
+        .iter()
+        .filter_map(|value| {
+            let key = relation_key_value(value, primary_key);
+            // Params aren't `Eq` (floats), so keys are compared by how they're written out.
+            let mut literal = InlineSql::default();
+            literal.push_bind(key.clone());
+            seen.insert(literal.sql).then_some((key, &**value))
+        })
+        .collect()
+}
+
+impl ColumnValue {
+    /// The value to bind for this column, or `None` for relationships.
+    pub(crate) fn as_bind_param(&self) -> Option<FilterComparisonParam> {
+        type P = FilterComparisonParam;
+        match self {
+            ColumnValue::Boolean(val) => Some(P::Bool(*val)),
+            ColumnValue::Int(val) => Some(P::Integer(*val)),
+            ColumnValue::Float(val) => Some(P::Float(*val)),
+            ColumnValue::String(val) | ColumnValue::Json(val) => Some(P::String(val.to_string())),
+            ColumnValue::Timestamp(val) => Some(P::Timestamp(*val)),
+            ColumnValue::Uuid(val) => Some(P::Uuid(*val)),
+            ColumnValue::Numeric(val) => Some(P::Numeric(*val)),
+            ColumnValue::Date(val) => Some(P::Date(*val)),
+            ColumnValue::Time(val) => Some(P::Time(*val)),
+            ColumnValue::TimestampTz(val) => Some(P::TimestampTz(*val)),
+            ColumnValue::Bytea(val) => Some(P::Bytea(val.clone())),
+            ColumnValue::Interval(val) => Some(P::Interval(*val)),
+            ColumnValue::Array(val) => Some(P::Array(val.clone())),
+            ColumnValue::Enum {
+                type_name,
+                variant,
+            } => Some(P::Enum(type_name.clone(), variant.to_string())),
+            ColumnValue::OneToMany {
+                ..
+            }
+            | ColumnValue::OneToOne {
+                ..
+            }
+            | ColumnValue::ManyToMany {
+                ..
+            } => None,
+        }
+    }
+}
+
 #[allow(unused)]
 pub(crate) trait SqlStatement {
     fn to_sql() -> String;
@@ -1,17 +1,27 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, hash::Hash};
 
 use sqlx::Postgres;
 
-use crate::BuildSql;
+use crate::{
+    queries::{CacheableSql, FilterComparisonParam, ShapeHasher, SqlSink},
+    BuildSql,
+};
 
 use crate::data_definition::table::{ColumnValue, DatabaseTableDefinition, Identifier, ObjectRepr};
 
-use super::insert::InsertStatement;
+use super::{
+    hash_object_shape, insert::InsertStatement, select_relation_key, sorted_columns, upserted_by,
+    Upserted,
+};
 
 pub struct UpdateStatement {
     pub(crate) table_name: Identifier,
+    /// The schema of the table, if it isn't in the default schema.
+    pub(crate) schema: Option<Identifier>,
     // TODO: Make this a little more specific? Good enough for now (probably), but needs to be thoroughly tested
     pub(crate) object_repr: HashMap<Identifier, ColumnValue>,
+    /// The primary key columns, identifying the row to update.
+    pub(crate) primary_key: Vec<Identifier>,
 }
 
 impl UpdateStatement {
@@ -19,72 +29,127 @@ impl UpdateStatement {
         table_def: DatabaseTableDefinition,
         object_map: HashMap<Identifier, ColumnValue>,
     ) -> Self {
+        let mut primary_key = table_def
+            .primary_key_columns()
+            .into_iter()
+            .map(|column| column.column_name.clone())
+            .collect::<Vec<_>>();
+        if primary_key.is_empty() {
+            primary_key.push(Identifier::new_unchecked("id"));
+        }
         Self {
             table_name: table_def.table_name.clone(),
+            schema: table_def.schema.clone(),
             object_repr: object_map,
+            primary_key,
         }
     }
 
     pub fn table_name(&self) -> Identifier {
         self.table_name.clone()
     }
+    pub fn schema(&self) -> Option<Identifier> {
+        self.schema.clone()
+    }
     pub fn object_repr(&self) -> &ObjectRepr {
         &self.object_repr
     }
+    pub fn primary_key(&self) -> &[Identifier] {
+        &self.primary_key
+    }
+
+    /// See `upserted_by()`.
+    fn upserted(&self) -> Upserted {
+        let relation = DatabaseTableDefinition::qualify(self.schema.as_ref(), &self.table_name);
+        upserted_by(relation, &self.object_repr, &self.primary_key)
+    }
 }
 
 impl UpdateStatement {
     /// This is a HACKY approach to getting upserts to work as a part of insert / update.
     /// It ignores OneToMany relationships (they should not be passed in at all, when used properly),
-    /// and does a (SELECT id FROM _table_name_) for OneToOne tables.
+    /// and selects the child's key (e.g. `(SELECT id FROM _table_name_)`) for OneToOne tables.
     /// Hacky, but gets the job done.
+    ///
+    /// Renders `NOTHING` instead when every column is part of the primary key.
     pub fn build_sql_no_build_children(
         &self,
-        builder: &mut sqlx::QueryBuilder<'_, Postgres>,
+        builder: &mut impl SqlSink,
     ) {
+        let key_values = self
+            .primary_key
+            .iter()
+            .map(|column| {
+                self.object_repr
+                    .get(column)
+                    .and_then(ColumnValue::as_bind_param)
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "Received update without key column {column} - shouldn't be possble."
+                        )
+                    })
+            })
+            .collect::<Vec<_>>();
+        let columns = sorted_columns(&self.object_repr)
+            .into_iter()
+            .filter(|(column, _)| !self.primary_key.contains(column))
+            .collect::<Vec<_>>();
+        if columns.is_empty() {
+            builder.push("NOTHING");
+            return;
+        }
+
         // builder.push(format!("UPDATE {} SET ", self.table_name));
         builder.push("UPDATE SET ");
-
-        let Some(ColumnValue::Uuid(id)) = self.object_repr.get(&Identifier::new_unchecked("id"))
-        else {
-            panic!("Received update without ID - shouldn't be possble.")
-        };
-        for (i, (column, value)) in self
-            .object_repr
-            .iter()
-            .filter(|(k, _v)| &k as &str != &Identifier::new_unchecked("id") as &str)
-            .enumerate()
-        {
+        let mut columns = columns.into_iter().peekable();
+        while let Some((column, value)) = columns.next() {
             match value {
-                ColumnValue::Boolean(val) => builder.push(column).push(" = ").push_bind(*val),
-                ColumnValue::Int(val) => builder.push(column).push(" = ").push_bind(*val),
-                ColumnValue::Float(val) => builder.push(column).push(" = ").push_bind(*val),
-                ColumnValue::String(val) | ColumnValue::Json(val) => {
-                    builder.push(column).push(" = ").push_bind(val.to_string())
-                },
-                ColumnValue::Timestamp(val) => builder.push(column).push(" = ").push_bind(*val),
-                ColumnValue::Uuid(val) => builder.push(column).push(" = ").push_bind(*val),
+                ColumnValue::Boolean(_)
+                | ColumnValue::Int(_)
+                | ColumnValue::Float(_)
+                | ColumnValue::String(_)
+                | ColumnValue::Json(_)
+                | ColumnValue::Enum {
+                    ..
+                }
+                | ColumnValue::Timestamp(_)
+                | ColumnValue::Uuid(_)
+                | ColumnValue::Numeric(_)
+                | ColumnValue::Date(_)
+                | ColumnValue::Time(_)
+                | ColumnValue::TimestampTz(_)
+                | ColumnValue::Bytea(_)
+                | ColumnValue::Interval(_)
+                | ColumnValue::Array(_) => builder
+                    .push(column)
+                    .push(" = ")
+                    .push_bind(value.as_bind_param().expect("Scalar values are bindable")),
                 ColumnValue::OneToOne {
                     child_table,
-                    value: _,
-                } => {
-                    let fk_name = &Identifier::new_unchecked("id"); // TODO: This is the ONLY FK supported for now. Eventually replace with dynamic FKs.
-                    builder
-                        .push(column)
-                        .push(" = ")
-                        .push(format!("(SELECT {fk_name} FROM {child_table})"))
-                    // Safe to inject directly, because `Identifier` is validated at runtime.
-                },
+                    primary_key,
+                    ..
+                } => builder
+                    .push(column)
+                    .push(" = ")
+                    .push(select_relation_key(child_table, primary_key)),
                 ColumnValue::OneToMany {
                     ..
+                }
+                | ColumnValue::ManyToMany {
+                    ..
                 } => todo!(),
             };
-            if i < &self.object_repr.len() - 2 {
-                // .len()-2 because we've stripped `id` from it above.
+            if columns.peek().is_some() {
                 builder.push(", ");
             }
         }
-        builder.push(format!(" WHERE {}.id=", &self.table_name)).push_bind(dbg!(*id));
+        builder.push(" WHERE ");
+        for (i, (column, value)) in self.primary_key.iter().zip(key_values).enumerate() {
+            if i > 0 {
+                builder.push(" AND ");
+            }
+            builder.push(format!("{}.{column}=", &self.table_name)).push_bind(value);
+        }
     }
 }
 
@@ -92,14 +157,149 @@ impl BuildSql for UpdateStatement {
     fn build_sql(
         &self,
         builder: &mut sqlx::QueryBuilder<'_, Postgres>,
+    ) {
+        self.write_sql(builder);
+    }
+}
+
+impl CacheableSql for UpdateStatement {
+    fn hash_shape(
+        &self,
+        state: &mut ShapeHasher,
+    ) {
+        (&self.table_name, &self.schema).hash(state);
+        self.primary_key.hash(state);
+        hash_object_shape(&self.object_repr, state, &mut self.upserted());
+    }
+
+    fn write_sql(
+        &self,
+        builder: &mut impl SqlSink,
     ) {
         // HACK: This actually does an UPSERT instead of a raw UPDATE.
         // We accomplish this by converting toa n INSERT statement, then
         // building the insert with the UPSERT flag set.
-        InsertStatement {
-            table_name: self.table_name.clone(),
-            object_repr: self.object_repr.clone(),
-        }
-        .build_insert_sql(true, builder);
+        InsertStatement::new(self.table_name.clone(), self.object_repr.clone())
+            .with_schema(self.schema.clone())
+            .with_primary_key(self.primary_key.clone())
+            .build_insert_sql(true, builder);
+    }
+
+    fn collect_binds(
+        &self,
+        binds: &mut Vec<FilterComparisonParam>,
+    ) {
+        let (object_repr, primary_key) = (&self.object_repr, &self.primary_key);
+        InsertStatement::collect_consecutive_binds(
+            object_repr,
+            primary_key,
+            true,
+            &mut self.upserted(),
+            binds,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        data_definition::table::{
+            ColumnValue, DatabaseTableDefinition, Identifier, ObjectRepr, TableColumn,
+            TableConstraint,
+        },
+        queries::{cache::BindCollector, CacheableSql},
+        BuildSql,
+    };
+
+    use super::UpdateStatement;
+
+    #[test]
+    fn composite_keys_are_matched_on_conflict_and_not_updated() {
+        let mut table = DatabaseTableDefinition::new("orders")
+            .unwrap()
+            .column(TableColumn::int("tenant_id").unwrap().non_null())
+            .column(TableColumn::int("order_no").unwrap().non_null())
+            .column(TableColumn::string("status").unwrap());
+        let key = vec![
+            table.columns[&Identifier::new_unchecked("tenant_id")].clone(),
+            table.columns[&Identifier::new_unchecked("order_no")].clone(),
+        ];
+        table.constraints.push(TableConstraint::primary_key(key));
+        let values = HashMap::from([
+            (Identifier::new_unchecked("tenant_id"), ColumnValue::Int(7)),
+            (Identifier::new_unchecked("order_no"), ColumnValue::Int(1001)),
+            (Identifier::new_unchecked("status"), ColumnValue::String("shipped".to_string())),
+        ]);
+
+        let mut builder = sqlx::QueryBuilder::new("");
+        UpdateStatement::new(table, values).build_sql(&mut builder);
+
+        assert_eq!(
+            builder.sql(),
+            "WITH orders as (INSERT INTO orders (order_no, status, tenant_id) VALUES ($1, $2, $3) ON CONFLICT (tenant_id, order_no) DO UPDATE SET status = $4 WHERE orders.tenant_id=$5 AND orders.order_no=$6 RETURNING * )SELECT * FROM orders;"
+        );
+    }
+
+    #[test]
+    fn binds_are_collected_without_rendering_in_the_order_they_are_written() {
+        let id =
+            |value: &str| (Identifier::new_unchecked("id"), ColumnValue::String(value.to_string()));
+        let child = |table: &str, values: Vec<ObjectRepr>| {
+            let (child_table, schema, primary_key) =
+                (Identifier::new_unchecked(table), None, vec![Identifier::new_unchecked("id")]);
+            match values.as_slice() {
+                [value] if table == "address" => ColumnValue::OneToOne {
+                    child_table,
+                    schema,
+                    primary_key,
+                    value: Box::new(value.clone()),
+                },
+                _ if table == "line_item" => ColumnValue::OneToMany {
+                    child_table,
+                    schema,
+                    primary_key,
+                    values: values.into_iter().map(Box::new).collect(),
+                },
+                _ => ColumnValue::ManyToMany {
+                    child_table,
+                    schema,
+                    primary_key,
+                    values: values.into_iter().map(Box::new).collect(),
+                },
+            }
+        };
+        let tag = |name: &str| HashMap::from([id(name)]);
+        let values = HashMap::from([
+            id("order_1"),
+            (Identifier::new_unchecked("note"), ColumnValue::String("gift".to_string())),
+            (
+                Identifier::new_unchecked("address"),
+                child("address", vec![HashMap::from([id("home")])]),
+            ),
+            (
+                Identifier::new_unchecked("line_items"),
+                child("line_item", vec![HashMap::from([id("li_1")]), HashMap::from([id("li_2")])]),
+            ),
+            (
+                Identifier::new_unchecked("tags"),
+                child("tag", vec![tag("red"), tag("blue"), tag("red")]),
+            ),
+        ]);
+        let statement = UpdateStatement {
+            table_name: Identifier::new_unchecked("orders"),
+            schema: None,
+            object_repr: values,
+            primary_key: vec![Identifier::new_unchecked("id")],
+        };
+
+        let mut binds = Vec::new();
+        statement.collect_binds(&mut binds);
+        let mut collector = BindCollector::default();
+        statement.write_sql(&mut collector);
+
+        assert_eq!(format!("{binds:?}"), format!("{:?}", collector.binds));
+        assert!(!binds.is_empty());
     }
 }
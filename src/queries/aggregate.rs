@@ -0,0 +1,513 @@
+use std::{fmt::Display, hash::Hash, marker::PhantomData};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{data_definition::table::Identifier, BuildSql};
+
+use super::{
+    CacheableSql, Filter, FilterComparisonParam, OrderBy, OrderDirection, Query, ShapeHasher,
+    SqlSink,
+};
+
+#[derive(Clone, Debug, Hash, Serialize, Deserialize)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+impl Display for AggregateFunction {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            AggregateFunction::Count => f.write_str("COUNT"),
+            AggregateFunction::Sum => f.write_str("SUM"),
+            AggregateFunction::Avg => f.write_str("AVG"),
+            AggregateFunction::Min => f.write_str("MIN"),
+            AggregateFunction::Max => f.write_str("MAX"),
+        }
+    }
+}
+
+/// An aggregate expression, e.g. `SUM(orders.amount) AS revenue`. Built from the generated
+/// `*Filters` types (`f.amount.sum()`), or with `Aggregate::count_all()` for `COUNT(*)`.
+///
+/// The alias is the name of the field the value is deserialized into. It defaults to
+/// `{function}_{column}` (e.g. `sum_amount`), and can be overridden with `alias()`.
+#[derive(Clone, Debug, Hash, Serialize, Deserialize)]
+pub struct Aggregate {
+    function: AggregateFunction,
+    column: Option<Identifier>,
+    alias: Identifier,
+}
+
+impl Aggregate {
+    pub fn new(
+        function: AggregateFunction,
+        column: Identifier,
+    ) -> Self {
+        let alias = format!(
+            "{}_{}",
+            function.to_string().to_lowercase(),
+            column.rsplit('.').next().unwrap_or(&column)
+        );
+        Self {
+            function,
+            column: Some(column),
+            alias: Identifier::new_unchecked(alias),
+        }
+    }
+
+    /// `COUNT(*)`, aliased to `count` by default.
+    pub fn count_all() -> Self {
+        Self {
+            function: AggregateFunction::Count,
+            column: None,
+            alias: Identifier::new_unchecked("count"),
+        }
+    }
+
+    /// Overrides the column alias. Aliases are written into the query unquoted, so they're validated like any other
+    /// identifier.
+    pub fn alias(
+        mut self,
+        alias: impl Into<String>,
+    ) -> Result<Self, String> {
+        self.alias = Identifier::new(alias)?;
+        Ok(self)
+    }
+
+    pub fn asc(&self) -> OrderBy {
+        OrderBy::new(self.alias.clone(), OrderDirection::Ascending)
+    }
+
+    pub fn desc(&self) -> OrderBy {
+        OrderBy::new(self.alias.clone(), OrderDirection::Desending)
+    }
+
+    fn compare(
+        &self,
+        build_filter: fn(FilterComparisonParam, FilterComparisonParam) -> Filter,
+        value: impl Into<FilterComparisonParam>,
+    ) -> Filter {
+        build_filter(FilterComparisonParam::Aggregate(Box::new(self.clone())), value.into())
+    }
+
+    // Comparisons for use in `HAVING`
+    pub fn eq(
+        &self,
+        value: impl Into<FilterComparisonParam>,
+    ) -> Filter {
+        self.compare(Filter::Equal, value)
+    }
+    pub fn ne(
+        &self,
+        value: impl Into<FilterComparisonParam>,
+    ) -> Filter {
+        self.compare(Filter::NotEqual, value)
+    }
+    pub fn lt(
+        &self,
+        value: impl Into<FilterComparisonParam>,
+    ) -> Filter {
+        self.compare(Filter::LessThan, value)
+    }
+    pub fn lte(
+        &self,
+        value: impl Into<FilterComparisonParam>,
+    ) -> Filter {
+        self.compare(Filter::LessThanOrEqual, value)
+    }
+    pub fn gt(
+        &self,
+        value: impl Into<FilterComparisonParam>,
+    ) -> Filter {
+        self.compare(Filter::GreaterThan, value)
+    }
+    pub fn gte(
+        &self,
+        value: impl Into<FilterComparisonParam>,
+    ) -> Filter {
+        self.compare(Filter::GreaterThanOrEqual, value)
+    }
+}
+
+impl IntoIterator for Aggregate {
+    type Item = Aggregate;
+    type IntoIter = std::iter::Once<Aggregate>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+/// Builds the expression only (no alias), so that it can be reused in `HAVING`.
+impl BuildSql for Aggregate {
+    fn build_sql(
+        &self,
+        builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    ) {
+        self.write_sql(builder);
+    }
+}
+
+impl Aggregate {
+    pub(crate) fn write_sql(
+        &self,
+        sink: &mut impl SqlSink,
+    ) {
+        match &self.column {
+            Some(column) => sink.push(format!("{}({column})", self.function)),
+            None => sink.push(format!("{}(*)", self.function)),
+        };
+    }
+}
+
+/// The precision to truncate a timestamp to when grouping, e.g. revenue per `Day`.
+#[derive(Clone, Hash)]
+pub enum DatePart {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+impl Display for DatePart {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            DatePart::Hour => f.write_str("hour"),
+            DatePart::Day => f.write_str("day"),
+            DatePart::Week => f.write_str("week"),
+            DatePart::Month => f.write_str("month"),
+            DatePart::Quarter => f.write_str("quarter"),
+            DatePart::Year => f.write_str("year"),
+        }
+    }
+}
+
+/// A single `GROUP BY` key. Built from the generated `*Filters` types, e.g. `f.region.group()`
+/// or `f.created_at.truncate(DatePart::Day)`.
+///
+/// The alias defaults to the column name, and can be overridden with `alias()`.
+#[derive(Clone, Hash)]
+pub struct GroupKey {
+    column: Identifier,
+    truncate: Option<DatePart>,
+    alias: Identifier,
+}
+
+impl GroupKey {
+    pub fn new(column: Identifier) -> Self {
+        let alias = Identifier::new_unchecked(column.rsplit('.').next().unwrap_or(&column));
+        Self {
+            column,
+            truncate: None,
+            alias,
+        }
+    }
+
+    pub fn truncate(
+        mut self,
+        date_part: DatePart,
+    ) -> Self {
+        self.truncate = Some(date_part);
+        self
+    }
+
+    /// Overrides the column alias. Aliases are written into the query unquoted, so they're validated like any other
+    /// identifier.
+    pub fn alias(
+        mut self,
+        alias: impl Into<String>,
+    ) -> Result<Self, String> {
+        self.alias = Identifier::new(alias)?;
+        Ok(self)
+    }
+
+    pub fn asc(&self) -> OrderBy {
+        OrderBy::new(self.alias.clone(), OrderDirection::Ascending)
+    }
+
+    pub fn desc(&self) -> OrderBy {
+        OrderBy::new(self.alias.clone(), OrderDirection::Desending)
+    }
+}
+
+impl IntoIterator for GroupKey {
+    type Item = GroupKey;
+    type IntoIter = std::iter::Once<GroupKey>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl BuildSql for GroupKey {
+    fn build_sql(
+        &self,
+        builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    ) {
+        self.write_sql(builder);
+    }
+}
+
+impl GroupKey {
+    pub(crate) fn write_sql(
+        &self,
+        sink: &mut impl SqlSink,
+    ) {
+        match &self.truncate {
+            Some(date_part) => sink.push(format!("date_trunc('{date_part}', {})", self.column)),
+            None => sink.push(&self.column),
+        };
+    }
+}
+
+/// A `GROUP BY` query over the table of `T`. Unlike `Query<T>`, relations are never joined, and each
+/// row contains only the group keys and aggregates - deserialize it into a struct with matching field names.
+///
+/// Created with `Query::group_by()`.
+pub struct AggregateQuery<T> {
+    /// The table to select from, qualified with its schema.
+    pub(crate) table_name: Identifier,
+    pub(crate) filter: Option<Filter>,
+    pub(crate) group_by: Vec<GroupKey>,
+    pub(crate) aggregates: Vec<Aggregate>,
+    pub(crate) having: Option<Filter>,
+    pub(crate) order_by: Vec<OrderBy>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) _t: PhantomData<T>,
+}
+
+impl<T> Query<T> {
+    /// Groups the results of this query by `keys`. Any existing `filter` and `limit` carry over, but `order_by` does
+    /// not: the grouped rows can only be ordered by their keys and aggregates, so set it on the `AggregateQuery`.
+    pub fn group_by(
+        self,
+        keys: impl IntoIterator<Item = GroupKey>,
+    ) -> AggregateQuery<T> {
+        AggregateQuery {
+            table_name: self.table.qualified_name(),
+            filter: self
+                .filter
+                .map(|filter| filter.qualify_tables(&|name| self.table.qualified_name_of(name))),
+            group_by: keys.into_iter().collect(),
+            aggregates: Vec::new(),
+            having: None,
+            order_by: Vec::new(),
+            limit: self.limit,
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<T> AggregateQuery<T> {
+    pub fn aggregate(
+        mut self,
+        aggregates: impl IntoIterator<Item = Aggregate>,
+    ) -> Self {
+        self.aggregates.extend(aggregates);
+        self
+    }
+
+    pub fn having(
+        mut self,
+        filter: Filter,
+    ) -> Self {
+        if let Some(f) = self.having {
+            self.having = Some(f & filter)
+        } else {
+            self.having = Some(filter)
+        }
+        self
+    }
+
+    /// Appends `order_by` to the existing sort keys. Sort by the keys returned from `GroupKey::asc()`
+    /// or `Aggregate::desc()` etc, as ungrouped columns can't be sorted on.
+    pub fn order_by(
+        mut self,
+        order_by: impl IntoIterator<Item = OrderBy>,
+    ) -> Self {
+        self.order_by.extend(order_by);
+        self
+    }
+
+    pub fn limit(
+        mut self,
+        limit: usize,
+    ) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl<T> BuildSql for AggregateQuery<T> {
+    fn build_sql(
+        &self,
+        query_builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    ) {
+        self.write_sql(query_builder);
+    }
+}
+
+impl<T> CacheableSql for AggregateQuery<T> {
+    fn hash_shape(
+        &self,
+        state: &mut ShapeHasher,
+    ) {
+        self.table_name.hash(state);
+        for filter in [&self.filter, &self.having] {
+            filter.is_some().hash(state);
+            if let Some(filter) = filter {
+                filter.hash_shape(state);
+            }
+        }
+        self.group_by.hash(state);
+        self.aggregates.hash(state);
+        self.order_by.hash(state);
+        self.limit.is_some().hash(state);
+    }
+
+    fn write_sql(
+        &self,
+        query_builder: &mut impl SqlSink,
+    ) {
+        query_builder.push("SELECT ");
+        let mut first = true;
+        for key in &self.group_by {
+            if !first {
+                query_builder.push(", ");
+            }
+            first = false;
+            key.write_sql(query_builder);
+            query_builder.push(" AS ").push(&key.alias);
+        }
+        for aggregate in &self.aggregates {
+            if !first {
+                query_builder.push(", ");
+            }
+            first = false;
+            aggregate.write_sql(query_builder);
+            query_builder.push(" AS ").push(&aggregate.alias);
+        }
+        query_builder.push(" FROM ").push(&self.table_name);
+        if let Some(filter) = &self.filter {
+            query_builder.push(" WHERE ");
+            filter.write_sql(query_builder);
+        }
+        if !self.group_by.is_empty() {
+            query_builder.push(" GROUP BY ");
+            let mut keys = self.group_by.iter().peekable();
+            while let Some(key) = keys.next() {
+                key.write_sql(query_builder);
+                if keys.peek().is_some() {
+                    query_builder.push(", ");
+                }
+            }
+        }
+        if let Some(having) = &self.having {
+            query_builder.push(" HAVING ");
+            having.write_sql(query_builder);
+        }
+        if !self.order_by.is_empty() {
+            query_builder.push(" ORDER BY ");
+            let mut order_by = self.order_by.iter().peekable();
+            while let Some(key) = order_by.next() {
+                key.write_sql(query_builder);
+                if order_by.peek().is_some() {
+                    query_builder.push(", ");
+                }
+            }
+        }
+        if let Some(limit) = self.limit {
+            query_builder
+                .push(" LIMIT ")
+                .push_bind(FilterComparisonParam::Integer(limit as i64));
+        }
+    }
+
+    fn collect_binds(
+        &self,
+        binds: &mut Vec<FilterComparisonParam>,
+    ) {
+        for filter in [&self.filter, &self.having].into_iter().flatten() {
+            filter.collect_binds(binds);
+        }
+        if let Some(limit) = self.limit {
+            binds.push(FilterComparisonParam::Integer(limit as i64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        data_definition::table::{DatabaseTableDefinition, Identifier, TableColumn},
+        queries::{filterable_types::FilterableType, Aggregate, DatePart, Query},
+        BuildSql,
+    };
+
+    #[test]
+    fn group_by_renders_keys_aggregates_and_having() {
+        let table = DatabaseTableDefinition::new("orders")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::string("region").unwrap())
+            .column(TableColumn::float("amount").unwrap())
+            .column(TableColumn::timestamp("created_at").unwrap());
+        let region = FilterableType::<String>::new(Identifier::new_unchecked("orders.region"));
+        let amount = FilterableType::<f64>::new(Identifier::new_unchecked("orders.amount"));
+        let created_at = FilterableType::<chrono::NaiveDateTime>::new(Identifier::new_unchecked(
+            "orders.created_at",
+        ));
+
+        let revenue = amount.sum().alias("revenue").unwrap();
+        let query = Query::<()>::new(Arc::new(table))
+            .group_by([created_at.truncate(DatePart::Day).alias("day").unwrap(), region.group()])
+            .aggregate([revenue.clone(), Aggregate::count_all()])
+            .having(revenue.gt(100.0))
+            .order_by(revenue.desc());
+        let mut builder = sqlx::QueryBuilder::new("");
+        query.build_sql(&mut builder);
+
+        assert_eq!(
+            builder.sql(),
+            "SELECT date_trunc('day', orders.created_at) AS day, orders.region AS region, SUM(orders.amount) AS revenue, COUNT(*) AS count \
+             FROM orders GROUP BY date_trunc('day', orders.created_at), orders.region \
+             HAVING SUM(orders.amount) > $1 ORDER BY revenue DESC"
+        );
+    }
+
+    #[test]
+    fn group_by_drops_the_row_ordering_and_rejects_bad_aliases() {
+        let table = DatabaseTableDefinition::new("orders")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::string("region").unwrap());
+        let id = FilterableType::<uuid::Uuid>::new(Identifier::new_unchecked("orders.id"));
+        let region = FilterableType::<String>::new(Identifier::new_unchecked("orders.region"));
+
+        let query = Query::<()>::new(Arc::new(table))
+            .order_by([id.asc()])
+            .group_by([region.group()])
+            .aggregate([Aggregate::count_all()]);
+        let mut builder = sqlx::QueryBuilder::new("");
+        query.build_sql(&mut builder);
+
+        assert_eq!(
+            builder.sql(),
+            "SELECT orders.region AS region, COUNT(*) AS count FROM orders GROUP BY orders.region"
+        );
+        assert!(Aggregate::count_all().alias("count; DROP TABLE orders").is_err());
+        assert!(region.group().alias("day)").is_err());
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use sqlx::{postgres::PgArguments, Postgres, QueryBuilder};
+
+use crate::BuildSql;
+
+use super::{ArrayParam, FilterComparisonParam};
+
+/// Somewhere to write SQL to. Statements write through this instead of directly to a `QueryBuilder`,
+/// so that the same code can either render the SQL, or write it some other way (e.g. with values inline).
+pub trait SqlSink {
+    fn push(
+        &mut self,
+        sql: impl Display,
+    ) -> &mut Self;
+
+    /// Binds `value` as a parameter. Non-value params (columns, aggregates, `null`) are written as SQL instead.
+    fn push_bind(
+        &mut self,
+        value: FilterComparisonParam,
+    ) -> &mut Self;
+}
+
+impl SqlSink for QueryBuilder<'_, Postgres> {
+    fn push(
+        &mut self,
+        sql: impl Display,
+    ) -> &mut Self {
+        QueryBuilder::push(self, sql)
+    }
+
+    fn push_bind(
+        &mut self,
+        value: FilterComparisonParam,
+    ) -> &mut Self {
+        type P = FilterComparisonParam;
+        match value {
+            P::String(val) => QueryBuilder::push_bind(self, val),
+            P::Uuid(val) => QueryBuilder::push_bind(self, val),
+            P::Integer(val) => QueryBuilder::push_bind(self, val),
+            P::Float(val) => QueryBuilder::push_bind(self, val),
+            P::Bool(val) => QueryBuilder::push_bind(self, val),
+            P::Timestamp(val) => QueryBuilder::push_bind(self, val),
+            P::Numeric(val) => QueryBuilder::push_bind(self, val),
+            P::Date(val) => QueryBuilder::push_bind(self, val),
+            P::Time(val) => QueryBuilder::push_bind(self, val),
+            P::TimestampTz(val) => QueryBuilder::push_bind(self, val),
+            P::Bytea(val) => QueryBuilder::push_bind(self, val),
+            P::Interval(val) => QueryBuilder::push_bind(self, val),
+            // Array operators need both sides to be the same type, so the cast is written out.
+            P::Array(val) => {
+                let sql_type = val.sql_type();
+                match val {
+                    ArrayParam::Bool(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::Int(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::Integer(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::Float(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::String(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::Uuid(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::Timestamp(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::Numeric(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::Date(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::Time(values) => QueryBuilder::push_bind(self, values),
+                    ArrayParam::TimestampTz(values) => QueryBuilder::push_bind(self, values),
+                }
+                .push(format!("::{sql_type}"))
+            },
+            // Text isn't implicitly cast to an enum, so the cast is written out.
+            P::Enum(type_name, val) => {
+                QueryBuilder::push_bind(self, val).push(format!("::{type_name}"))
+            },
+            P::TableColumn(_) | P::Aggregate(_) | P::Null => {
+                value.write_sql(self);
+                self
+            },
+        }
+    }
+}
+
+/// Ignores the SQL, and keeps the bound values in order. Checks `CacheableSql::collect_binds()` in debug builds.
+#[derive(Default)]
+pub(crate) struct BindCollector {
+    pub(crate) binds: Vec<FilterComparisonParam>,
+}
+
+impl SqlSink for BindCollector {
+    fn push(
+        &mut self,
+        _sql: impl Display,
+    ) -> &mut Self {
+        self
+    }
+
+    fn push_bind(
+        &mut self,
+        value: FilterComparisonParam,
+    ) -> &mut Self {
+        type P = FilterComparisonParam;
+        match value {
+            P::TableColumn(_) | P::Aggregate(_) | P::Null => {},
+            value => self.binds.push(value),
+        }
+        self
+    }
+}
+
+/// Writes bound values inline as SQL literals, for DDL (e.g. `CHECK` constraints) where parameters aren't allowed.
+#[derive(Default)]
+pub(crate) struct InlineSql {
+    pub(crate) sql: String,
+}
+
+impl SqlSink for InlineSql {
+    fn push(
+        &mut self,
+        sql: impl Display,
+    ) -> &mut Self {
+        self.sql.push_str(&sql.to_string());
+        self
+    }
+
+    fn push_bind(
+        &mut self,
+        value: FilterComparisonParam,
+    ) -> &mut Self {
+        type P = FilterComparisonParam;
+        let literal = match &value {
+            P::String(val) => format!("'{}'", val.replace('\'', "''")),
+            P::Uuid(val) => format!("'{val}'::uuid"),
+            P::Integer(val) => val.to_string(),
+            P::Float(val) if val.is_finite() => val.to_string(),
+            P::Float(val) => format!("'{val}'::float8"),
+            P::Bool(val) => val.to_string().to_uppercase(),
+            P::Timestamp(val) => format!("'{val}'::timestamp"),
+            P::Numeric(val) => format!("'{val}'::numeric"),
+            P::Date(val) => format!("'{val}'::date"),
+            P::Time(val) => format!("'{val}'::time"),
+            P::TimestampTz(val) => format!("'{}'::timestamptz", val.to_rfc3339()),
+            P::Bytea(val) => {
+                let hex = val.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+                format!("'\\x{hex}'::bytea")
+            },
+            P::Interval(val) => {
+                format!("'{}.{:06} seconds'::interval", val.as_secs(), val.subsec_micros())
+            },
+            P::Array(val) => {
+                self.push("ARRAY[");
+                let mut elements = val.elements().into_iter().peekable();
+                while let Some(element) = elements.next() {
+                    self.push_bind(element);
+                    if elements.peek().is_some() {
+                        self.push(", ");
+                    }
+                }
+                return self.push(format!("]::{}", val.sql_type()));
+            },
+            P::Enum(type_name, val) => format!("'{}'::{type_name}", val.replace('\'', "''")),
+            P::TableColumn(_) | P::Aggregate(_) | P::Null => {
+                value.write_sql(self);
+                return self;
+            },
+        };
+        self.push(literal)
+    }
+}
+
+/// A statement whose rendered SQL can be cached, and reused for any other statement of the same shape.
+pub trait CacheableSql: BuildSql {
+    /// Hashes everything that affects the rendered SQL (tables, columns, filter structure, the
+    /// *types* of bound values, etc.), but not the bound values themselves.
+    fn hash_shape(
+        &self,
+        state: &mut ShapeHasher,
+    );
+
+    /// Writes the statement to `sink`. `BuildSql::build_sql` is implemented on top of this.
+    fn write_sql(
+        &self,
+        sink: &mut impl SqlSink,
+    ) where
+        Self: Sized;
+
+    /// Collects the values that `write_sql()` binds, in the same order, without rendering any SQL - so that a
+    /// cached statement only pays for its values.
+    fn collect_binds(
+        &self,
+        binds: &mut Vec<FilterComparisonParam>,
+    );
+
+    /// Returns the SQL for this statement - from the cache if a statement of the same shape has been
+    /// rendered before - along with the values to bind to it.
+    fn cached_sql(&self) -> CachedStatement
+    where
+        Self: Sized,
+    {
+        thread_local! {
+            // Reused for every statement, so that looking one up doesn't allocate a key.
+            static SHAPE: RefCell<ShapeHasher> = RefCell::default();
+        }
+
+        let mut binds = Vec::new();
+        self.collect_binds(&mut binds);
+        if cfg!(debug_assertions) {
+            let mut collector = BindCollector::default();
+            self.write_sql(&mut collector);
+            assert_eq!(
+                format!("{binds:?}"),
+                format!("{:?}", collector.binds),
+                "collect_binds() doesn't match the values bound by write_sql()"
+            );
+        }
+
+        let cache = sql_cache();
+        let lookup = SHAPE.with_borrow_mut(|shape| {
+            shape.bytes.clear();
+            std::any::type_name::<Self>().hash(shape);
+            self.hash_shape(shape);
+            match cache.read().ok().and_then(|c| c.get(&*shape.bytes).cloned()) {
+                Some(sql) => Ok(sql),
+                None => Err(Box::<[u8]>::from(shape.bytes.as_slice())),
+            }
+        });
+        let key = match lookup {
+            Ok(sql) => {
+                return CachedStatement {
+                    sql,
+                    binds,
+                }
+            },
+            Err(key) => key,
+        };
+
+        let mut builder = QueryBuilder::new("");
+        self.write_sql(&mut builder);
+        let sql: Arc<str> = builder.into_sql().into();
+        if let Ok(mut cache) = cache.write() {
+            if cache.len() >= MAX_CACHED_STATEMENTS {
+                cache.clear();
+            }
+            cache.insert(key, sql.clone());
+        }
+        CachedStatement {
+            sql,
+            binds,
+        }
+    }
+}
+
+/// Records everything written to it, rather than hashing it, so that the shape of a statement can be used as its
+/// cache key without any risk of two different shapes colliding.
+#[derive(Default)]
+pub struct ShapeHasher {
+    bytes: Vec<u8>,
+}
+
+impl Hasher for ShapeHasher {
+    fn write(
+        &mut self,
+        bytes: &[u8],
+    ) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::new();
+        self.bytes.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+// Shapes are usually fixed by the code calling the ORM, but not always (e.g. `IN` lists of different
+// lengths, or user-supplied filters), so the cache is bounded.
+const MAX_CACHED_STATEMENTS: usize = 4096;
+
+type SqlCache = RwLock<HashMap<Box<[u8]>, Arc<str>>>;
+
+fn sql_cache() -> &'static SqlCache {
+    static SQL_CACHE: OnceLock<SqlCache> = OnceLock::new();
+    SQL_CACHE.get_or_init(Default::default)
+}
+
+/// Rendered SQL, and the values to bind to it.
+pub struct CachedStatement {
+    pub sql: Arc<str>,
+    pub binds: Vec<FilterComparisonParam>,
+}
+
+impl CachedStatement {
+    /// Wraps the SQL, e.g. in a `to_json` select. `prefix` and `suffix` must not contain placeholders.
+    pub(crate) fn wrap(
+        self,
+        prefix: &str,
+        suffix: &str,
+    ) -> Self {
+        Self {
+            sql: format!("{prefix}{}{suffix}", self.sql).into(),
+            binds: self.binds,
+        }
+    }
+
+    /// Builds a `sqlx` query with the bound values. `sqlx` prepares and caches the statement on each
+    /// connection, keyed by the SQL - which is now identical for every statement of the same shape.
+    pub fn query(&self) -> sqlx::query::Query<'_, Postgres, PgArguments> {
+        type P = FilterComparisonParam;
+        self.binds.iter().fold(sqlx::query(&self.sql), |query, bind| match bind {
+            P::String(val) => query.bind(val.clone()),
+            P::Uuid(val) => query.bind(*val),
+            P::Integer(val) => query.bind(*val),
+            P::Float(val) => query.bind(*val),
+            P::Bool(val) => query.bind(*val),
+            P::Timestamp(val) => query.bind(*val),
+            P::Numeric(val) => query.bind(*val),
+            P::Date(val) => query.bind(*val),
+            P::Time(val) => query.bind(*val),
+            P::TimestampTz(val) => query.bind(*val),
+            P::Bytea(val) => query.bind(val.clone()),
+            P::Interval(val) => query.bind(*val),
+            P::Array(val) => match val {
+                ArrayParam::Bool(values) => query.bind(values.clone()),
+                ArrayParam::Int(values) => query.bind(values.clone()),
+                ArrayParam::Integer(values) => query.bind(values.clone()),
+                ArrayParam::Float(values) => query.bind(values.clone()),
+                ArrayParam::String(values) => query.bind(values.clone()),
+                ArrayParam::Uuid(values) => query.bind(values.clone()),
+                ArrayParam::Timestamp(values) => query.bind(values.clone()),
+                ArrayParam::Numeric(values) => query.bind(values.clone()),
+                ArrayParam::Date(values) => query.bind(values.clone()),
+                ArrayParam::Time(values) => query.bind(values.clone()),
+                ArrayParam::TimestampTz(values) => query.bind(values.clone()),
+            },
+            P::Enum(_, val) => query.bind(val.clone()),
+            P::TableColumn(_) | P::Aggregate(_) | P::Null => query,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        data_definition::table::{DatabaseTableDefinition, Identifier, TableColumn},
+        queries::{
+            filterable_types::{FilterEq, FilterPartialEq, FilterableType},
+            CacheableSql, Filter, FilterComparisonParam, Query,
+        },
+        BuildSql,
+    };
+
+    #[test]
+    fn cached_sql_matches_built_sql_and_rebinds_values() {
+        let table = Arc::new(
+            DatabaseTableDefinition::new("cache_item")
+                .unwrap()
+                .column(TableColumn::uuid("id").unwrap().non_null().pk())
+                .column(TableColumn::string("name").unwrap())
+                .column(TableColumn::int("stock").unwrap()),
+        );
+        let name = FilterableType::<String>::new(Identifier::new_unchecked("cache_item.name"));
+        let stock = FilterableType::<i64>::new(Identifier::new_unchecked("cache_item.stock"));
+        let query = |n: &str, s: i64| {
+            Query::<()>::new(table.clone())
+                .filter(name.eq(n) & stock.gt(s))
+                .order_by(name.asc())
+                .limit(10)
+        };
+
+        let first = query("a", 1).cached_sql();
+        let second = query("b", 2).cached_sql();
+        let mut builder = sqlx::QueryBuilder::new("");
+        query("c", 3).build_sql(&mut builder);
+
+        assert_eq!(first.sql, second.sql);
+        assert_eq!(&*first.sql, builder.sql());
+        assert!(first.sql.ends_with(" LIMIT $3"));
+        assert!(matches!(
+            &second.binds[..],
+            [
+                FilterComparisonParam::String(n),
+                FilterComparisonParam::Integer(2),
+                FilterComparisonParam::Integer(10)
+            ] if n == "b"
+        ));
+        // A different shape gets different SQL. Raw placeholders are bound once for each use.
+        let raw = Filter::raw(
+            "cache_item.stock BETWEEN $1 AND $1 + 10",
+            vec![FilterComparisonParam::Integer(5)],
+        )
+        .unwrap();
+        let third = Query::<()>::new(table.clone()).filter(name.eq("a") & raw).cached_sql();
+        assert_ne!(third.sql, first.sql);
+        assert_eq!(third.binds.len(), 3);
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The output of `EXPLAIN (FORMAT JSON)` for a single statement.
+///
+/// Timings are only present when the plan was generated with `ANALYZE`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueryPlan {
+    #[serde(rename = "Plan")]
+    pub plan: PlanNode,
+    /// In milliseconds.
+    #[serde(rename = "Planning Time")]
+    pub planning_time: Option<f64>,
+    /// In milliseconds.
+    #[serde(rename = "Execution Time")]
+    pub execution_time: Option<f64>,
+}
+
+/// A single node of a query plan, e.g. a `Seq Scan` or `Hash Join`.
+/// The `actual_*` fields are only present when the plan was generated with `ANALYZE`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlanNode {
+    #[serde(rename = "Node Type")]
+    pub node_type: String,
+    #[serde(rename = "Relation Name")]
+    pub relation_name: Option<String>,
+    #[serde(rename = "Alias")]
+    pub alias: Option<String>,
+    #[serde(rename = "Startup Cost")]
+    pub startup_cost: f64,
+    #[serde(rename = "Total Cost")]
+    pub total_cost: f64,
+    #[serde(rename = "Plan Rows")]
+    pub plan_rows: f64,
+    #[serde(rename = "Plan Width")]
+    pub plan_width: i64,
+    /// In milliseconds.
+    #[serde(rename = "Actual Startup Time")]
+    pub actual_startup_time: Option<f64>,
+    /// In milliseconds, per loop.
+    #[serde(rename = "Actual Total Time")]
+    pub actual_total_time: Option<f64>,
+    /// Per loop.
+    #[serde(rename = "Actual Rows")]
+    pub actual_rows: Option<f64>,
+    #[serde(rename = "Actual Loops")]
+    pub actual_loops: Option<i64>,
+    #[serde(rename = "Plans", default)]
+    pub plans: Vec<PlanNode>,
+    /// Everything else Postgres reports for this node (filters, join conditions, sort keys, etc).
+    #[serde(flatten)]
+    pub details: HashMap<String, serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryPlan;
+
+    #[test]
+    fn deserializes_explain_analyze_output() {
+        let output = r#"[{
+            "Plan": {
+                "Node Type": "Hash Join", "Parallel Aware": false, "Join Type": "Left",
+                "Startup Cost": 1.09, "Total Cost": 2.21, "Plan Rows": 4, "Plan Width": 64,
+                "Actual Startup Time": 0.031, "Actual Total Time": 0.040, "Actual Rows": 3, "Actual Loops": 1,
+                "Hash Cond": "(customer.id = orders.customer_id)",
+                "Plans": [{
+                    "Node Type": "Seq Scan", "Relation Name": "orders", "Alias": "orders",
+                    "Startup Cost": 0.00, "Total Cost": 1.04, "Plan Rows": 4, "Plan Width": 48
+                }]
+            },
+            "Planning Time": 0.101,
+            "Execution Time": 0.065
+        }]"#;
+        let plan = serde_json::from_str::<Vec<QueryPlan>>(output).unwrap().remove(0);
+
+        assert_eq!(plan.plan.node_type, "Hash Join");
+        assert_eq!(plan.plan.actual_rows, Some(3.0));
+        assert_eq!(plan.plan.details["Hash Cond"], "(customer.id = orders.customer_id)");
+        assert_eq!(plan.plan.plans[0].relation_name.as_deref(), Some("orders"));
+        assert_eq!(plan.plan.plans[0].actual_rows, None);
+        assert_eq!(plan.execution_time, Some(0.065));
+    }
+}
@@ -0,0 +1,518 @@
+use std::fmt::Display;
+
+use crate::data_definition::table::{DatabaseColumnType, DatabaseTableDefinition, Identifier};
+
+use super::{
+    query_string::{filterable_type, Operator},
+    ArrayParam, Filter, FilterComparisonParam,
+};
+
+/// An error from `Filter::parse()`, pointing at the (byte) position in the input where it went wrong.
+#[derive(Debug, PartialEq)]
+pub struct FilterParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl FilterParseError {
+    fn new(
+        position: usize,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for FilterParseError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(f, "{} (at position {})", self.message, self.position)
+    }
+}
+
+impl std::error::Error for FilterParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Comparison(&'static str),
+    LeftParen,
+    RightParen,
+    Comma,
+    End,
+}
+
+impl Display for TokenKind {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            TokenKind::Ident(ident) => write!(f, "`{ident}`"),
+            TokenKind::String(string) => write!(f, "'{string}'"),
+            TokenKind::Integer(int) => write!(f, "{int}"),
+            TokenKind::Float(float) => write!(f, "{float}"),
+            TokenKind::Comparison(op) => write!(f, "`{op}`"),
+            TokenKind::LeftParen => f.write_str("`(`"),
+            TokenKind::RightParen => f.write_str("`)`"),
+            TokenKind::Comma => f.write_str("`,`"),
+            TokenKind::End => f.write_str("end of input"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FilterParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(position, c)) = chars.peek() {
+        let kind = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            },
+            '(' => {
+                chars.next();
+                TokenKind::LeftParen
+            },
+            ')' => {
+                chars.next();
+                TokenKind::RightParen
+            },
+            ',' => {
+                chars.next();
+                TokenKind::Comma
+            },
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let next = chars.peek().map(|&(_, c)| c);
+                let (op, len) = match (c, next) {
+                    ('=', _) => ("=", 1),
+                    ('!', Some('=')) | ('<', Some('>')) => ("!=", 2),
+                    ('<', Some('=')) => ("<=", 2),
+                    ('>', Some('=')) => (">=", 2),
+                    ('<', _) => ("<", 1),
+                    ('>', _) => (">", 1),
+                    _ => return Err(FilterParseError::new(position, "Expected `!=`")),
+                };
+                if len == 2 {
+                    chars.next();
+                }
+                TokenKind::Comparison(op)
+            },
+            '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        // `''` is an escaped quote, as in SQL
+                        Some((_, '\'')) if matches!(chars.peek(), Some((_, '\''))) => {
+                            chars.next();
+                            value.push('\'');
+                        },
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => value.push(c),
+                        None => return Err(FilterParseError::new(position, "Unterminated string")),
+                    }
+                }
+                TokenKind::String(value)
+            },
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let mut end = position;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.' || (i == position && c == '-')) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let text = &input[position..end];
+                if let Ok(int) = text.parse() {
+                    TokenKind::Integer(int)
+                } else if let Ok(float) = text.parse() {
+                    TokenKind::Float(float)
+                } else {
+                    return Err(FilterParseError::new(
+                        position,
+                        format!("Invalid number `{text}`"),
+                    ));
+                }
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = position;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + 1;
+                    chars.next();
+                }
+                TokenKind::Ident(input[position..end].to_string())
+            },
+            c => {
+                return Err(FilterParseError::new(position, format!("Unexpected character `{c}`")))
+            },
+        };
+        tokens.push(Token {
+            kind,
+            position,
+        });
+    }
+    tokens.push(Token {
+        kind: TokenKind::End,
+        position: input.len(),
+    });
+    Ok(tokens)
+}
+
+/// A recursive descent parser for the grammar:
+/// ```text
+/// expr       := and ("or" and)*
+/// and        := primary ("and" primary)*
+/// primary    := "(" expr ")" | comparison
+/// comparison := column ("=" | "!=" | "<>" | "<" | "<=" | ">" | ">=" | "like") literal
+///             | column "in" "(" literal ("," literal)* ")"
+///             | column "is" ["not"] "null"
+/// literal    := 'string' | number | true | false
+/// ```
+/// Keywords are case-insensitive.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    index: usize,
+    table: &'a DatabaseTableDefinition,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].clone();
+        if token.kind != TokenKind::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn is_keyword(
+        &self,
+        keyword: &str,
+    ) -> bool {
+        matches!(&self.peek().kind, TokenKind::Ident(ident) if ident.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect(
+        &mut self,
+        kind: TokenKind,
+    ) -> Result<(), FilterParseError> {
+        let token = self.next();
+        if token.kind == kind {
+            Ok(())
+        } else {
+            Err(FilterParseError::new(
+                token.position,
+                format!("Expected {kind}, found {}", token.kind),
+            ))
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Filter, FilterParseError> {
+        let mut filters = vec![self.parse_and()?];
+        while self.is_keyword("or") {
+            self.next();
+            filters.push(self.parse_and()?);
+        }
+        Ok(if filters.len() == 1 { filters.remove(0) } else { Filter::Or(filters) })
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, FilterParseError> {
+        let mut filters = vec![self.parse_primary()?];
+        while self.is_keyword("and") {
+            self.next();
+            filters.push(self.parse_primary()?);
+        }
+        Ok(if filters.len() == 1 { filters.remove(0) } else { Filter::And(filters) })
+    }
+
+    fn parse_primary(&mut self) -> Result<Filter, FilterParseError> {
+        if self.peek().kind == TokenKind::LeftParen {
+            self.next();
+            let filter = self.parse_expr()?;
+            self.expect(TokenKind::RightParen)?;
+            return Ok(filter);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Filter, FilterParseError> {
+        let token = self.next();
+        let TokenKind::Ident(column_name) = &token.kind else {
+            return Err(FilterParseError::new(
+                token.position,
+                format!("Expected a field name, found {}", token.kind),
+            ));
+        };
+        let column = Identifier::new(column_name.as_str())
+            .ok()
+            .and_then(|ident| self.table.columns.get(&ident))
+            .ok_or_else(|| {
+                FilterParseError::new(
+                    token.position,
+                    format!("Unknown field `{column_name}` on `{}`", self.table.table_name),
+                )
+            })?;
+        let column_type = filterable_type(column).ok_or_else(|| {
+            FilterParseError::new(
+                token.position,
+                format!("Field `{column_name}` can't be filtered on"),
+            )
+        })?;
+        let column_ref = FilterComparisonParam::TableColumn(Identifier::new_unchecked(format!(
+            "{}.{}",
+            self.table.table_name, column.column_name
+        )));
+
+        let op_token = self.next();
+        let operator = match &op_token.kind {
+            TokenKind::Comparison("=") => Operator::Eq,
+            TokenKind::Comparison("!=") => Operator::Ne,
+            TokenKind::Comparison("<") => Operator::Lt,
+            TokenKind::Comparison("<=") => Operator::Lte,
+            TokenKind::Comparison(">") => Operator::Gt,
+            TokenKind::Comparison(">=") => Operator::Gte,
+            TokenKind::Ident(kw) if kw.eq_ignore_ascii_case("like") => Operator::Like,
+            TokenKind::Ident(kw) if kw.eq_ignore_ascii_case("in") => Operator::In,
+            TokenKind::Ident(kw) if kw.eq_ignore_ascii_case("contains") => Operator::Contains,
+            TokenKind::Ident(kw) if kw.eq_ignore_ascii_case("overlaps") => Operator::Overlaps,
+            TokenKind::Ident(kw) if kw.eq_ignore_ascii_case("is") => {
+                let negate = self.is_keyword("not");
+                if negate {
+                    self.next();
+                }
+                if !self.is_keyword("null") {
+                    let token = self.next();
+                    return Err(FilterParseError::new(
+                        token.position,
+                        format!("Expected `null`, found {}", token.kind),
+                    ));
+                }
+                self.next();
+                return Ok(if negate {
+                    Filter::NotEqual(column_ref, FilterComparisonParam::Null)
+                } else {
+                    Filter::Equal(column_ref, FilterComparisonParam::Null)
+                });
+            },
+            kind => {
+                return Err(FilterParseError::new(
+                    op_token.position,
+                    format!("Expected a comparison operator, found {kind}"),
+                ))
+            },
+        };
+        if !operator.is_supported_for(&column_type) {
+            return Err(FilterParseError::new(
+                op_token.position,
+                format!("Operator {} is not supported for field `{column_name}`", op_token.kind),
+            ));
+        }
+
+        if operator == Operator::In {
+            let values = self.parse_list(column_name, &column_type)?;
+            return Ok(Filter::In(column_ref, values));
+        }
+        if let (Operator::Contains | Operator::Overlaps, DatabaseColumnType::Array(element_type)) =
+            (operator, &column_type)
+        {
+            let position = self.peek().position;
+            let elements = self.parse_list(column_name, element_type)?;
+            let array = ArrayParam::from_elements(element_type, elements).ok_or_else(|| {
+                FilterParseError::new(
+                    position,
+                    format!("Arrays of {} can't be filtered on", element_type.as_str()),
+                )
+            })?;
+            let array = FilterComparisonParam::Array(array);
+            return Ok(match operator {
+                Operator::Contains => Filter::Contains(column_ref, array),
+                _ => Filter::Overlaps(column_ref, array),
+            });
+        }
+
+        let value = self.parse_literal(column_name, &column_type)?;
+        Ok(match operator {
+            Operator::Eq => Filter::Equal(column_ref, value),
+            Operator::Ne => Filter::NotEqual(column_ref, value),
+            Operator::Lt => Filter::LessThan(column_ref, value),
+            Operator::Lte => Filter::LessThanOrEqual(column_ref, value),
+            Operator::Gt => Filter::GreaterThan(column_ref, value),
+            Operator::Gte => Filter::GreaterThanOrEqual(column_ref, value),
+            Operator::Like => Filter::Like(column_ref, value),
+            Operator::In | Operator::Contains | Operator::Overlaps => {
+                unreachable!("Handled above")
+            },
+        })
+    }
+
+    /// Parses a parenthesized, comma-separated list of literals, e.g. for `in (...)`.
+    fn parse_list(
+        &mut self,
+        column_name: &str,
+        column_type: &DatabaseColumnType,
+    ) -> Result<Vec<FilterComparisonParam>, FilterParseError> {
+        self.expect(TokenKind::LeftParen)?;
+        let mut values = vec![self.parse_literal(column_name, column_type)?];
+        while self.peek().kind == TokenKind::Comma {
+            self.next();
+            values.push(self.parse_literal(column_name, column_type)?);
+        }
+        self.expect(TokenKind::RightParen)?;
+        Ok(values)
+    }
+
+    /// Parses a literal, and checks that it matches the type of the column it's compared to.
+    fn parse_literal(
+        &mut self,
+        column_name: &str,
+        column_type: &DatabaseColumnType,
+    ) -> Result<FilterComparisonParam, FilterParseError> {
+        type E = DatabaseColumnType;
+        type P = FilterComparisonParam;
+        let token = self.next();
+        let value = match (column_type, &token.kind) {
+            (E::Boolean, TokenKind::Ident(b)) if b.eq_ignore_ascii_case("true") => {
+                Some(P::Bool(true))
+            },
+            (E::Boolean, TokenKind::Ident(b)) if b.eq_ignore_ascii_case("false") => {
+                Some(P::Bool(false))
+            },
+            (E::Int | E::BigInt, TokenKind::Integer(int)) => Some(P::Integer(*int)),
+            (E::Float, TokenKind::Integer(int)) => Some(P::Float(*int as f64)),
+            (E::Float, TokenKind::Float(float)) => Some(P::Float(*float)),
+            (E::String, TokenKind::String(string)) => Some(P::String(string.clone())),
+            (E::Uuid, TokenKind::String(string)) => string.parse().ok().map(P::Uuid),
+            (E::Timestamp, TokenKind::String(string)) => string.parse().ok().map(P::Timestamp),
+            (E::Numeric, TokenKind::Integer(int)) => Some(P::Numeric((*int).into())),
+            // Floats are written back out (shortest round-trip form) so that `9.99` stays exact.
+            (E::Numeric, TokenKind::Float(float)) => float.to_string().parse().ok().map(P::Numeric),
+            (E::Numeric, TokenKind::String(string)) => string.parse().ok().map(P::Numeric),
+            (E::Date, TokenKind::String(string)) => string.parse().ok().map(P::Date),
+            (E::Time, TokenKind::String(string)) => string.parse().ok().map(P::Time),
+            (E::TimestampTz, TokenKind::String(string)) => string.parse().ok().map(P::TimestampTz),
+            (E::Enum(enum_type), TokenKind::String(string)) => enum_type
+                .variants
+                .contains(string)
+                .then(|| P::Enum(enum_type.name.clone(), string.clone())),
+            _ => None,
+        };
+        value.ok_or_else(|| {
+            FilterParseError::new(
+                token.position,
+                format!(
+                    "Expected a value of type {} for field `{column_name}`, found {}",
+                    column_type.as_str(),
+                    token.kind
+                ),
+            )
+        })
+    }
+}
+
+impl Filter {
+    /// Parses a filter expression, such as `status = 'open' and (priority > 2 or assignee is null)`.
+    ///
+    /// Field names are resolved against `table`, and literals must match the field's type. Strings
+    /// use single quotes (escape a quote by doubling it), and keywords are case-insensitive.
+    /// Supported operators are `=`, `!=` (or `<>`), `<`, `<=`, `>`, `>=`, `like`, `in (...)`,
+    /// `is null` and `is not null`. Array fields support `contains (...)` and `overlaps (...)`.
+    pub fn parse(
+        input: &str,
+        table: &DatabaseTableDefinition,
+    ) -> Result<Filter, FilterParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            index: 0,
+            table,
+        };
+        let filter = parser.parse_expr()?;
+        let token = parser.next();
+        if token.kind != TokenKind::End {
+            return Err(FilterParseError::new(
+                token.position,
+                format!("Expected `and`, `or` or end of input, found {}", token.kind),
+            ));
+        }
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data_definition::table::{DatabaseTableDefinition, TableColumn},
+        queries::Filter,
+        BuildSql,
+    };
+
+    use super::FilterParseError;
+
+    fn get_table_def() -> DatabaseTableDefinition {
+        DatabaseTableDefinition::new("ticket")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::string("status").unwrap().non_null())
+            .column(TableColumn::int("priority").unwrap().non_null())
+            .column(TableColumn::uuid("assignee").unwrap())
+    }
+
+    #[test]
+    fn parses_nested_expressions() {
+        let filter = Filter::parse(
+            "status = 'open' AND (priority > 2 or assignee is null) and status in ('a', 'it''s')",
+            &get_table_def(),
+        )
+        .unwrap();
+        let mut builder = sqlx::QueryBuilder::new("");
+        filter.build_sql(&mut builder);
+
+        assert_eq!(
+            builder.sql(),
+            "(ticket.status = $1 AND (ticket.priority > $2 OR ticket.assignee IS NULL) AND ticket.status IN ($3, $4))"
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_positions() {
+        let table = get_table_def();
+        let error = |input| Filter::parse(input, &table).unwrap_err();
+
+        assert_eq!(
+            error("status = 'open' and owner = 'me'"),
+            FilterParseError::new(20, "Unknown field `owner` on `ticket`")
+        );
+        assert_eq!(
+            error("priority > 'high'"),
+            FilterParseError::new(
+                11,
+                "Expected a value of type INT for field `priority`, found 'high'"
+            )
+        );
+        assert_eq!(error("priority like 1").position, 9);
+        assert_eq!(error("(priority > 1").position, 13);
+        assert_eq!(error("status = 'open").position, 9);
+    }
+}
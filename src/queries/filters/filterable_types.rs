@@ -1,13 +1,102 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, ops::Deref, sync::Arc};
 
 use uuid::Uuid;
 
-use crate::data_definition::table::Identifier;
+use crate::{
+    data_definition::table::{DbEnum, Identifier},
+    queries::{Aggregate, AggregateFunction, DatePart, GroupKey, OrderBy, OrderDirection},
+};
 
-use super::Filter;
+use super::{ArrayParam, Filter, FilterComparisonParam};
 
 pub trait Filterable {
     type FilterType: Default;
+
+    /// The key column that relationships to this type reference - see `DatabaseTableDefinition::relation_key()`.
+    const KEY_COLUMN: &'static str = "id";
+}
+
+/// Implemented by the generated `*Filters` structs, so that they can be built from inside a
+/// relationship. Every filter created by a scoped `*Filters` struct is wrapped in a correlated
+/// `EXISTS` subquery against the related table.
+pub trait RelationFilters
+where
+    Self: Default,
+{
+    fn in_scope(scope: Arc<RelationScope>) -> Self;
+}
+
+/// Describes how a related table joins back to the table it is being filtered from.
+///
+/// Scopes chain through `parent`, so that a filter on a grandchild table renders as nested
+/// `EXISTS` subqueries (e.g. `EXISTS (SELECT 1 FROM address AS address_1 WHERE ... AND EXISTS (...))`).
+///
+/// Each subquery aliases its table by how many subqueries it wraps (`{table}_{depth}`), so that
+/// self-referential relations (e.g. `category.children`) correlate against the parent row rather than themselves.
+#[derive(Clone)]
+pub struct RelationScope {
+    table_name: Identifier,
+    /// `(column on this table, column on the parent table)` pairs, fully qualified with the table names.
+    correlation: Vec<(Identifier, Identifier)>,
+    parent: Option<Arc<RelationScope>>,
+}
+
+impl RelationScope {
+    pub fn new(
+        table_name: Identifier,
+        correlation: Vec<(Identifier, Identifier)>,
+        parent: Option<Arc<RelationScope>>,
+    ) -> Self {
+        Self {
+            table_name,
+            correlation,
+            parent,
+        }
+    }
+
+    /// Wraps `filter` in an `EXISTS` subquery for this scope, and for every parent scope above it.
+    pub fn wrap(
+        &self,
+        filter: Filter,
+    ) -> Filter {
+        self.wrap_subquery(filter, false)
+    }
+
+    fn wrap_subquery(
+        &self,
+        filter: Filter,
+        negate: bool,
+    ) -> Filter {
+        let alias = subquery_alias(&self.table_name, &filter);
+        let correlation = self.correlation.iter().map(|(column, parent_column)| {
+            Filter::Equal(
+                FilterComparisonParam::TableColumn(column.clone()),
+                FilterComparisonParam::TableColumn(parent_column.clone()),
+            )
+            .alias_left(&self.table_name, &alias)
+        });
+        let subquery_filter = Box::new(Filter::And(
+            correlation.chain([filter.alias_table(&self.table_name, &alias)]).collect(),
+        ));
+        let filter = if negate {
+            Filter::NotExists(self.table_name.clone(), alias, subquery_filter)
+        } else {
+            Filter::Exists(self.table_name.clone(), alias, subquery_filter)
+        };
+        match &self.parent {
+            Some(parent) => parent.wrap(filter),
+            None => filter,
+        }
+    }
+}
+
+/// The alias for a subquery on `table_name` wrapping `filter`. Numbered by how many subqueries `filter` already
+/// nests, so that every alias is unique along the path from the outer query.
+fn subquery_alias(
+    table_name: &Identifier,
+    filter: &Filter,
+) -> Identifier {
+    Identifier::new_unchecked(format!("{table_name}_{}", filter.subquery_depth() + 1))
 }
 
 // Trying out the type-state pattern here.
@@ -26,11 +115,11 @@ macro_rules! impl_filter_for {
                     &self,
                     value: impl Into<<Self as $trait_name>::Type>,
                 ) -> Filter {
-                    Filter::$comparison_type(
+                    self.scoped(Filter::$comparison_type(
                         super::FilterComparisonParam::TableColumn(
                             self.column_name.clone(),                        ),
-                        super::FilterComparisonParam::$param_type_enum(value.into()),
-                    )
+                        super::FilterComparisonParam::$param_type_enum(Into::<$base_type>::into(value).into()),
+                    ))
                 }
                 )*
             }
@@ -69,6 +158,15 @@ macro_rules! impl_numeric_type {
         typetype! {$type}
         impl_filter_for!($type: $type, new_int, $db_type, FilterEq eq:Equal, ne:NotEqual);
         impl_filter_for!($type: $type, new_int, $db_type, FilterPartialEq lt:LessThan, lte:LessThanOrEqual, gt:GreaterThan, gte:GreaterThanOrEqual);
+        impl FilterableType<$type> {
+            pub fn sum(&self) -> Aggregate {
+                Aggregate::new(AggregateFunction::Sum, self.column_name.clone())
+            }
+
+            pub fn avg(&self) -> Aggregate {
+                Aggregate::new(AggregateFunction::Avg, self.column_name.clone())
+            }
+        }
     }
 }
 
@@ -87,26 +185,114 @@ impl_numeric_type!(f64: Float);
 
 // TODO: Implement this for OPTIONS too
 // TODO: Implement this for stronger dynamic typing with numerics
+// The integer key types (see `PrimaryKey`), so that `Related<T>` fields can be filtered on whatever `T` is keyed by.
+impl_numeric_type!(i32: Integer);
+impl_numeric_type!(i16: Integer);
+impl_numeric_type!(u32: Integer);
+impl_numeric_type!(f32: Float);
 // impl_numeric_type!(usize: Integer);
 // impl_numeric_type!(u64: Integer);
-// impl_numeric_type!(u32: Integer);
 // impl_numeric_type!(u16: Integer);
 // impl_numeric_type!(u8: Integer);
 // impl_numeric_type!(isize: Integer);
-// impl_numeric_type!(i32: Integer);
-// impl_numeric_type!(i16: Integer);
 // impl_numeric_type!(i8: Integer);
-// impl_numeric_type!(f32: Float);
+// impl_numeric_type!(i8: Integer);
 typetype! {chrono::NaiveDateTime}
 impl_filter_for!(chrono::NaiveDateTime: chrono::NaiveDateTime, new_timestamp, Timestamp, FilterEq eq:Equal, ne:NotEqual);
+typetype! {rust_decimal::Decimal}
+impl_filter_for!(rust_decimal::Decimal: rust_decimal::Decimal, numeric, Numeric, FilterEq eq:Equal, ne:NotEqual);
+impl_filter_for!(rust_decimal::Decimal: rust_decimal::Decimal, numeric, Numeric, FilterPartialEq lt:LessThan, lte:LessThanOrEqual, gt:GreaterThan, gte:GreaterThanOrEqual);
+typetype! {chrono::NaiveDate}
+impl_filter_for!(chrono::NaiveDate: chrono::NaiveDate, date, Date, FilterEq eq:Equal, ne:NotEqual);
+impl_filter_for!(chrono::NaiveDate: chrono::NaiveDate, date, Date, FilterPartialEq lt:LessThan, lte:LessThanOrEqual, gt:GreaterThan, gte:GreaterThanOrEqual);
+typetype! {chrono::NaiveTime}
+impl_filter_for!(chrono::NaiveTime: chrono::NaiveTime, time, Time, FilterEq eq:Equal, ne:NotEqual);
+impl_filter_for!(chrono::NaiveTime: chrono::NaiveTime, time, Time, FilterPartialEq lt:LessThan, lte:LessThanOrEqual, gt:GreaterThan, gte:GreaterThanOrEqual);
+typetype! {chrono::DateTime<chrono::Utc>}
+impl_filter_for!(chrono::DateTime<chrono::Utc>: chrono::DateTime<chrono::Utc>, timestamptz, TimestampTz, FilterEq eq:Equal, ne:NotEqual);
+impl_filter_for!(chrono::DateTime<chrono::Utc>: chrono::DateTime<chrono::Utc>, timestamptz, TimestampTz, FilterPartialEq lt:LessThan, lte:LessThanOrEqual, gt:GreaterThan, gte:GreaterThanOrEqual);
+typetype! {std::time::Duration}
+impl_filter_for!(std::time::Duration: std::time::Duration, interval, Interval, FilterEq eq:Equal, ne:NotEqual);
+impl_filter_for!(std::time::Duration: std::time::Duration, interval, Interval, FilterPartialEq lt:LessThan, lte:LessThanOrEqual, gt:GreaterThan, gte:GreaterThanOrEqual);
+// `Vec<u8>` is stored as BYTEA, rather than as an array.
+typetype! {u8}
+impl_filter_for!(Vec<u8>: Vec<u8>, bytea, Bytea, FilterEq eq:Equal, ne:NotEqual);
+
+// Arrays of the types above, e.g. `Vec<String>` for a `VARCHAR[]` column.
+impl<T: TypeFilter> FilterArray for FilterableType<Vec<T>>
+where
+    ArrayParam: From<Vec<T>>,
+{
+    type Type = Vec<T>;
+
+    fn contains(
+        &self,
+        values: impl Into<<Self as FilterArray>::Type>,
+    ) -> Filter {
+        self.scoped(Filter::Contains(
+            FilterComparisonParam::TableColumn(self.column_name.clone()),
+            FilterComparisonParam::Array(values.into().into()),
+        ))
+    }
+
+    fn overlaps(
+        &self,
+        values: impl Into<<Self as FilterArray>::Type>,
+    ) -> Filter {
+        self.scoped(Filter::Overlaps(
+            FilterComparisonParam::TableColumn(self.column_name.clone()),
+            FilterComparisonParam::Array(values.into().into()),
+        ))
+    }
+}
 
 // impl<T> TypeFilter for Vec<T> where T: TypeFilter {}
 // impl<T> TypeFilter for Option<T> where T: TypeFilter {}
 
+// Enums are compared by variant, cast to their Postgres type.
+impl<T: DbEnum> TypeFilter for T {}
+impl<T: DbEnum> FilterEq for FilterableType<T> {
+    type Type = T;
+
+    fn eq(
+        &self,
+        value: impl Into<<Self as FilterEq>::Type>,
+    ) -> Filter {
+        self.scoped(Filter::Equal(
+            FilterComparisonParam::TableColumn(self.column_name.clone()),
+            FilterComparisonParam::from_enum(&value.into()),
+        ))
+    }
+
+    fn ne(
+        &self,
+        value: impl Into<<Self as FilterEq>::Type>,
+    ) -> Filter {
+        self.scoped(Filter::NotEqual(
+            FilterComparisonParam::TableColumn(self.column_name.clone()),
+            FilterComparisonParam::from_enum(&value.into()),
+        ))
+    }
+}
+impl<T: DbEnum> FilterIn for FilterableType<T> {
+    type Type = Vec<T>;
+
+    fn contained_in(
+        &self,
+        values: impl Into<<Self as FilterIn>::Type>,
+    ) -> Filter {
+        self.scoped(Filter::In(
+            FilterComparisonParam::TableColumn(self.column_name.clone()),
+            values.into().iter().map(FilterComparisonParam::from_enum).collect(),
+        ))
+    }
+}
+
 #[allow(private_bounds)]
 pub struct FilterableType<T: TypeFilter> {
     // _table_name: Identifier,
     column_name: Identifier,
+    scope: Option<Arc<RelationScope>>,
     _t: PhantomData<T>,
 }
 
@@ -119,9 +305,296 @@ impl<T: TypeFilter> FilterableType<T> {
         Self {
             // _table_name,
             column_name,
+            scope: None,
+            _t: PhantomData,
+        }
+    }
+
+    /// Places this column inside a relationship - any filters built from it will be wrapped in an `EXISTS` subquery.
+    pub fn in_scope(
+        mut self,
+        scope: Option<Arc<RelationScope>>,
+    ) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    fn scoped(
+        &self,
+        filter: Filter,
+    ) -> Filter {
+        match &self.scope {
+            Some(scope) => scope.wrap(filter),
+            None => filter,
+        }
+    }
+
+    /// Sorts by this column, in ascending order.
+    pub fn asc(&self) -> OrderBy {
+        OrderBy::new(self.column_name.clone(), OrderDirection::Ascending)
+    }
+
+    /// Sorts by this column, in descending order.
+    pub fn desc(&self) -> OrderBy {
+        OrderBy::new(self.column_name.clone(), OrderDirection::Desending)
+    }
+
+    /// Groups by this column, in an aggregate query.
+    pub fn group(&self) -> GroupKey {
+        GroupKey::new(self.column_name.clone())
+    }
+
+    pub fn count(&self) -> Aggregate {
+        Aggregate::new(AggregateFunction::Count, self.column_name.clone())
+    }
+
+    pub fn min(&self) -> Aggregate {
+        Aggregate::new(AggregateFunction::Min, self.column_name.clone())
+    }
+
+    pub fn max(&self) -> Aggregate {
+        Aggregate::new(AggregateFunction::Max, self.column_name.clone())
+    }
+}
+
+impl FilterableType<chrono::NaiveDateTime> {
+    /// Groups by this timestamp, truncated to `date_part` (e.g. one group per day).
+    pub fn truncate(
+        &self,
+        date_part: DatePart,
+    ) -> GroupKey {
+        self.group().truncate(date_part)
+    }
+}
+
+/// A relationship field on the generated `*Filters` structs. Used to choose which relations a query
+/// loads, e.g. `.include(|f| f.line_items)`.
+pub trait Relation {
+    /// The table the related objects are stored in.
+    fn table_name(&self) -> &Identifier;
+}
+
+/// Filters for a one-to-one child. Derefs to the child's `*Filters`, so that
+/// `|f| f.address.city.eq("Oslo")` works as expected.
+pub struct FilterableOneToOne<T>
+where
+    T: Filterable,
+    T::FilterType: RelationFilters,
+{
+    scope: Arc<RelationScope>,
+    filters: T::FilterType,
+}
+
+impl<T> FilterableOneToOne<T>
+where
+    T: Filterable,
+    T::FilterType: RelationFilters,
+{
+    /// # Arguments
+    ///
+    /// * `child_table` - The table the related objects are stored in.
+    /// * `child_key` - The fully qualified key column on the child table (e.g. `address.id`)
+    /// * `foreign_key` - The fully qualified column on the parent table referencing `child_key` (e.g. `customer.address_id`)
+    /// * `parent_scope` - The scope of the parent table, if the parent is itself a relationship.
+    pub fn new(
+        child_table: Identifier,
+        child_key: Identifier,
+        foreign_key: Identifier,
+        parent_scope: Option<Arc<RelationScope>>,
+    ) -> Self {
+        let scope =
+            Arc::new(RelationScope::new(child_table, vec![(child_key, foreign_key)], parent_scope));
+        Self {
+            filters: T::FilterType::in_scope(scope.clone()),
+            scope,
+        }
+    }
+
+    /// Matches when the child object exists and satisfies `predicate`.
+    pub fn matches(
+        &self,
+        predicate: impl Fn(T::FilterType) -> Filter,
+    ) -> Filter {
+        self.scope.wrap(predicate(T::FilterType::default()))
+    }
+}
+
+impl<T> Deref for FilterableOneToOne<T>
+where
+    T: Filterable,
+    T::FilterType: RelationFilters,
+{
+    type Target = T::FilterType;
+
+    fn deref(&self) -> &Self::Target {
+        &self.filters
+    }
+}
+
+impl<T> Relation for FilterableOneToOne<T>
+where
+    T: Filterable,
+    T::FilterType: RelationFilters,
+{
+    fn table_name(&self) -> &Identifier {
+        &self.scope.table_name
+    }
+}
+
+/// Filters for a collection of one-to-many children.
+pub struct FilterableOneToMany<T>
+where
+    T: Filterable,
+{
+    scope: Arc<RelationScope>,
+    _t: PhantomData<T>,
+}
+
+impl<T> FilterableOneToMany<T>
+where
+    T: Filterable,
+{
+    /// # Arguments
+    ///
+    /// * `child_table` - The table the related objects are stored in.
+    /// * `parent_reference` - The fully qualified column on the child table referencing the parent (e.g. `line_item.parent_id`)
+    /// * `parent_key` - The fully qualified key column of the parent table (e.g. `order.id`)
+    /// * `parent_scope` - The scope of the parent table, if the parent is itself a relationship.
+    pub fn new(
+        child_table: Identifier,
+        parent_reference: Identifier,
+        parent_key: Identifier,
+        parent_scope: Option<Arc<RelationScope>>,
+    ) -> Self {
+        Self {
+            scope: Arc::new(RelationScope::new(
+                child_table,
+                vec![(parent_reference, parent_key)],
+                parent_scope,
+            )),
+            _t: PhantomData,
+        }
+    }
+
+    /// Matches when at least one child satisfies `predicate`.
+    pub fn any(
+        &self,
+        predicate: impl Fn(T::FilterType) -> Filter,
+    ) -> Filter {
+        self.scope.wrap(predicate(T::FilterType::default()))
+    }
+
+    /// Matches when no children satisfy `predicate`.
+    pub fn none(
+        &self,
+        predicate: impl Fn(T::FilterType) -> Filter,
+    ) -> Filter {
+        self.scope.wrap_subquery(predicate(T::FilterType::default()), true)
+    }
+}
+
+impl<T> Relation for FilterableOneToMany<T>
+where
+    T: Filterable,
+{
+    fn table_name(&self) -> &Identifier {
+        &self.scope.table_name
+    }
+}
+
+/// Filters for a collection of many-to-many children, linked through a join table (see
+/// `DatabaseTableDefinition::join_table()`).
+pub struct FilterableManyToMany<T>
+where
+    T: Filterable,
+{
+    links: Arc<RelationScope>,
+    child_table: Identifier,
+    /// `(key column on the child table, column on the join table referencing it)`
+    child_correlation: (Identifier, Identifier),
+    _t: PhantomData<T>,
+}
+
+impl<T> FilterableManyToMany<T>
+where
+    T: Filterable,
+{
+    /// # Arguments
+    ///
+    /// * `child_table` - The table the related objects are stored in.
+    /// * `join_table` - The table linking parents to children (e.g. `article_tags_to_tag`)
+    /// * `parent_reference` - The fully qualified column on the join table referencing the parent (e.g. `article_tags_to_tag.parent_id`)
+    /// * `child_reference` - The fully qualified column on the join table referencing the child (e.g. `article_tags_to_tag.child_id`)
+    /// * `parent_key` - The fully qualified key column of the parent table (e.g. `article.id`)
+    /// * `parent_scope` - The scope of the parent table, if the parent is itself a relationship.
+    pub fn new(
+        child_table: Identifier,
+        join_table: Identifier,
+        parent_reference: Identifier,
+        child_reference: Identifier,
+        parent_key: Identifier,
+        parent_scope: Option<Arc<RelationScope>>,
+    ) -> Self {
+        Self {
+            links: Arc::new(RelationScope::new(
+                join_table,
+                vec![(parent_reference, parent_key)],
+                parent_scope,
+            )),
+            child_correlation: (
+                Identifier::new_unchecked(format!("{child_table}.{}", T::KEY_COLUMN)),
+                child_reference,
+            ),
+            child_table,
             _t: PhantomData,
         }
     }
+
+    fn linked_child(
+        &self,
+        predicate: impl Fn(T::FilterType) -> Filter,
+    ) -> Filter {
+        let predicate = predicate(T::FilterType::default());
+        let alias = subquery_alias(&self.child_table, &predicate);
+        let (child_key, child_reference) = self.child_correlation.clone();
+        Filter::Exists(
+            self.child_table.clone(),
+            alias.clone(),
+            Box::new(Filter::And(vec![
+                Filter::Equal(
+                    FilterComparisonParam::TableColumn(child_key),
+                    FilterComparisonParam::TableColumn(child_reference),
+                )
+                .alias_left(&self.child_table, &alias),
+                predicate.alias_table(&self.child_table, &alias),
+            ])),
+        )
+    }
+
+    /// Matches when at least one linked child satisfies `predicate`.
+    pub fn any(
+        &self,
+        predicate: impl Fn(T::FilterType) -> Filter,
+    ) -> Filter {
+        self.links.wrap(self.linked_child(predicate))
+    }
+
+    /// Matches when no linked children satisfy `predicate`.
+    pub fn none(
+        &self,
+        predicate: impl Fn(T::FilterType) -> Filter,
+    ) -> Filter {
+        self.links.wrap_subquery(self.linked_child(predicate), true)
+    }
+}
+
+impl<T> Relation for FilterableManyToMany<T>
+where
+    T: Filterable,
+{
+    fn table_name(&self) -> &Identifier {
+        &self.child_table
+    }
 }
 
 pub trait FilterEq {
@@ -171,7 +644,254 @@ pub trait FilterIn {
         t: impl Into<<Self as crate::queries::filters::filterable_types::FilterIn>::Type>,
     ) -> Filter;
 }
+pub trait FilterArray {
+    type Type;
+    /// `column @> values` - the array holds every one of `values`.
+    fn contains(
+        &self,
+        t: impl Into<<Self as crate::queries::filters::filterable_types::FilterArray>::Type>,
+    ) -> Filter;
+    /// `column && values` - the array holds at least one of `values`.
+    fn overlaps(
+        &self,
+        t: impl Into<<Self as crate::queries::filters::filterable_types::FilterArray>::Type>,
+    ) -> Filter;
+}
 
 // #[cfg(features = "experimental")]
 // FilterableTypes for OneToOne / OneToMany
 // pub trait Filter
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{data_definition::table::Identifier, BuildSql};
+
+    use super::*;
+
+    // Hand-written equivalents of what `#[derive(Filterable)]` generates.
+    struct Address;
+    struct AddressFilters {
+        city: FilterableType<String>,
+    }
+    impl AddressFilters {
+        fn build(scope: Option<Arc<RelationScope>>) -> Self {
+            Self {
+                city: FilterableType::new(Identifier::new_unchecked("address.city"))
+                    .in_scope(scope),
+            }
+        }
+    }
+    impl Default for AddressFilters {
+        fn default() -> Self {
+            Self::build(None)
+        }
+    }
+    impl RelationFilters for AddressFilters {
+        fn in_scope(scope: Arc<RelationScope>) -> Self {
+            Self::build(Some(scope))
+        }
+    }
+    impl Filterable for Address {
+        type FilterType = AddressFilters;
+    }
+
+    struct LineItem;
+    struct LineItemFilters {
+        price: FilterableType<i64>,
+    }
+    impl Default for LineItemFilters {
+        fn default() -> Self {
+            Self {
+                price: FilterableType::new(Identifier::new_unchecked("line_item.price")),
+            }
+        }
+    }
+    impl Filterable for LineItem {
+        type FilterType = LineItemFilters;
+    }
+
+    struct Tag;
+    struct TagFilters {
+        name: FilterableType<String>,
+    }
+    impl Default for TagFilters {
+        fn default() -> Self {
+            Self {
+                name: FilterableType::new(Identifier::new_unchecked("tag.name")),
+            }
+        }
+    }
+    impl Filterable for Tag {
+        type FilterType = TagFilters;
+    }
+
+    struct OrderFilters {
+        address: FilterableOneToOne<Address>,
+        line_items: FilterableOneToMany<LineItem>,
+        tags: FilterableManyToMany<Tag>,
+    }
+    impl Default for OrderFilters {
+        fn default() -> Self {
+            Self {
+                address: FilterableOneToOne::new(
+                    Identifier::new_unchecked("address"),
+                    Identifier::new_unchecked("address.id"),
+                    Identifier::new_unchecked("order.address_id"),
+                    None,
+                ),
+                line_items: FilterableOneToMany::new(
+                    Identifier::new_unchecked("line_item"),
+                    Identifier::new_unchecked("line_item.parent_id"),
+                    Identifier::new_unchecked("order.id"),
+                    None,
+                ),
+                tags: FilterableManyToMany::new(
+                    Identifier::new_unchecked("tag"),
+                    Identifier::new_unchecked("order_tags_to_tag"),
+                    Identifier::new_unchecked("order_tags_to_tag.parent_id"),
+                    Identifier::new_unchecked("order_tags_to_tag.child_id"),
+                    Identifier::new_unchecked("order.id"),
+                    None,
+                ),
+            }
+        }
+    }
+
+    fn to_sql(filter: Filter) -> String {
+        let mut builder = sqlx::QueryBuilder::new("");
+        filter.build_sql(&mut builder);
+        builder.into_sql()
+    }
+
+    #[test]
+    fn relation_filters_render_as_exists_subqueries() {
+        let f = OrderFilters::default();
+
+        assert_eq!(
+            to_sql(f.address.city.eq("Oslo")),
+            "EXISTS (SELECT 1 FROM address AS address_1 WHERE (address_1.id = order.address_id AND address_1.city = $1))"
+        );
+        assert_eq!(
+            to_sql(f.line_items.any(|li| li.price.gt(100))),
+            "EXISTS (SELECT 1 FROM line_item AS line_item_1 WHERE (line_item_1.parent_id = order.id AND line_item_1.price > $1))"
+        );
+        assert_eq!(
+            to_sql(f.line_items.none(|li| li.price.gt(100)) | f.address.city.eq("Oslo")),
+            "(NOT EXISTS (SELECT 1 FROM line_item AS line_item_1 WHERE (line_item_1.parent_id = order.id AND line_item_1.price > $1)) OR EXISTS (SELECT 1 FROM address AS address_1 WHERE (address_1.id = order.address_id AND address_1.city = $2)))"
+        );
+    }
+
+    struct Category;
+    struct CategoryFilters {
+        name: FilterableType<String>,
+        children: FilterableOneToMany<Category>,
+    }
+    impl Default for CategoryFilters {
+        fn default() -> Self {
+            Self {
+                name: FilterableType::new(Identifier::new_unchecked("category.name")),
+                children: FilterableOneToMany::new(
+                    Identifier::new_unchecked("category"),
+                    Identifier::new_unchecked("category.parent_id"),
+                    Identifier::new_unchecked("category.id"),
+                    None,
+                ),
+            }
+        }
+    }
+    impl Filterable for Category {
+        type FilterType = CategoryFilters;
+    }
+
+    #[test]
+    fn self_referential_relations_correlate_against_the_parent_row() {
+        let f = CategoryFilters::default();
+
+        assert_eq!(
+            to_sql(f.children.any(|child| child.name.eq("Shoes"))),
+            "EXISTS (SELECT 1 FROM category AS category_1 WHERE (category_1.parent_id = category.id AND category_1.name = $1))"
+        );
+        assert_eq!(
+            to_sql(f.children.any(|child| child.children.any(|grandchild| grandchild.name.eq("Boots")))),
+            "EXISTS (SELECT 1 FROM category AS category_2 WHERE (category_2.parent_id = category.id AND \
+             EXISTS (SELECT 1 FROM category AS category_1 WHERE (category_1.parent_id = category_2.id AND category_1.name = $1))))"
+        );
+    }
+
+    #[test]
+    fn many_to_many_filters_go_through_the_join_table() {
+        let f = OrderFilters::default();
+
+        assert_eq!(
+            to_sql(f.tags.any(|tag| tag.name.eq("rust"))),
+            "EXISTS (SELECT 1 FROM order_tags_to_tag AS order_tags_to_tag_2 WHERE (order_tags_to_tag_2.parent_id = order.id AND \
+             EXISTS (SELECT 1 FROM tag AS tag_1 WHERE (tag_1.id = order_tags_to_tag_2.child_id AND tag_1.name = $1))))"
+        );
+        assert!(to_sql(f.tags.none(|tag| tag.name.eq("rust"))).starts_with(
+            "NOT EXISTS (SELECT 1 FROM order_tags_to_tag AS order_tags_to_tag_2 WHERE (order_tags_to_tag_2.parent_id = order.id AND EXISTS"
+        ));
+    }
+    // Hand-written equivalent of what `#[derive(DbEnum)]` generates.
+    #[derive(Clone, Copy)]
+    enum Status {
+        Open,
+        Closed,
+    }
+    impl DbEnum for Status {
+        fn enum_type() -> crate::data_definition::table::EnumType {
+            crate::data_definition::table::EnumType::new(
+                Identifier::new_unchecked("status"),
+                vec!["Open".to_string(), "Closed".to_string()],
+            )
+        }
+
+        fn as_variant(&self) -> &'static str {
+            match self {
+                Status::Open => "Open",
+                Status::Closed => "Closed",
+            }
+        }
+
+        fn from_variant(variant: &str) -> Option<Self> {
+            match variant {
+                "Open" => Some(Status::Open),
+                "Closed" => Some(Status::Closed),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn enum_filters_cast_to_the_enum_type() {
+        let status = FilterableType::<Status>::new(Identifier::new_unchecked("ticket.status"));
+
+        assert_eq!(to_sql(status.eq(Status::Open)), "ticket.status = $1::status");
+        assert_eq!(
+            to_sql(status.contained_in(vec![Status::Open, Status::Closed])),
+            "ticket.status IN ($1::status, $2::status)"
+        );
+        assert_eq!(status.ne(Status::Closed).to_inline_sql(), "ticket.status != 'Closed'::status");
+    }
+
+    #[test]
+    fn array_filters_cast_to_the_column_type() {
+        let tags = FilterableType::<Vec<String>>::new(Identifier::new_unchecked("post.tags"));
+        let scores = FilterableType::<Vec<i64>>::new(Identifier::new_unchecked("post.scores"));
+        let price =
+            FilterableType::<rust_decimal::Decimal>::new(Identifier::new_unchecked("post.price"));
+
+        assert_eq!(
+            to_sql(tags.contains(vec!["rust".to_string()]) & scores.overlaps(vec![1, 2])),
+            "(post.tags @> $1::VARCHAR[] AND post.scores && $2::BIGINT[])"
+        );
+        // Literals for DDL, e.g. `CHECK` constraints. Decimals are written exactly.
+        assert_eq!(
+            (scores.contains(vec![])
+                | price.gte("10.10".parse::<rust_decimal::Decimal>().unwrap()))
+            .to_inline_sql(),
+            "(post.scores @> ARRAY[]::BIGINT[] OR post.price >= '10.10'::numeric)"
+        );
+    }
+}
@@ -1,9 +1,20 @@
-use crate::{data_definition::table::Identifier, BuildSql};
+use crate::{
+    data_definition::table::{DatabaseColumnType, DbEnum, Identifier},
+    queries::{
+        cache::{CacheableSql, InlineSql, ShapeHasher},
+        Aggregate, RawSql, SqlSink,
+    },
+    BuildSql,
+};
+use serde::{Deserialize, Serialize};
 use sqlx::{Postgres, QueryBuilder};
-use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
+use std::{
+    hash::Hash,
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign},
+};
 use uuid::Uuid;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 // TODO: This is duplicated iwth DB types somewhere else
 pub enum FilterComparisonParam {
     TableColumn(Identifier),
@@ -13,28 +24,252 @@ pub enum FilterComparisonParam {
     Float(f64),
     Bool(bool),
     Timestamp(chrono::NaiveDateTime),
+    Numeric(rust_decimal::Decimal),
+    Date(chrono::NaiveDate),
+    Time(chrono::NaiveTime),
+    TimestampTz(chrono::DateTime<chrono::Utc>),
+    Bytea(Vec<u8>),
+    Interval(std::time::Duration),
+    Array(ArrayParam),
+    /// A `DbEnum` variant, and the Postgres type it's cast to.
+    Enum(Identifier, String),
+    Aggregate(Box<Aggregate>),
     Null,
 }
 
 impl FilterComparisonParam {
+    /// See `Filter::alias_table()`.
+    fn alias_table(
+        self,
+        table_name: &Identifier,
+        alias: &Identifier,
+    ) -> Self {
+        match self {
+            FilterComparisonParam::TableColumn(column) => {
+                match column.strip_prefix(table_name.as_str()).and_then(|c| c.strip_prefix('.')) {
+                    Some(column_name) => FilterComparisonParam::TableColumn(
+                        Identifier::new_unchecked(format!("{alias}.{column_name}")),
+                    ),
+                    None => FilterComparisonParam::TableColumn(column),
+                }
+            },
+            param => param,
+        }
+    }
+
     pub fn build_sql(
         &self,
         builder: &mut QueryBuilder<Postgres>,
+    ) {
+        self.write_sql(builder);
+    }
+
+    pub(crate) fn write_sql(
+        &self,
+        sink: &mut impl SqlSink,
     ) {
         match self {
-            FilterComparisonParam::TableColumn(col) => builder.push(col),
-            FilterComparisonParam::String(val) => builder.push_bind(val.to_string()),
-            FilterComparisonParam::Uuid(val) => builder.push_bind(*val),
-            FilterComparisonParam::Integer(val) => builder.push_bind(*val),
-            FilterComparisonParam::Float(val) => builder.push_bind(*val),
-            FilterComparisonParam::Bool(val) => builder.push_bind(*val),
-            FilterComparisonParam::Timestamp(val) => builder.push_bind(*val),
-            FilterComparisonParam::Null => builder.push("null"),
+            FilterComparisonParam::TableColumn(col) => {
+                sink.push(col);
+            },
+            FilterComparisonParam::Aggregate(aggregate) => aggregate.write_sql(sink),
+            FilterComparisonParam::Null => {
+                sink.push("null");
+            },
+            value => {
+                sink.push_bind(value.clone());
+            },
         };
     }
+
+    /// See `CacheableSql::collect_binds()`.
+    pub(crate) fn collect_binds(
+        &self,
+        binds: &mut Vec<FilterComparisonParam>,
+    ) {
+        match self {
+            FilterComparisonParam::TableColumn(_)
+            | FilterComparisonParam::Aggregate(_)
+            | FilterComparisonParam::Null => {},
+            value => binds.push(value.clone()),
+        }
+    }
+
+    /// Hashes the column / aggregate, or the *type* of a bound value. See `CacheableSql::hash_shape()`.
+    pub(crate) fn hash_shape(
+        &self,
+        state: &mut ShapeHasher,
+    ) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            FilterComparisonParam::TableColumn(col) => col.hash(state),
+            FilterComparisonParam::Aggregate(aggregate) => aggregate.hash(state),
+            // The cast is part of the SQL.
+            FilterComparisonParam::Enum(type_name, _) => type_name.hash(state),
+            FilterComparisonParam::Array(array) => std::mem::discriminant(array).hash(state),
+            _ => {},
+        }
+    }
+}
+
+macro_rules! impl_from_for_param {
+    ($type:ty: $variant:ident) => {
+        impl From<$type> for FilterComparisonParam {
+            fn from(value: $type) -> Self {
+                FilterComparisonParam::$variant(value.into())
+            }
+        }
+    };
 }
+impl_from_for_param!(String: String);
+impl_from_for_param!(&str: String);
+impl_from_for_param!(Uuid: Uuid);
+impl_from_for_param!(i64: Integer);
+impl_from_for_param!(i32: Integer);
+impl_from_for_param!(f64: Float);
+impl_from_for_param!(bool: Bool);
+impl_from_for_param!(chrono::NaiveDateTime: Timestamp);
+impl_from_for_param!(rust_decimal::Decimal: Numeric);
+impl_from_for_param!(chrono::NaiveDate: Date);
+impl_from_for_param!(chrono::NaiveTime: Time);
+impl_from_for_param!(chrono::DateTime<chrono::Utc>: TimestampTz);
+impl_from_for_param!(Vec<u8>: Bytea);
+impl_from_for_param!(std::time::Duration: Interval);
 
-#[derive(Clone)]
+/// The values of an array column. Kept typed, so that an empty array still binds as the right type.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ArrayParam {
+    Bool(Vec<bool>),
+    /// An `INT[]`.
+    Int(Vec<i32>),
+    /// A `BIGINT[]`.
+    Integer(Vec<i64>),
+    Float(Vec<f64>),
+    String(Vec<String>),
+    Uuid(Vec<Uuid>),
+    Timestamp(Vec<chrono::NaiveDateTime>),
+    Numeric(Vec<rust_decimal::Decimal>),
+    Date(Vec<chrono::NaiveDate>),
+    Time(Vec<chrono::NaiveTime>),
+    TimestampTz(Vec<chrono::DateTime<chrono::Utc>>),
+}
+
+impl ArrayParam {
+    /// The Postgres type of the array.
+    pub(crate) fn sql_type(&self) -> &'static str {
+        match self {
+            ArrayParam::Bool(_) => "BOOL[]",
+            ArrayParam::Int(_) => "INT[]",
+            ArrayParam::Integer(_) => "BIGINT[]",
+            ArrayParam::Float(_) => "FLOAT[]",
+            ArrayParam::String(_) => "VARCHAR[]",
+            ArrayParam::Uuid(_) => "UUID[]",
+            ArrayParam::Timestamp(_) => "TIMESTAMP[]",
+            ArrayParam::Numeric(_) => "NUMERIC[]",
+            ArrayParam::Date(_) => "DATE[]",
+            ArrayParam::Time(_) => "TIME[]",
+            ArrayParam::TimestampTz(_) => "TIMESTAMPTZ[]",
+        }
+    }
+
+    /// The elements, as (scalar) params.
+    pub(crate) fn elements(&self) -> Vec<FilterComparisonParam> {
+        fn params<T: Clone + Into<FilterComparisonParam>>(
+            values: &[T]
+        ) -> Vec<FilterComparisonParam> {
+            values.iter().cloned().map(Into::into).collect()
+        }
+        match self {
+            ArrayParam::Bool(values) => params(values),
+            ArrayParam::Int(values) => params(values),
+            ArrayParam::Integer(values) => params(values),
+            ArrayParam::Float(values) => params(values),
+            ArrayParam::String(values) => params(values),
+            ArrayParam::Uuid(values) => params(values),
+            ArrayParam::Timestamp(values) => params(values),
+            ArrayParam::Numeric(values) => params(values),
+            ArrayParam::Date(values) => params(values),
+            ArrayParam::Time(values) => params(values),
+            ArrayParam::TimestampTz(values) => params(values),
+        }
+    }
+}
+
+impl ArrayParam {
+    /// Builds an array of `element_type` from scalar params, or `None` if any of them is a different type.
+    pub(crate) fn from_elements(
+        element_type: &DatabaseColumnType,
+        elements: Vec<FilterComparisonParam>,
+    ) -> Option<Self> {
+        macro_rules! collect {
+            ($variant:ident, $param:ident) => {
+                elements
+                    .into_iter()
+                    .map(|element| match element {
+                        FilterComparisonParam::$param(value) => Some(value),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .map(ArrayParam::$variant)
+            };
+        }
+        type E = DatabaseColumnType;
+        match element_type {
+            E::Boolean => collect!(Bool, Bool),
+            // Values that don't fit in an `INT` are a type mismatch, like any other.
+            E::Int => elements
+                .into_iter()
+                .map(|element| match element {
+                    FilterComparisonParam::Integer(value) => i32::try_from(value).ok(),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(ArrayParam::Int),
+            E::BigInt => collect!(Integer, Integer),
+            E::Float => collect!(Float, Float),
+            E::String => collect!(String, String),
+            E::Uuid => collect!(Uuid, Uuid),
+            E::Timestamp => collect!(Timestamp, Timestamp),
+            E::Numeric => collect!(Numeric, Numeric),
+            E::Date => collect!(Date, Date),
+            E::Time => collect!(Time, Time),
+            E::TimestampTz => collect!(TimestampTz, TimestampTz),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_from_for_array {
+    ($type:ty: $variant:ident) => {
+        impl From<Vec<$type>> for ArrayParam {
+            fn from(values: Vec<$type>) -> Self {
+                ArrayParam::$variant(values.into_iter().map(Into::into).collect())
+            }
+        }
+    };
+}
+impl_from_for_array!(bool: Bool);
+impl_from_for_array!(i64: Integer);
+impl_from_for_array!(i32: Int);
+impl_from_for_array!(u32: Integer);
+impl_from_for_array!(f64: Float);
+impl_from_for_array!(f32: Float);
+impl_from_for_array!(String: String);
+impl_from_for_array!(&str: String);
+impl_from_for_array!(Uuid: Uuid);
+impl_from_for_array!(chrono::NaiveDateTime: Timestamp);
+impl_from_for_array!(rust_decimal::Decimal: Numeric);
+impl_from_for_array!(chrono::NaiveDate: Date);
+impl_from_for_array!(chrono::NaiveTime: Time);
+impl_from_for_array!(chrono::DateTime<chrono::Utc>: TimestampTz);
+
+impl FilterComparisonParam {
+    pub fn from_enum<T: DbEnum>(value: &T) -> Self {
+        FilterComparisonParam::Enum(T::enum_type().name, value.as_variant().to_string())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 // TODO: Make Filters associated with their tables
 // There's a lot more to do with the filters here - nailing this down is gonna be super powerful
 // Will work with PG stuff, and also let me piecemeal replace the functionality...
@@ -49,6 +284,13 @@ pub enum Filter {
     GreaterThan(FilterComparisonParam, FilterComparisonParam),     // Non-String types
     GreaterThanOrEqual(FilterComparisonParam, FilterComparisonParam), // Non-String types
     In(FilterComparisonParam, Vec<FilterComparisonParam>),         // All types
+    Contains(FilterComparisonParam, FilterComparisonParam),        // Arrays only - `@>`
+    Overlaps(FilterComparisonParam, FilterComparisonParam),        // Arrays only - `&&`
+    Exists(Identifier, Identifier, Box<Filter>), // Correlated subquery against a related table, and its alias
+    NotExists(Identifier, Identifier, Box<Filter>), // Correlated subquery against a related table, and its alias
+    // Raw SQL can't be trusted from another process, so it is never (de)serialized.
+    #[serde(skip)]
+    Raw(RawSql), // Hand-written SQL, only constructed through `Filter::raw()` / `RawSql::new()` so its placeholders are checked.
 }
 
 impl Filter {
@@ -64,6 +306,104 @@ impl Filter {
             Filter::GreaterThan(_, _) => ">",
             Filter::GreaterThanOrEqual(_, _) => ">=",
             Filter::In(_, _) => "IN",
+            Filter::Contains(_, _) => "@>",
+            Filter::Overlaps(_, _) => "&&",
+            Filter::Exists(..) => "EXISTS",
+            Filter::NotExists(..) => "NOT EXISTS",
+            Filter::Raw(_) => "",
+        }
+    }
+
+    /// Embeds a hand-written SQL expression in a filter, e.g. for window functions.
+    /// Placeholders are numbered from `$1` within `sql`, regardless of what else is in the query.
+    ///
+    /// Returns an error if `sql` references a placeholder with no matching bind.
+    pub fn raw(
+        sql: impl Into<String>,
+        binds: Vec<FilterComparisonParam>,
+    ) -> Result<Self, String> {
+        Ok(Filter::Raw(RawSql::new(sql, binds)?))
+    }
+
+    /// Maps the tables of any `EXISTS` subqueries, e.g. to qualify them with their schema.
+    pub(crate) fn qualify_tables(
+        self,
+        qualify: &impl Fn(&Identifier) -> Identifier,
+    ) -> Self {
+        let qualify_all =
+            |filters: Vec<Filter>| filters.into_iter().map(|f| f.qualify_tables(qualify)).collect();
+        match self {
+            Filter::And(filters) => Filter::And(qualify_all(filters)),
+            Filter::Or(filters) => Filter::Or(qualify_all(filters)),
+            Filter::Exists(table_name, alias, filter) => Filter::Exists(
+                qualify(&table_name),
+                alias,
+                Box::new(filter.qualify_tables(qualify)),
+            ),
+            Filter::NotExists(table_name, alias, filter) => Filter::NotExists(
+                qualify(&table_name),
+                alias,
+                Box::new(filter.qualify_tables(qualify)),
+            ),
+            filter => filter,
+        }
+    }
+
+    /// How deeply `EXISTS` subqueries are nested within the filter.
+    pub(crate) fn subquery_depth(&self) -> usize {
+        match self {
+            Filter::And(filters) | Filter::Or(filters) => {
+                filters.iter().map(Filter::subquery_depth).max().unwrap_or_default()
+            },
+            Filter::Exists(_, _, filter) | Filter::NotExists(_, _, filter) => {
+                1 + filter.subquery_depth()
+            },
+            _ => 0,
+        }
+    }
+
+    /// Like `alias_table()`, but only for the left-hand side of a comparison - for correlations, where both sides
+    /// can be on the same table (e.g. `category.parent_id = category.id`).
+    pub(crate) fn alias_left(
+        self,
+        table_name: &Identifier,
+        alias: &Identifier,
+    ) -> Self {
+        match self {
+            Filter::Equal(l, r) => Filter::Equal(l.alias_table(table_name, alias), r),
+            filter => filter,
+        }
+    }
+
+    /// Points the columns of `table_name` at `alias` instead, e.g. `category.name` -> `category_1.name`.
+    pub(crate) fn alias_table(
+        self,
+        table_name: &Identifier,
+        alias: &Identifier,
+    ) -> Self {
+        let param = |param: FilterComparisonParam| param.alias_table(table_name, alias);
+        let filter = |filter: Filter| filter.alias_table(table_name, alias);
+        match self {
+            Filter::And(filters) => Filter::And(filters.into_iter().map(filter).collect()),
+            Filter::Or(filters) => Filter::Or(filters.into_iter().map(filter).collect()),
+            Filter::Equal(l, r) => Filter::Equal(param(l), param(r)),
+            Filter::NotEqual(l, r) => Filter::NotEqual(param(l), param(r)),
+            Filter::Like(l, r) => Filter::Like(param(l), param(r)),
+            Filter::LessThan(l, r) => Filter::LessThan(param(l), param(r)),
+            Filter::LessThanOrEqual(l, r) => Filter::LessThanOrEqual(param(l), param(r)),
+            Filter::GreaterThan(l, r) => Filter::GreaterThan(param(l), param(r)),
+            Filter::GreaterThanOrEqual(l, r) => Filter::GreaterThanOrEqual(param(l), param(r)),
+            Filter::In(val, list) => Filter::In(param(val), list.into_iter().map(param).collect()),
+            Filter::Contains(l, r) => Filter::Contains(param(l), param(r)),
+            Filter::Overlaps(l, r) => Filter::Overlaps(param(l), param(r)),
+            // Nested subqueries can still reference this table, e.g. in their correlation.
+            Filter::Exists(table, inner_alias, inner) => {
+                Filter::Exists(table, inner_alias, Box::new(filter(*inner)))
+            },
+            Filter::NotExists(table, inner_alias, inner) => {
+                Filter::NotExists(table, inner_alias, Box::new(filter(*inner)))
+            },
+            Filter::Raw(raw) => Filter::Raw(raw),
         }
     }
 }
@@ -135,16 +475,37 @@ impl BuildSql for Filter {
     fn build_sql(
         &self,
         builder: &mut QueryBuilder<Postgres>,
+    ) {
+        self.write_sql(builder);
+    }
+}
+
+impl Filter {
+    pub(crate) fn write_sql(
+        &self,
+        builder: &mut impl SqlSink,
     ) {
         match self {
             Filter::And(children) | Filter::Or(children) => {
                 let mut iter = children.iter().peekable();
+                builder.push("(");
                 while let Some(child) = iter.next() {
-                    child.build_sql(builder);
+                    child.write_sql(builder);
                     if iter.peek().is_some() {
-                        builder.push(" AND ");
+                        builder.push(" ");
+                        builder.push(self.get_operator());
+                        builder.push(" ");
                     }
                 }
+                builder.push(")");
+            },
+            Filter::Equal(l, FilterComparisonParam::Null) => {
+                l.write_sql(builder);
+                builder.push(" IS NULL");
+            },
+            Filter::NotEqual(l, FilterComparisonParam::Null) => {
+                l.write_sql(builder);
+                builder.push(" IS NOT NULL");
             },
             Filter::Equal(l, r)
             | Filter::NotEqual(l, r)
@@ -152,16 +513,129 @@ impl BuildSql for Filter {
             | Filter::LessThan(l, r)
             | Filter::LessThanOrEqual(l, r)
             | Filter::GreaterThan(l, r)
-            | Filter::GreaterThanOrEqual(l, r) => {
-                l.build_sql(builder);
+            | Filter::GreaterThanOrEqual(l, r)
+            | Filter::Contains(l, r)
+            | Filter::Overlaps(l, r) => {
+                l.write_sql(builder);
                 builder.push(" ");
                 builder.push(self.get_operator());
                 builder.push(" ");
-                r.build_sql(builder);
+                r.write_sql(builder);
+            },
+            Filter::In(val, list) => {
+                if list.is_empty() {
+                    // `IN ()` is a syntax error - nothing can be in an empty list.
+                    builder.push("FALSE");
+                    return;
+                }
+                val.write_sql(builder);
+                builder.push(" IN (");
+                let mut iter = list.iter().peekable();
+                while let Some(item) = iter.next() {
+                    item.write_sql(builder);
+                    if iter.peek().is_some() {
+                        builder.push(", ");
+                    }
+                }
+                builder.push(")");
+            },
+            Filter::Exists(table_name, alias, filter)
+            | Filter::NotExists(table_name, alias, filter) => {
+                builder.push(self.get_operator());
+                builder.push(" (SELECT 1 FROM ");
+                builder.push(table_name);
+                builder.push(" AS ");
+                builder.push(alias);
+                builder.push(" WHERE ");
+                filter.write_sql(builder);
+                builder.push(")");
+            },
+            Filter::Raw(raw) => {
+                builder.push("(");
+                raw.write_sql(builder);
+                builder.push(")");
+            },
+        }
+    }
+
+    /// Renders the filter with its values written inline as literals, rather than bound. For DDL (e.g.
+    /// `CHECK` constraints), where parameters aren't allowed.
+    pub(crate) fn to_inline_sql(&self) -> String {
+        let mut sink = InlineSql::default();
+        self.write_sql(&mut sink);
+        sink.sql
+    }
+
+    /// See `CacheableSql::collect_binds()`.
+    pub(crate) fn collect_binds(
+        &self,
+        binds: &mut Vec<FilterComparisonParam>,
+    ) {
+        match self {
+            Filter::And(children) | Filter::Or(children) => {
+                children.iter().for_each(|child| child.collect_binds(binds));
+            },
+            Filter::Equal(l, FilterComparisonParam::Null)
+            | Filter::NotEqual(l, FilterComparisonParam::Null) => l.collect_binds(binds),
+            Filter::Equal(l, r)
+            | Filter::NotEqual(l, r)
+            | Filter::Like(l, r)
+            | Filter::LessThan(l, r)
+            | Filter::LessThanOrEqual(l, r)
+            | Filter::GreaterThan(l, r)
+            | Filter::GreaterThanOrEqual(l, r)
+            | Filter::Contains(l, r)
+            | Filter::Overlaps(l, r) => {
+                l.collect_binds(binds);
+                r.collect_binds(binds);
+            },
+            Filter::In(_, list) if list.is_empty() => {},
+            Filter::In(val, list) => {
+                val.collect_binds(binds);
+                list.iter().for_each(|item| item.collect_binds(binds));
+            },
+            Filter::Exists(_, _, filter) | Filter::NotExists(_, _, filter) => {
+                filter.collect_binds(binds)
+            },
+            Filter::Raw(raw) => raw.collect_binds(binds),
+        }
+    }
+
+    /// See `CacheableSql::hash_shape()`.
+    pub(crate) fn hash_shape(
+        &self,
+        state: &mut ShapeHasher,
+    ) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Filter::And(children) | Filter::Or(children) => {
+                children.len().hash(state);
+                children.iter().for_each(|child| child.hash_shape(state));
+            },
+            Filter::Equal(l, r)
+            | Filter::NotEqual(l, r)
+            | Filter::Like(l, r)
+            | Filter::LessThan(l, r)
+            | Filter::LessThanOrEqual(l, r)
+            | Filter::GreaterThan(l, r)
+            | Filter::GreaterThanOrEqual(l, r)
+            | Filter::Contains(l, r)
+            | Filter::Overlaps(l, r) => {
+                l.hash_shape(state);
+                r.hash_shape(state);
+            },
+            Filter::In(val, list) => {
+                val.hash_shape(state);
+                list.len().hash(state);
+                list.iter().for_each(|item| item.hash_shape(state));
             },
-            Filter::In(_val, _list) => {
-                todo!()
+            Filter::Exists(table_name, alias, filter)
+            | Filter::NotExists(table_name, alias, filter) => {
+                table_name.hash(state);
+                alias.hash(state);
+                filter.hash_shape(state);
             },
+            Filter::Raw(raw) => raw.hash_shape(state),
         }
     }
 }
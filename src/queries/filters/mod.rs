@@ -2,3 +2,7 @@
 mod filters;
 pub use filters::*;
 pub mod filterable_types;
+mod query_string;
+pub use query_string::QueryStringError;
+mod dsl;
+pub use dsl::FilterParseError;
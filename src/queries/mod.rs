@@ -1,7 +1,15 @@
+pub(crate) mod aggregate;
+pub(crate) mod cache;
+pub(crate) mod explain;
 mod filters;
 pub(crate) mod query_builder;
+pub(crate) mod raw;
+pub use aggregate::*;
+pub use cache::{CacheableSql, CachedStatement, ShapeHasher, SqlSink};
+pub use explain::*;
 pub use filters::*;
 pub use query_builder::*;
+pub use raw::RawSql;
 
 #[cfg(test)]
 mod tests {
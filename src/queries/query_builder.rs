@@ -1,15 +1,18 @@
 use serde::{Deserialize, Serialize};
-use std::{fmt::Display, marker::PhantomData, sync::Arc};
+use std::{fmt::Display, hash::Hash, marker::PhantomData, sync::Arc};
 
 use crate::{
-    data_definition::table::{DatabaseTableDefinition, Identifier},
+    data_definition::table::{
+        DatabaseColumnType, DatabaseTableDefinition, Identifier, TableColumn,
+    },
+    data_manager::decode::NEGATIVE_INTERVAL,
     object_management::{
         delete::DeleteStatement, insert::InsertStatement, update::UpdateStatement,
     },
     BuildSql,
 };
 
-use super::Filter;
+use super::{CacheableSql, Filter, FilterComparisonParam, ShapeHasher, SqlSink};
 
 pub struct Query<T> {
     pub(crate) table: Arc<DatabaseTableDefinition>,
@@ -17,7 +20,11 @@ pub struct Query<T> {
 
     pub(crate) limit: Option<usize>,
     pub(crate) _t: PhantomData<T>,
-    pub(crate) order_by: Option<OrderBy>,
+    pub(crate) order_by: Vec<OrderBy>,
+    /// The fields to select, or `None` for every column (and every relation).
+    pub(crate) fields: Option<Vec<Identifier>>,
+    /// The tables of the relations to load, or `None` to load every relation.
+    pub(crate) relations: Option<Vec<Identifier>>,
 }
 
 pub trait Saveable {
@@ -25,6 +32,15 @@ pub trait Saveable {
     fn save(&self) -> Result<(), String>;
 }
 
+/// A subset of the `Source` type's fields, deserialized into a different struct.
+/// Derived with `#[derive(Projection)]` and `#[projection(SourceType)]`.
+pub trait Projection {
+    type Source;
+
+    /// The names of the `Source` fields included in this projection.
+    fn fields() -> Vec<Identifier>;
+}
+
 pub trait Deleteable {
     // TODO: rework this to actually Delete, not get_delete_statement
     fn get_delete_statement(&self) -> DeleteStatement<Self>
@@ -38,10 +54,67 @@ pub trait Updateable {
         Self: std::marker::Sized;
 }
 
+/// A single `ORDER BY` key. Built from the generated `*Filters` types, e.g. `f.created_at.desc()`.
+#[derive(Clone, Hash)]
 pub struct OrderBy {
     col_name: Identifier,
     direction: OrderDirection,
+    nulls: Option<NullsOrder>,
 }
+
+impl OrderBy {
+    pub fn new(
+        col_name: Identifier,
+        direction: OrderDirection,
+    ) -> Self {
+        Self {
+            col_name,
+            direction,
+            nulls: None,
+        }
+    }
+
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls = Some(NullsOrder::First);
+        self
+    }
+
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls = Some(NullsOrder::Last);
+        self
+    }
+
+    pub(crate) fn write_sql(
+        &self,
+        sink: &mut impl SqlSink,
+    ) {
+        sink.push(format!("{} {}", self.col_name, self.direction));
+        if let Some(nulls) = &self.nulls {
+            sink.push(format!(" {nulls}"));
+        }
+    }
+}
+
+// Lets a single key be passed anywhere a list of keys is expected.
+impl IntoIterator for OrderBy {
+    type Item = OrderBy;
+    type IntoIter = std::iter::Once<OrderBy>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl BuildSql for OrderBy {
+    fn build_sql(
+        &self,
+        builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    ) {
+        self.write_sql(builder);
+    }
+}
+
+#[derive(Clone, Hash)]
 pub enum OrderDirection {
     Ascending,
     Desending,
@@ -58,7 +131,36 @@ impl Display for OrderDirection {
     }
 }
 
+#[derive(Clone, Hash)]
+pub enum NullsOrder {
+    First,
+    Last,
+}
+impl Display for NullsOrder {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            NullsOrder::First => f.write_str("NULLS FIRST"),
+            NullsOrder::Last => f.write_str("NULLS LAST"),
+        }
+    }
+}
+
 impl<T> Query<T> {
+    pub fn new(table: Arc<DatabaseTableDefinition>) -> Self {
+        Self {
+            table,
+            filter: None,
+            limit: None,
+            _t: PhantomData,
+            order_by: Vec::new(),
+            fields: None,
+            relations: None,
+        }
+    }
+
     #[allow(unused)]
     pub fn limit(
         mut self,
@@ -80,17 +182,91 @@ impl<T> Query<T> {
         self
     }
 
+    /// Appends `order_by` to the existing sort keys. Earlier keys take precedence.
     pub fn order_by(
         mut self,
-        col_name: Identifier,
-        direction: OrderDirection,
+        order_by: impl IntoIterator<Item = OrderBy>,
     ) -> Self {
-        self.order_by = Some(OrderBy {
-            col_name,
-            direction,
-        });
+        self.order_by.extend(order_by);
+        self
+    }
+
+    /// Narrows the query down to only the given fields, and changes the result type to `P`.
+    pub fn select<P>(
+        self,
+        fields: Vec<Identifier>,
+    ) -> Query<P> {
+        Query {
+            table: self.table,
+            filter: self.filter,
+            limit: self.limit,
+            _t: PhantomData,
+            order_by: self.order_by,
+            fields: Some(fields),
+            relations: self.relations,
+        }
+    }
+
+    /// Loads the relation stored in `relation_table`. Once any relation is included (or
+    /// `exclude_relations()` is called), only included relations are joined.
+    pub fn include(
+        mut self,
+        relation_table: Identifier,
+    ) -> Self {
+        self.relations.get_or_insert_with(Vec::new).push(relation_table);
         self
     }
+
+    /// Loads no relations, other than those added with `include()`.
+    pub fn exclude_relations(mut self) -> Self {
+        self.relations = Some(Vec::new());
+        self
+    }
+
+    fn is_selected(
+        &self,
+        column: &TableColumn,
+    ) -> bool {
+        let relation_table = match &column.column_type {
+            DatabaseColumnType::OneToOne(name) => Some(name.trim_end_matches("_id")), // TODO: UNHACK THIS
+            DatabaseColumnType::OneToMany(name) | DatabaseColumnType::ManyToMany(name) => {
+                Some(name.as_str())
+            },
+            _ => None,
+        };
+        if let (Some(relation_table), Some(relations)) = (relation_table, &self.relations) {
+            if !relations.iter().any(|relation| relation.as_str() == relation_table) {
+                return false;
+            }
+        }
+        let Some(fields) = &self.fields else {
+            return true;
+        };
+        let field_name = match &column.column_type {
+            DatabaseColumnType::OneToOne(_) | DatabaseColumnType::Related(_) => {
+                column.column_name.trim_end_matches("_id") // TODO: UNHACK THIS
+            },
+            _ => &column.column_name,
+        };
+        fields.iter().any(|field| field.as_str() == field_name)
+    }
+
+    /// The key column relationships to (or from) `table_name` join on - see `DatabaseTableDefinition::relation_key()`.
+    /// Tables that aren't this table or one of its `child_tables` (e.g. in hand-built definitions) are assumed
+    /// to be keyed on `id`.
+    fn relation_key_of(
+        &self,
+        table_name: &Identifier,
+    ) -> Identifier {
+        let table = match self.table.table_name == *table_name {
+            true => Some(&*self.table),
+            false => self.table.related_table(table_name),
+        };
+        table
+            .and_then(|table| table.relation_key().ok())
+            .map(|key| key.column_name.clone())
+            .unwrap_or_else(|| Identifier::new_unchecked("id"))
+    }
 }
 
 pub trait Insertable
@@ -100,36 +276,129 @@ where
     type CreateRequest: Default + Serialize + for<'a> Deserialize<'a> + Into<Self>;
 
     fn get_insert_statement(&self) -> InsertStatement;
+
+    /// Fills in the keys the database numbered on insert (`#[primary_key(identity)]`), from the inserted row.
+    fn set_generated_keys(
+        &mut self,
+        _row: &sqlx::postgres::PgRow,
+    ) -> Result<(), crate::Error> {
+        Ok(())
+    }
 }
 
 impl<T> BuildSql for Query<T> {
     fn build_sql(
         &self,
         query_builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    ) {
+        self.write_sql(query_builder);
+    }
+}
+
+impl<T> CacheableSql for Query<T> {
+    fn hash_shape(
+        &self,
+        state: &mut ShapeHasher,
+    ) {
+        self.table.qualified_name().hash(state);
+        for column in self.table.columns.values() {
+            (&column.column_name, &column.column_type).hash(state);
+        }
+        self.table.qualified_primary_key().hash(state);
+        self.fields.hash(state);
+        self.relations.hash(state);
+        self.filter.is_some().hash(state);
+        if let Some(filter) = &self.filter {
+            filter.hash_shape(state);
+        }
+        self.order_by.hash(state);
+        self.limit.is_some().hash(state);
+    }
+
+    fn write_sql(
+        &self,
+        query_builder: &mut impl SqlSink,
     ) {
         let table_name = self.table.table_name.clone();
-        let mut group_by: Vec<String> = vec![format!("{}.id", &self.table.table_name)];
+        let mut group_by: Vec<String> =
+            self.table.qualified_primary_key().iter().map(ToString::to_string).collect();
+        if group_by.is_empty() {
+            group_by.push(format!("{}.id", &self.table.table_name)); // TODO: Tables without a key can't be grouped
+        }
         type E = crate::data_definition::table::DatabaseColumnType;
         // STEP ONE: get all table relationships
         let mut attrs = self
             .table
             .columns
             .values()
-            .filter_map(|col| {
+            .filter(|col| self.is_selected(col))
+            .map(|col| {
                 let col_name = col.column_name.to_string();
+                let column = format!("{table_name}.{col_name}");
                 match &col.column_type {
+                    // Sent as text, so that it isn't read as an `f64` on the way through JSON.
+                    E::Numeric => format!("{column}::text as {col_name}"),
+                    E::Array(element_type) if **element_type == E::Numeric => {
+                        format!("{column}::text[] as {col_name}")
+                    },
+                    // Selected in the shape that serde expects for a `Vec<u8>` - JSON would otherwise get a hex string.
+                    E::Bytea => format!(
+                        "CASE WHEN {column} IS NULL THEN NULL ELSE ARRAY(SELECT get_byte({column}, i) FROM generate_series(0, length({column}) - 1) i) END as {col_name}"
+                    ),
+                    // Selected in the shape that serde expects for a `std::time::Duration`. Months count as 30 days.
+                    // A `Duration` can't be negative, so negative intervals are selected as a message instead, which
+                    // fails to deserialize with an error naming the column.
+                    E::Interval => format!(
+                        "CASE WHEN {column} IS NULL THEN NULL WHEN {column} < INTERVAL '0' THEN to_json('{NEGATIVE_INTERVAL}'::text) ELSE json_build_object('secs', trunc(extract(epoch FROM {column}))::bigint, 'nanos', (mod(extract(epoch FROM {column}), 1) * 1000000000)::int) END as {col_name}"
+                    ),
                     E::Boolean
                     | E::Int
+                    | E::BigInt
                     | E::Float
                     | E::String
                     | E::Timestamp
                     | E::Uuid
-                    | E::Json => Some(format!("{table_name}.{col_name}")),
+                    | E::Json
+                    | E::Date
+                    | E::Time
+                    | E::TimestampTz
+                    | E::Array(_)
+                    | E::Enum(_) => column,
+                    // Relationships are returned as a single JSON column each, so that they can be
+                    // decoded without wrapping the whole row in `to_json`. `FILTER` drops the all-null
+                    // row that the LEFT JOIN produces when there are no children.
                     E::OneToMany(child_table) => {
-                        Some(format!("json_agg({child_table}) as {child_table}"))
+                        let child_key = self.relation_key_of(child_table);
+                        format!(
+                            "COALESCE(json_agg({child_table}) FILTER (WHERE {child_table}.{child_key} IS NOT NULL), '[]') as {col_name}"
+                        )
+                    },
+                    // Aggregated in a subquery through the join table, since the children aren't owned.
+                    E::ManyToMany(child_table) => {
+                        let join_table = DatabaseTableDefinition::join_table_name(
+                            &table_name,
+                            &col.column_name,
+                            child_table,
+                        );
+                        let (child_relation, join_relation) = (
+                            self.table.qualified_name_of(child_table),
+                            self.table.qualified_name_of(&join_table),
+                        );
+                        let (parent_key, child_key) =
+                            (self.relation_key_of(&table_name), self.relation_key_of(child_table));
+                        // The children are aliased, in case they're in the same table as the parent.
+                        format!(
+                            "COALESCE((SELECT json_agg({col_name}) FROM {child_relation} AS {col_name} INNER JOIN {join_relation} ON {join_table}.child_id = {col_name}.{child_key} WHERE {join_table}.parent_id = {table_name}.{parent_key}), '[]') as {col_name}"
+                        )
+                    },
+                    E::OneToOne(_) => {
+                        let name = col_name.trim_end_matches("_id"); // TODO: UNHACK THIS
+                        format!("to_json({name}) as {name}")
+                    },
+                    // Only the key is loaded, under the field name.
+                    E::Related(_) => {
+                        format!("{table_name}.{col_name} as {}", col_name.trim_end_matches("_id"))
                     },
-                    E::ManyToMany(_) => todo!(),
-                    E::OneToOne(_) => Some(col_name.trim_end_matches("_id").to_string()), // TODO: UNHACK THIS
                 }
             })
             .peekable();
@@ -141,59 +410,102 @@ impl<T> BuildSql for Query<T> {
             }
         }
         query_builder.push(" FROM ");
-        query_builder.push(&table_name);
+        query_builder.push(self.table.qualified_name());
         // TODO: Inner Joins -
         // STEP THREE: Need to impl BuildSql for INNER JOIN
-        for child_tbl in self.table.columns.values() {
+        for child_tbl in self.table.columns.values().filter(|col| self.is_selected(col)) {
             match &child_tbl.column_type {
                 crate::data_definition::table::DatabaseColumnType::OneToOne(name) => {
-                    let name = name.strip_suffix("_id").unwrap(); // TODO: UNHACK THIS
+                    // Older definitions name the target `{child}_id`.
+                    let name = Identifier::new_unchecked(name.strip_suffix("_id").unwrap_or(name));
+                    let child_key = self.relation_key_of(&name);
                     group_by.push(name.to_string());
                     query_builder
                         .push(" LEFT OUTER JOIN ")
-                        .push(name)
+                        .push(self.table.qualified_name_of(&name))
                         .push(" ON ")
-                        .push(name)
-                        .push(".id = ")
-                        .push(name)
-                        .push("_id ");
+                        .push(format!(
+                            "{name}.{child_key} = {table_name}.{}",
+                            child_tbl.column_name
+                        ));
                 },
-                crate::data_definition::table::DatabaseColumnType::OneToMany(name)
-                | crate::data_definition::table::DatabaseColumnType::ManyToMany(name) => {
+                crate::data_definition::table::DatabaseColumnType::OneToMany(name) => {
+                    // TODO: The child's reference to the parent is still assumed to be `parent_id`.
+                    let parent_key = self.relation_key_of(&table_name);
                     query_builder
                         .push(" LEFT OUTER JOIN ")
-                        .push(name)
+                        .push(self.table.qualified_name_of(name))
                         .push(" ON ")
                         .push(name)
-                        .push(format!(".parent_id = {table_name}.id")); // TODO: This requires `parent_id` and `id`
+                        .push(format!(".parent_id = {table_name}.{parent_key}"));
                 },
                 _ => {},
             };
         }
         if let Some(filter) = &self.filter {
             query_builder.push(" WHERE ");
-            filter.build_sql(query_builder);
+            filter
+                .clone()
+                .qualify_tables(&|table_name| self.table.qualified_name_of(table_name))
+                .write_sql(query_builder);
         }
         // TODO: Unhack (part of the "everything built on id" problem)
         query_builder.push(" GROUP BY (").push(group_by.join(", ")).push(")");
-        if let Some(OrderBy {
-            col_name,
-            direction,
-        }) = &self.order_by
-        {
-            query_builder.push(format!(" ORDER BY {col_name} {direction} "));
+        if !self.order_by.is_empty() {
+            // Tie-break on the primary key, so that paging through results is deterministic.
+            let primary_keys = self
+                .table
+                .qualified_primary_key()
+                .into_iter()
+                .filter(|pk| !self.order_by.iter().any(|order_by| &order_by.col_name == pk))
+                .map(|pk| OrderBy::new(pk, OrderDirection::Ascending))
+                .collect::<Vec<_>>();
+
+            query_builder.push(" ORDER BY ");
+            let mut order_by = self.order_by.iter().chain(primary_keys.iter()).peekable();
+            while let Some(key) = order_by.next() {
+                key.write_sql(query_builder);
+                if order_by.peek().is_some() {
+                    query_builder.push(", ");
+                }
+            }
         }
-        if let Some(limit) = &self.limit {
-            query_builder.push(format!(" LIMIT {limit} "));
+        if let Some(limit) = self.limit {
+            query_builder
+                .push(" LIMIT ")
+                .push_bind(FilterComparisonParam::Integer(limit as i64));
         }
 
         // STEP FOUR: Probably will need to do more with build_query_as. will find out
     }
+
+    fn collect_binds(
+        &self,
+        binds: &mut Vec<FilterComparisonParam>,
+    ) {
+        if let Some(filter) = &self.filter {
+            filter.collect_binds(binds);
+        }
+        if let Some(limit) = self.limit {
+            binds.push(FilterComparisonParam::Integer(limit as i64));
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::data_definition::table::{DatabaseTableDefinition, TableColumn};
+    use std::sync::Arc;
+
+    use crate::{
+        data_definition::table::{
+            DatabaseColumnType, DatabaseTableDefinition, Identifier, TableColumn, TableKey,
+        },
+        queries::{
+            filterable_types::{FilterPartialEq, FilterableType},
+            Query,
+        },
+        BuildSql,
+    };
 
     fn get_table_def() -> DatabaseTableDefinition {
         type T = TableColumn;
@@ -217,4 +529,207 @@ mod tests {
 
         todo!()
     }
+
+    #[test]
+    fn order_by_renders_all_keys_with_primary_key_tiebreak() {
+        let table = DatabaseTableDefinition::new("item")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::string("name").unwrap())
+            .column(TableColumn::timestamp("created_at").unwrap());
+        let created_at = FilterableType::<chrono::NaiveDateTime>::new(Identifier::new_unchecked(
+            "item.created_at",
+        ));
+        let name = FilterableType::<String>::new(Identifier::new_unchecked("item.name"));
+
+        let query = Query::<()>::new(Arc::new(table))
+            .order_by([created_at.desc().nulls_last(), name.asc()]);
+        let mut builder = sqlx::QueryBuilder::new("");
+        query.build_sql(&mut builder);
+
+        assert!(builder
+            .sql()
+            .ends_with(" ORDER BY item.created_at DESC NULLS LAST, item.name ASC, item.id ASC"));
+    }
+
+    #[test]
+    fn select_only_includes_projected_columns_and_relations() {
+        let table = DatabaseTableDefinition::new("orders")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::string("name").unwrap())
+            .column(TableColumn::string("notes").unwrap())
+            .column(
+                TableColumn::new(
+                    "customer_id",
+                    DatabaseColumnType::OneToOne(Identifier::new_unchecked("customer_id")),
+                    Vec::new(),
+                )
+                .unwrap(),
+            )
+            .column(
+                TableColumn::new(
+                    "line_items",
+                    DatabaseColumnType::OneToMany(Identifier::new_unchecked("line_item")),
+                    Vec::new(),
+                )
+                .unwrap(),
+            );
+        let query = Query::<()>::new(Arc::new(table))
+            .select::<()>(vec![Identifier::new_unchecked("id"), Identifier::new_unchecked("name")]);
+        let mut builder = sqlx::QueryBuilder::new("");
+        query.build_sql(&mut builder);
+
+        assert_eq!(builder.sql(), "SELECT orders.id, orders.name FROM orders GROUP BY (orders.id)");
+    }
+
+    #[test]
+    fn relations_are_selected_as_json_columns_and_can_be_excluded() {
+        let table = DatabaseTableDefinition::new("orders")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(
+                TableColumn::new(
+                    "customer_id",
+                    DatabaseColumnType::OneToOne(Identifier::new_unchecked("customer_id")),
+                    Vec::new(),
+                )
+                .unwrap(),
+            )
+            .column(
+                TableColumn::new(
+                    "line_items",
+                    DatabaseColumnType::OneToMany(Identifier::new_unchecked("line_item")),
+                    Vec::new(),
+                )
+                .unwrap(),
+            );
+        let table = Arc::new(table);
+        let mut builder = sqlx::QueryBuilder::new("");
+        Query::<()>::new(table.clone()).build_sql(&mut builder);
+
+        assert!(builder.sql().starts_with(
+            "SELECT to_json(customer) as customer, orders.id, \
+             COALESCE(json_agg(line_item) FILTER (WHERE line_item.id IS NOT NULL), '[]') as line_items FROM orders"
+        ));
+
+        let mut builder = sqlx::QueryBuilder::new("");
+        Query::<()>::new(table.clone()).exclude_relations().build_sql(&mut builder);
+        assert_eq!(builder.sql(), "SELECT orders.id FROM orders GROUP BY (orders.id)");
+
+        let mut builder = sqlx::QueryBuilder::new("");
+        Query::<()>::new(table)
+            .include(Identifier::new_unchecked("line_item"))
+            .build_sql(&mut builder);
+        assert_eq!(
+            builder.sql(),
+            "SELECT orders.id, COALESCE(json_agg(line_item) FILTER (WHERE line_item.id IS NOT NULL), '[]') as line_items \
+             FROM orders LEFT OUTER JOIN line_item ON line_item.parent_id = orders.id GROUP BY (orders.id)"
+        );
+    }
+
+    #[test]
+    fn schemas_qualify_tables_but_not_columns() {
+        let table = DatabaseTableDefinition::new("invoice")
+            .unwrap()
+            .schema("billing")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::int("total").unwrap().non_null());
+        let total = FilterableType::<i64>::new(Identifier::new_unchecked("invoice.total"));
+        let mut builder = sqlx::QueryBuilder::new("");
+        Query::<()>::new(Arc::new(table)).filter(total.gt(100)).build_sql(&mut builder);
+
+        assert_eq!(
+            builder.sql(),
+            "SELECT invoice.id, invoice.total FROM billing.invoice WHERE invoice.total > $1 GROUP BY (invoice.id)"
+        );
+        assert!(DatabaseTableDefinition::new("invoice")
+            .unwrap()
+            .schema("billing.archive")
+            .is_err());
+        assert!(DatabaseTableDefinition::new("billing.invoice").is_err());
+    }
+
+    #[test]
+    fn negative_intervals_are_rejected_rather_than_misread() {
+        let table = DatabaseTableDefinition::new("subscription")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(TableColumn::interval("grace_period").unwrap());
+        let mut builder = sqlx::QueryBuilder::new("");
+        Query::<()>::new(Arc::new(table)).build_sql(&mut builder);
+
+        assert!(builder.sql().contains(
+            "WHEN subscription.grace_period < INTERVAL '0' THEN to_json('negative intervals can not be read as a Duration'::text) ELSE json_build_object("
+        ));
+        let duration: Result<std::time::Duration, _> =
+            serde_json::from_value(serde_json::json!(super::NEGATIVE_INTERVAL));
+        assert!(duration.unwrap_err().to_string().contains(super::NEGATIVE_INTERVAL));
+    }
+
+    #[test]
+    fn many_to_many_relations_are_selected_through_the_join_table() {
+        let table = DatabaseTableDefinition::new("article")
+            .unwrap()
+            .column(TableColumn::uuid("id").unwrap().non_null().pk())
+            .column(
+                TableColumn::new(
+                    "tags",
+                    DatabaseColumnType::ManyToMany(Identifier::new_unchecked("tag")),
+                    Vec::new(),
+                )
+                .unwrap(),
+            );
+        let mut builder = sqlx::QueryBuilder::new("");
+        Query::<()>::new(Arc::new(table)).build_sql(&mut builder);
+
+        assert_eq!(
+            builder.sql(),
+            "SELECT article.id, COALESCE((SELECT json_agg(tags) FROM tag AS tags \
+             INNER JOIN article_tags_to_tag ON article_tags_to_tag.child_id = tags.id \
+             WHERE article_tags_to_tag.parent_id = article.id), '[]') as tags FROM article GROUP BY (article.id)"
+        );
+    }
+    #[test]
+    fn relations_join_on_the_related_tables_keys() {
+        let keyed_on = |table_name: &str, key: &str| {
+            DatabaseTableDefinition::new(table_name)
+                .unwrap()
+                .column(TableColumn::string(key).unwrap().non_null().pk())
+        };
+        let mut table = keyed_on("article", "slug")
+            .column(
+                TableColumn::new(
+                    "author_id",
+                    DatabaseColumnType::OneToOne(Identifier::new_unchecked("author")),
+                    Vec::new(),
+                )
+                .unwrap(),
+            )
+            .column(
+                TableColumn::new(
+                    "tags",
+                    DatabaseColumnType::ManyToMany(Identifier::new_unchecked("tag")),
+                    Vec::new(),
+                )
+                .unwrap(),
+            );
+        table
+            .child_tables
+            .insert(TableKey::of::<u8>(), Box::new(keyed_on("author", "handle")));
+        table
+            .child_tables
+            .insert(TableKey::of::<u16>(), Box::new(keyed_on("tag", "name")));
+        let mut builder = sqlx::QueryBuilder::new("");
+        Query::<()>::new(Arc::new(table)).build_sql(&mut builder);
+
+        assert_eq!(
+            builder.sql(),
+            "SELECT to_json(author) as author, article.slug, COALESCE((SELECT json_agg(tags) FROM tag AS tags \
+             INNER JOIN article_tags_to_tag ON article_tags_to_tag.child_id = tags.name \
+             WHERE article_tags_to_tag.parent_id = article.slug), '[]') as tags \
+             FROM article LEFT OUTER JOIN author ON author.handle = article.author_id GROUP BY (article.slug, author)"
+        );
+    }
 }
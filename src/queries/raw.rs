@@ -0,0 +1,174 @@
+use std::hash::Hash;
+
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::BuildSql;
+
+use super::{CacheableSql, FilterComparisonParam, ShapeHasher, SqlSink};
+
+/// A hand-written SQL statement or fragment, with its parameters bound separately.
+///
+/// Placeholders are numbered locally from `$1`, and renumbered as the fragment is pushed into a
+/// larger query - so a `Filter::Raw` can be combined with other filters without counting their binds.
+/// Placeholders inside single-quoted string literals are left alone.
+#[derive(Clone, Debug)]
+pub struct RawSql {
+    sql: String,
+    binds: Vec<FilterComparisonParam>,
+}
+
+impl RawSql {
+    pub fn new(
+        sql: impl Into<String>,
+        binds: Vec<FilterComparisonParam>,
+    ) -> Result<Self, String> {
+        let sql = sql.into();
+        validate_placeholders(&sql, binds.len())?;
+        Ok(Self {
+            sql,
+            binds,
+        })
+    }
+}
+
+impl BuildSql for RawSql {
+    fn build_sql(
+        &self,
+        builder: &mut QueryBuilder<'_, Postgres>,
+    ) {
+        self.write_sql(builder);
+    }
+}
+
+impl CacheableSql for RawSql {
+    fn hash_shape(
+        &self,
+        state: &mut ShapeHasher,
+    ) {
+        self.sql.hash(state);
+        self.binds.iter().for_each(|bind| bind.hash_shape(state));
+    }
+
+    fn write_sql(
+        &self,
+        sink: &mut impl SqlSink,
+    ) {
+        build_raw_sql(&self.sql, &self.binds, sink);
+    }
+
+    fn collect_binds(
+        &self,
+        binds: &mut Vec<FilterComparisonParam>,
+    ) {
+        for token in tokenize(&self.sql) {
+            if let RawToken::Placeholder(index) = token {
+                if let Some(bind) = self.binds.get(index.wrapping_sub(1)) {
+                    bind.collect_binds(binds);
+                }
+            }
+        }
+    }
+}
+
+/// Splits `sql` into literal text and placeholder numbers (`$1` -> `1`).
+fn tokenize(sql: &str) -> Vec<RawToken<'_>> {
+    let mut tokens = Vec::new();
+    let mut in_string = false;
+    let mut text_start = 0;
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' => in_string = !in_string,
+            '$' if !in_string => {
+                let mut end = i + 1;
+                while let Some((j, d)) = chars.peek() {
+                    if !d.is_ascii_digit() {
+                        break;
+                    }
+                    end = j + 1;
+                    chars.next();
+                }
+                if end > i + 1 {
+                    tokens.push(RawToken::Text(&sql[text_start..i]));
+                    // Only digits, so this can only fail on overflow - which is out of range anyway.
+                    tokens
+                        .push(RawToken::Placeholder(sql[i + 1..end].parse().unwrap_or(usize::MAX)));
+                    text_start = end;
+                }
+            },
+            _ => {},
+        }
+    }
+    tokens.push(RawToken::Text(&sql[text_start..]));
+    tokens
+}
+
+enum RawToken<'a> {
+    Text(&'a str),
+    Placeholder(usize),
+}
+
+fn validate_placeholders(
+    sql: &str,
+    bind_count: usize,
+) -> Result<(), String> {
+    for token in tokenize(sql) {
+        if let RawToken::Placeholder(index) = token {
+            if index == 0 || index > bind_count {
+                return Err(format!(
+                    "Placeholder ${index} in raw SQL is out of range - {bind_count} values were bound."
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn build_raw_sql(
+    sql: &str,
+    binds: &[FilterComparisonParam],
+    builder: &mut impl SqlSink,
+) {
+    for token in tokenize(sql) {
+        match token {
+            RawToken::Text(text) => {
+                builder.push(text);
+            },
+            RawToken::Placeholder(index) => binds
+                .get(index.wrapping_sub(1))
+                .unwrap_or_else(|| panic!("Placeholder ${index} in raw SQL is out of range - this should have been caught on create."))
+                .write_sql(builder),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data_definition::table::Identifier,
+        queries::{
+            filterable_types::{FilterEq, FilterableType},
+            Filter, FilterComparisonParam, RawSql,
+        },
+        BuildSql,
+    };
+
+    #[test]
+    fn raw_filters_renumber_placeholders() {
+        let name = FilterableType::<String>::new(Identifier::new_unchecked("item.name"));
+        let filter = name.eq("widget")
+            & Filter::raw(
+                "rank() OVER (ORDER BY item.price) <= $1 AND item.note != '$1'",
+                vec![FilterComparisonParam::Integer(3)],
+            )
+            .unwrap();
+        let mut builder = sqlx::QueryBuilder::new("");
+        filter.build_sql(&mut builder);
+
+        assert_eq!(
+            builder.sql(),
+            "(item.name = $1 AND (rank() OVER (ORDER BY item.price) <= $2 AND item.note != '$1'))"
+        );
+        assert!(RawSql::new("SELECT $2", vec![FilterComparisonParam::Integer(1)]).is_err());
+    }
+}